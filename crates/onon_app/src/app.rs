@@ -1,11 +1,17 @@
-use onon_render::{RenderObject, Renderer, mesh::Vertex};
+use onon_render::{
+  Camera, ObjectId, PickQuery, PickResult, RenderObject, Renderer, Scene, mesh::Vertex,
+};
 use std::sync::Arc;
 use winit::window::Window;
 
 pub struct WgpuApp {
   pub window: Arc<Window>,
   renderer: Renderer<'static>,
-  objects: Vec<RenderObject>,
+  scene: Scene,
+  camera: Camera,
+  cursor_position: winit::dpi::PhysicalPosition<f64>,
+  pending_pick: Option<PickQuery>,
+  selected: Option<ObjectId>,
 }
 
 const VERTICES: &[onon_render::mesh::Vertex] = &[
@@ -50,18 +56,33 @@ impl WgpuApp {
     );
 
     let path = std::path::PathBuf::from("C:/dev/GameEngines/onon_gfx/resources/happy-tree-cartoon.png");
-    let render_objects = vec![RenderObject::new(
+    // Files can't be read on the web, the texture is embedded and cached under its path instead
+    #[cfg(target_arch = "wasm32")]
+    let renderer = {
+      let mut renderer = renderer;
+      let bytes = include_bytes!("../../../resources/happy-tree-cartoon.png");
+      if let Err(e) = renderer.load_texture_from_memory(&path, bytes, None) {
+        log::error!("Failed to load the embedded texture: {:?}", e);
+      }
+      renderer
+    };
+    let mut scene = Scene::new();
+    scene.insert(RenderObject::new(
       mesh,
-      0,
+      renderer.default_material(),
       None,
       Some(path),
       None,
-    )];
+    ));
 
     Self {
       window: window.clone(),
       renderer: renderer,
-      objects: render_objects,
+      scene,
+      camera: Camera::default(),
+      cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+      pending_pick: None,
+      selected: None,
     }
   }
 
@@ -76,13 +97,17 @@ impl WgpuApp {
           .create_view(&wgpu::TextureViewDescriptor::default());
         {
           let mut render_pass = frame_ctx.create_render_pass(&view);
-          let res = self.renderer.render_solids(&mut render_pass, &self.objects);
+          let res = self.renderer.render_solids(&mut render_pass, &mut self.scene, &self.camera);
           match res {
             Ok(()) => {}
             Err(e) => log::error!("{}", e),
           }
         }
+        if let Err(e) = self.renderer.render_picking(&mut frame_ctx, &mut self.scene, &self.camera) {
+          log::error!("{}", e);
+        }
         self.renderer.finish_rendering(frame_ctx);
+        self.poll_pick();
       }
       Ok(None) => {}
       Err(wgpu::SurfaceError::Lost) => log::error!("Surface is lost"),
@@ -97,10 +122,43 @@ impl WgpuApp {
 
   pub fn mouse_click(
     &mut self,
-    _state: winit::event::ElementState,
-    _button: winit::event::MouseButton,
+    state: winit::event::ElementState,
+    button: winit::event::MouseButton,
   ) -> bool {
-    false
+    if state != winit::event::ElementState::Pressed || button != winit::event::MouseButton::Left {
+      return false;
+    }
+
+    // Plain meshes are resolved right away, the ID buffer handles the rest a frame later
+    let cursor = glam::vec2(self.cursor_position.x as f32, self.cursor_position.y as f32);
+    let point = self
+      .camera
+      .screen_to_world(cursor, self.renderer.render_state.get_size());
+    if let Some((id, hit)) = self.scene.hit_test(point, &self.camera) {
+      self.selected = Some(id);
+      self.pending_pick = None;
+      log::info!("Selected {:?} at {:?}", id, hit.tex_coords);
+      return true;
+    }
+
+    let x = cursor.x.max(0.0) as u32;
+    let y = cursor.y.max(0.0) as u32;
+    self.pending_pick = Some(self.renderer.pick(x, y));
+    true
+  }
+
+  fn poll_pick(&mut self) {
+    let Some(query) = self.pending_pick else {
+      return;
+    };
+
+    match self.renderer.pick_result(query) {
+      PickResult::Pending => return,
+      PickResult::Hit(id) => self.selected = Some(id),
+      PickResult::Miss => self.selected = None,
+    }
+    self.pending_pick = None;
+    log::info!("Selected {:?}", self.selected);
   }
 
   pub fn mouse_wheel(
@@ -111,7 +169,8 @@ impl WgpuApp {
     true
   }
 
-  pub fn cursor_move(&mut self, _position: winit::dpi::PhysicalPosition<f64>) -> bool {
+  pub fn cursor_move(&mut self, position: winit::dpi::PhysicalPosition<f64>) -> bool {
+    self.cursor_position = position;
     false
   }
 
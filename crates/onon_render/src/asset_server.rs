@@ -0,0 +1,498 @@
+use crate::{
+  Renderer,
+  material_manager::MaterialId,
+  mesh::Mesh2D,
+  render_resource::{Shader, TextureArrayId},
+  shader_pass::PipelineState,
+};
+use anyhow::{Context, Result};
+use std::{
+  collections::HashMap,
+  marker::PhantomData,
+  path::{Path, PathBuf},
+};
+
+/// Typed reference to an asset of the `AssetServer`, cheap to copy and compare
+pub struct Handle<T> {
+  id: u32,
+  marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+  fn new(id: u32) -> Self {
+    Self {
+      id,
+      marker: PhantomData,
+    }
+  }
+
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+}
+
+impl<T> Clone for Handle<T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+  fn eq(&self, other: &Self) -> bool {
+    self.id == other.id
+  }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+  fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    self.id.hash(state);
+  }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Handle<{}>({})", std::any::type_name::<T>(), self.id)
+  }
+}
+
+/// Outcome of the latest load of an asset, reloads included
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadState {
+  Loading,
+  Loaded,
+  Failed,
+}
+
+/// A texture file and where it lives once loaded
+#[derive(Clone)]
+pub struct TextureAsset {
+  /// Texture array it is loaded into, `None` being the default one
+  pub texture_array: Option<TextureArrayId>,
+  pub slot: Option<u32>,
+  /// See `RenderObject::uv_scale`
+  pub uv_scale: glam::Vec2,
+}
+
+/// A material built from a WGSL file, rebuilt in place when the file changes
+pub struct MaterialAsset {
+  /// `None` until the shader compiles
+  pub material: Option<MaterialId>,
+  pub shader: Handle<Shader>,
+  pub state: PipelineState,
+}
+
+struct AssetEntry<T> {
+  path: Option<PathBuf>,
+  state: LoadState,
+  value: Option<T>,
+}
+
+/// Assets of one type, found by handle or by the path they were loaded from
+pub struct Assets<T> {
+  entries: Vec<AssetEntry<T>>,
+  by_path: HashMap<PathBuf, Handle<T>>,
+}
+
+impl<T> Assets<T> {
+  fn new() -> Self {
+    Self {
+      entries: Vec::new(),
+      by_path: HashMap::new(),
+    }
+  }
+
+  /// Assets with a path are deduplicated by it
+  fn insert(&mut self, path: Option<PathBuf>, state: LoadState, value: Option<T>) -> Handle<T> {
+    let handle = Handle::new(self.entries.len() as u32);
+    if let Some(path) = &path {
+      self.by_path.insert(path.clone(), handle);
+    }
+    self.entries.push(AssetEntry { path, state, value });
+    handle
+  }
+
+  fn entry_mut(&mut self, handle: Handle<T>) -> Option<&mut AssetEntry<T>> {
+    self.entries.get_mut(handle.id as usize)
+  }
+
+  fn handles(&self) -> impl Iterator<Item = Handle<T>> + use<T> {
+    (0..self.entries.len() as u32).map(Handle::new)
+  }
+
+  /// Latest usable version of the asset, it may be older than a failed reload
+  pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+    self.entries.get(handle.id as usize)?.value.as_ref()
+  }
+
+  pub fn state(&self, handle: Handle<T>) -> Option<LoadState> {
+    self.entries.get(handle.id as usize).map(|entry| entry.state)
+  }
+
+  pub fn path(&self, handle: Handle<T>) -> Option<&Path> {
+    self.entries.get(handle.id as usize)?.path.as_deref()
+  }
+
+  pub fn handle(&self, path: &Path) -> Option<Handle<T>> {
+    self.by_path.get(path).copied()
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+/// Loads textures, meshes, shaders and materials once per path and hands out typed
+/// handles to them. With `watch_for_changes`, edited texture and WGSL files are
+/// reloaded in place by `update`, objects keeping their slots and material ids.
+pub struct AssetServer {
+  textures: Assets<TextureAsset>,
+  meshes: Assets<Mesh2D>,
+  shaders: Assets<Shader>,
+  materials: Assets<MaterialAsset>,
+  /// Textures the renderer hasn't finished loading
+  texture_requests: Vec<Handle<TextureAsset>>,
+  #[cfg(not(target_arch = "wasm32"))]
+  watcher: Option<FileWatcher>,
+}
+
+impl AssetServer {
+  pub fn new() -> Self {
+    Self {
+      textures: Assets::new(),
+      meshes: Assets::new(),
+      shaders: Assets::new(),
+      materials: Assets::new(),
+      texture_requests: Vec::new(),
+      #[cfg(not(target_arch = "wasm32"))]
+      watcher: None,
+    }
+  }
+
+  /// Starts watching the files of loaded and future assets, native only
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn watch_for_changes(&mut self) -> Result<()> {
+    let mut watcher = FileWatcher::new()?;
+    let texture_paths = self.textures.entries.iter().map(|entry| &entry.path);
+    let shader_paths = self.shaders.entries.iter().map(|entry| &entry.path);
+    for path in texture_paths.chain(shader_paths).flatten() {
+      watcher.watch(path);
+    }
+    self.watcher = Some(watcher);
+    Ok(())
+  }
+
+  #[cfg(target_arch = "wasm32")]
+  pub fn watch_for_changes(&mut self) -> Result<()> {
+    Err(anyhow::anyhow!("Files can't be watched on the web"))
+  }
+
+  /// Loads the texture at `path` in the background into the texture array of `texture_array`,
+  /// `None` being the default one. A path already loaded keeps its first array.
+  pub fn load_texture<P: AsRef<Path>>(
+    &mut self,
+    path: P,
+    texture_array: Option<TextureArrayId>,
+  ) -> Handle<TextureAsset> {
+    let path = path.as_ref();
+    if let Some(handle) = self.textures.handle(path) {
+      return handle;
+    }
+
+    let texture = TextureAsset {
+      texture_array,
+      slot: None,
+      uv_scale: glam::Vec2::ONE,
+    };
+    let handle = self.textures.insert(Some(path.to_path_buf()), LoadState::Loading, Some(texture));
+    self.texture_requests.push(handle);
+    self.watch(path);
+    handle
+  }
+
+  pub fn add_mesh(&mut self, mesh: Mesh2D) -> Handle<Mesh2D> {
+    self.meshes.insert(None, LoadState::Loaded, Some(mesh))
+  }
+
+  /// Reads and parses the WGSL file at `path`
+  pub fn load_shader<P: AsRef<Path>>(&mut self, path: P) -> Handle<Shader> {
+    let path = path.as_ref();
+    if let Some(handle) = self.shaders.handle(path) {
+      return handle;
+    }
+
+    let (state, shader) = match read_shader(path) {
+      Ok(shader) => (LoadState::Loaded, Some(shader)),
+      Err(error) => {
+        log::error!("Failed to load shader: {:?}", error);
+        (LoadState::Failed, None)
+      }
+    };
+    self.watch(path);
+    self.shaders.insert(Some(path.to_path_buf()), state, shader)
+  }
+
+  /// Creates a material from the WGSL file at `path`, the shader being loaded once.
+  /// Every call makes a new material, so they can have their own parameters.
+  pub fn load_material<P: AsRef<Path>>(
+    &mut self,
+    renderer: &mut Renderer,
+    path: P,
+    state: PipelineState,
+  ) -> Handle<MaterialAsset> {
+    let shader = self.load_shader(path);
+    let material = self.shaders.get(shader).and_then(|shader| {
+      renderer
+        .create_material(shader.source(), state)
+        .inspect_err(|error| log::error!("Failed to create material: {:?}", error))
+        .ok()
+    });
+
+    let load_state = if material.is_some() { LoadState::Loaded } else { LoadState::Failed };
+    let asset = MaterialAsset {
+      material,
+      shader,
+      state,
+    };
+    self.materials.insert(None, load_state, Some(asset))
+  }
+
+  pub fn textures(&self) -> &Assets<TextureAsset> {
+    &self.textures
+  }
+
+  pub fn meshes(&self) -> &Assets<Mesh2D> {
+    &self.meshes
+  }
+
+  pub fn shaders(&self) -> &Assets<Shader> {
+    &self.shaders
+  }
+
+  pub fn materials(&self) -> &Assets<MaterialAsset> {
+    &self.materials
+  }
+
+  /// Follows the textures the renderer is loading and reloads changed files, once per frame
+  pub fn update(&mut self, renderer: &mut Renderer) {
+    let mut index = 0;
+    while index < self.texture_requests.len() {
+      if self.update_texture(renderer, self.texture_requests[index]) {
+        self.texture_requests.swap_remove(index);
+      } else {
+        index += 1;
+      }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    for path in self.watcher.as_mut().map(FileWatcher::changed).unwrap_or_default() {
+      if let Some(handle) = self.textures.handle(&path) {
+        self.reload_texture(renderer, handle);
+      }
+      if let Some(handle) = self.shaders.handle(&path) {
+        self.reload_shader(renderer, handle);
+      }
+    }
+  }
+
+  /// Returns true once the texture is loaded or failed to
+  fn update_texture(&mut self, renderer: &mut Renderer, handle: Handle<TextureAsset>) -> bool {
+    let Some(entry) = self.textures.entry_mut(handle) else {
+      return true;
+    };
+    let (Some(path), Some(texture)) = (&entry.path, &mut entry.value) else {
+      return true;
+    };
+
+    match renderer.texture_load_state(path, texture.texture_array) {
+      // Nothing would ever load into a missing array
+      None if renderer.texture_array(texture.texture_array).is_none() => {
+        log::error!("No texture array {:?} to load {:?} into", texture.texture_array, path);
+        entry.state = LoadState::Failed;
+        true
+      }
+      None => {
+        renderer.preload_texture(path, texture.texture_array);
+        false
+      }
+      Some(LoadState::Loading) => false,
+      Some(LoadState::Failed) => {
+        entry.state = LoadState::Failed;
+        true
+      }
+      Some(LoadState::Loaded) => {
+        let Some(texture_array) = renderer.texture_array(texture.texture_array) else {
+          return false;
+        };
+        let slot = texture_array.by_path(path).copied();
+        texture.slot = slot;
+        texture.uv_scale = slot.map_or(glam::Vec2::ONE, |slot| texture_array.region(slot).uv_scale);
+        entry.state = LoadState::Loaded;
+        true
+      }
+    }
+  }
+
+  fn reload_texture(&mut self, renderer: &mut Renderer, handle: Handle<TextureAsset>) {
+    let Some(entry) = self.textures.entry_mut(handle) else {
+      return;
+    };
+    let (Some(path), Some(texture)) = (&entry.path, &entry.value) else {
+      return;
+    };
+
+    match renderer.reload_texture(path, texture.texture_array) {
+      Ok(()) => log::info!("Reloading texture {:?}", path),
+      Err(error) => {
+        log::error!("Failed to reload texture: {:?}", error);
+        entry.state = LoadState::Failed;
+        return;
+      }
+    }
+    // Read again for the new size, or waited for when it wasn't loaded
+    entry.state = LoadState::Loading;
+    if !self.texture_requests.contains(&handle) {
+      self.texture_requests.push(handle);
+    }
+  }
+
+  /// Replaces the shader and rebuilds its materials, keeping the old ones when it doesn't compile
+  fn reload_shader(&mut self, renderer: &mut Renderer, handle: Handle<Shader>) {
+    let Some(entry) = self.shaders.entry_mut(handle) else {
+      return;
+    };
+    let Some(path) = &entry.path else {
+      return;
+    };
+
+    match read_shader(path) {
+      Ok(shader) => {
+        log::info!("Reloaded shader {:?}", path);
+        entry.state = LoadState::Loaded;
+        entry.value = Some(shader);
+      }
+      Err(error) => {
+        log::error!("Failed to reload shader: {:?}", error);
+        entry.state = LoadState::Failed;
+        return;
+      }
+    }
+    let Some(shader) = self.shaders.get(handle) else {
+      return;
+    };
+
+    for material_handle in self.materials.handles() {
+      let Some(entry) = self.materials.entry_mut(material_handle) else {
+        continue;
+      };
+      let Some(asset) = entry.value.as_mut().filter(|asset| asset.shader == handle) else {
+        continue;
+      };
+
+      let result = match asset.material {
+        Some(id) => renderer.reload_material(id, shader.source(), asset.state),
+        None => renderer
+          .create_material(shader.source(), asset.state)
+          .map(|id| asset.material = Some(id)),
+      };
+      entry.state = match result {
+        Ok(()) => LoadState::Loaded,
+        Err(error) => {
+          log::error!("Failed to rebuild material {:?}: {:?}", material_handle, error);
+          LoadState::Failed
+        }
+      };
+    }
+  }
+
+  fn watch(&mut self, _path: &Path) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(watcher) = &mut self.watcher {
+      watcher.watch(_path);
+    }
+  }
+}
+
+impl Default for AssetServer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn read_shader(path: &Path) -> Result<Shader> {
+  let source = std::fs::read_to_string(path).context(format!("{:?}", path))?;
+  Shader::from_wgsl(&source).context(format!("{:?}", path))
+}
+
+/// Watches the directories of asset files, as editors often save by replacing the file
+#[cfg(not(target_arch = "wasm32"))]
+struct FileWatcher {
+  watcher: notify::RecommendedWatcher,
+  events: std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+  directories: std::collections::HashSet<PathBuf>,
+  /// Canonical paths of the watched files to the paths their assets were loaded with
+  files: HashMap<PathBuf, PathBuf>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileWatcher {
+  fn new() -> Result<Self> {
+    let (sender, events) = std::sync::mpsc::channel();
+    Ok(Self {
+      watcher: notify::recommended_watcher(sender)?,
+      events,
+      directories: std::collections::HashSet::new(),
+      files: HashMap::new(),
+    })
+  }
+
+  fn watch(&mut self, path: &Path) {
+    use notify::Watcher;
+
+    let Ok(canonical) = path.canonicalize() else {
+      log::warn!("Can't watch {:?}, it doesn't exist", path);
+      return;
+    };
+    if let Some(directory) = canonical.parent()
+      && self.directories.insert(directory.to_path_buf())
+      && let Err(error) = self.watcher.watch(directory, notify::RecursiveMode::NonRecursive)
+    {
+      log::error!("Failed to watch {:?}: {:?}", directory, error);
+    }
+    self.files.insert(canonical, path.to_path_buf());
+  }
+
+  /// Asset paths of the files written since the last call
+  fn changed(&mut self) -> Vec<PathBuf> {
+    let mut changed = Vec::new();
+    for event in self.events.try_iter() {
+      let event = match event {
+        Ok(event) => event,
+        Err(error) => {
+          log::error!("File watcher error: {:?}", error);
+          continue;
+        }
+      };
+      if !matches!(event.kind, notify::EventKind::Create(_) | notify::EventKind::Modify(_)) {
+        continue;
+      }
+
+      for path in event.paths {
+        let path = path.canonicalize().unwrap_or(path);
+        if let Some(asset_path) = self.files.get(&path)
+          && !changed.contains(asset_path)
+        {
+          changed.push(asset_path.clone());
+        }
+      }
+    }
+    changed
+  }
+}
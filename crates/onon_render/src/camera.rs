@@ -0,0 +1,46 @@
+use winit::dpi::PhysicalSize;
+
+use crate::render_layers::RenderLayers;
+
+/// A view of the objects, only objects sharing a layer with the camera are drawn.
+#[derive(Debug, Clone)]
+pub struct Camera {
+  pub layers: RenderLayers,
+  /// Orientation of the view for 3D passes such as the skybox, looking down -Z when identity.
+  /// Objects are still drawn in world space.
+  pub rotation: glam::Quat,
+  /// Vertical field of view of 3D passes, in radians
+  pub fov_y: f32,
+}
+
+impl Camera {
+  pub fn new(layers: RenderLayers) -> Self {
+    Self {
+      layers,
+      rotation: glam::Quat::IDENTITY,
+      fov_y: 60f32.to_radians(),
+    }
+  }
+
+  pub fn sees(&self, layers: RenderLayers) -> bool {
+    self.layers.intersects(layers)
+  }
+
+  /// Perspective projection of the camera rotation, without translation, for a view of `aspect` width over height
+  pub fn rotation_view_projection(&self, aspect: f32) -> glam::Mat4 {
+    glam::Mat4::perspective_rh(self.fov_y, aspect, 0.1, 10.0) * glam::Mat4::from_quat(self.rotation.inverse())
+  }
+
+  /// Converts a position in physical pixels of a `viewport` to world space
+  pub fn screen_to_world(&self, position: glam::Vec2, viewport: PhysicalSize<u32>) -> glam::Vec2 {
+    let size = glam::vec2(viewport.width.max(1) as f32, viewport.height.max(1) as f32);
+    let ndc = position / size * 2.0 - glam::Vec2::ONE;
+    glam::vec2(ndc.x, -ndc.y)
+  }
+}
+
+impl Default for Camera {
+  fn default() -> Self {
+    Self::new(RenderLayers::ALL)
+  }
+}
\ No newline at end of file
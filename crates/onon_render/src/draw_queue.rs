@@ -0,0 +1,121 @@
+use crate::{
+  render_object::RenderObject,
+  render_resource::{Material, SamplerId, TextureArrayId, render_pipeline::BlendMode},
+  scene::ObjectId,
+};
+
+/// Draws are ordered by comparing the fields in declaration order.
+///
+/// There is no depth buffer, so every layer is painted back to front through `depth`,
+/// nearer objects drawn over further ones whether they are opaque or transparent.
+/// Objects at the same depth are then grouped by material, bind group and mesh,
+/// transparent ones last.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SortKey {
+  pub layer: u8,
+  /// Inverted depth, the furthest objects come first
+  pub depth: u32,
+  pub transparent: bool,
+  pub material: u32,
+  pub blend: BlendMode,
+  /// See `bind_group_key`
+  pub bind_group: u64,
+  pub mesh: u32,
+}
+
+impl SortKey {
+  pub fn new(object: &RenderObject, material: &Material, bind_group: u64) -> Self {
+    let blend = object.blend.unwrap_or(material.blend_mode());
+
+    Self {
+      layer: object.sort_layer,
+      depth: !ordered_bits(object.depth),
+      transparent: material.pass().state().transparent || blend.is_transparent(),
+      material: object.material.0,
+      blend,
+      bind_group,
+      mesh: object.mesh.id.0,
+    }
+  }
+}
+
+/// Identifies the group 0 bind group of a texture array sampled with `sampler`,
+/// `None` being the array's own sampler
+pub fn bind_group_key(texture_array: TextureArrayId, sampler: Option<SamplerId>) -> u64 {
+  (texture_array.0 as u64) << 32 | sampler.map_or(0, |sampler| sampler.0 as u64 + 1)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DrawItem {
+  pub key: SortKey,
+  pub object: ObjectId,
+  /// Sampler of the object, or of its material
+  pub sampler: Option<SamplerId>,
+}
+
+#[derive(Default)]
+pub struct DrawQueue {
+  items: Vec<DrawItem>,
+}
+
+impl DrawQueue {
+  pub fn push(&mut self, item: DrawItem) {
+    self.items.push(item);
+  }
+
+  /// Stable sort, so equal keys keep their submission order
+  pub fn sort(&mut self) {
+    self.items.sort_by_key(|item| item.key);
+  }
+
+  pub fn clear(&mut self) {
+    self.items.clear();
+  }
+
+  pub fn items(&self) -> &[DrawItem] {
+    &self.items
+  }
+}
+
+/// Remembers what is bound on a render pass to skip redundant state changes.
+#[derive(Default)]
+pub struct PassState {
+  material: Option<(u32, BlendMode)>,
+  bind_group: Option<u64>,
+  mesh: Option<u32>,
+}
+
+impl PassState {
+  /// Returns true when the material pipeline of `blend` and bind groups have to be set
+  pub fn set_material(&mut self, material: u32, blend: BlendMode) -> bool {
+    replace(&mut self.material, (material, blend))
+  }
+
+  /// Returns true when the bind group has to be set
+  pub fn set_bind_group(&mut self, bind_group: u64) -> bool {
+    replace(&mut self.bind_group, bind_group)
+  }
+
+  /// Returns true when the vertex and index buffers have to be set
+  pub fn set_mesh(&mut self, mesh: u32) -> bool {
+    replace(&mut self.mesh, mesh)
+  }
+}
+
+fn replace<T: Copy + PartialEq>(current: &mut Option<T>, new: T) -> bool {
+  if *current == Some(new) {
+    return false;
+  }
+  *current = Some(new);
+  true
+}
+
+/// Maps a float to an integer with the same ordering
+fn ordered_bits(value: f32) -> u32 {
+  let bits = value.to_bits();
+  if bits & 0x8000_0000 != 0 {
+    !bits
+  } else {
+    bits | 0x8000_0000
+  }
+}
@@ -3,10 +3,36 @@ pub mod mesh;
 pub mod render_object;
 pub mod shader_pass;
 pub mod texture_manager;
+pub mod material_manager;
+pub mod sprite_batch;
+pub mod sprite_animation;
+pub mod rect;
+pub mod draw_queue;
+pub mod render_layers;
+pub mod camera;
+pub mod transform;
+pub mod scene;
+pub mod picking;
+pub mod skybox;
+pub mod texture_loader;
+pub mod asset_server;
 
 pub mod render_resource;
 mod queries;
 
 pub use renderer::Renderer;
 pub use render_object::RenderObject;
-pub use texture_manager::TextureManager;
\ No newline at end of file
+pub use texture_manager::TextureManager;
+pub use material_manager::{MaterialId, MaterialManager};
+pub use sprite_batch::{Flip, SpriteBatch};
+pub use sprite_animation::{AnimationPlayer, PlaybackMode, SpriteAnimation, SpriteFrame, SpriteSheet};
+pub use rect::Rect;
+pub use render_resource::render_pipeline::BlendMode;
+pub use render_layers::RenderLayers;
+pub use camera::Camera;
+pub use transform::Transform;
+pub use scene::{ObjectId, Scene};
+pub use picking::{PickQuery, PickResult};
+pub use skybox::Skybox;
+pub use texture_loader::TextureLoader;
+pub use asset_server::{AssetServer, Handle, LoadState};
\ No newline at end of file
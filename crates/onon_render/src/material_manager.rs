@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+
+use crate::render_resource::{Material, material::MaterialFallbacks};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(pub u32);
+
+pub struct MaterialManager {
+  materials: Vec<Material>,
+  fallbacks: MaterialFallbacks,
+}
+
+impl MaterialManager {
+  pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+    Self {
+      materials: Vec::new(),
+      fallbacks: MaterialFallbacks::new(device, queue),
+    }
+  }
+
+  pub fn add(&mut self, material: Material) -> MaterialId {
+    self.materials.push(material);
+    MaterialId(self.materials.len() as u32 - 1)
+  }
+
+  pub fn get(&self, id: MaterialId) -> Option<&Material> {
+    self.materials.get(id.0 as usize)
+  }
+
+  pub fn get_mut(&mut self, id: MaterialId) -> Option<&mut Material> {
+    self.materials.get_mut(id.0 as usize)
+  }
+
+  /// Puts `material` in place of the one of `id`, objects using `id` draw with it from then on
+  pub fn replace(&mut self, id: MaterialId, material: Material) -> Option<Material> {
+    let slot = self.materials.get_mut(id.0 as usize)?;
+    Some(std::mem::replace(slot, material))
+  }
+
+  /// Brings the GPU side of every material up to date, to be called before drawing
+  pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<()> {
+    for (id, material) in self.materials.iter_mut().enumerate() {
+      material
+        .prepare(device, queue, &self.fallbacks)
+        .context(format!("Failed to prepare material {id}"))?;
+    }
+    Ok(())
+  }
+}
\ No newline at end of file
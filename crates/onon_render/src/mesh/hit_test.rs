@@ -0,0 +1,251 @@
+use crate::{mesh::Mesh2D, rect::Rect};
+
+/// Triangle of a mesh under a point
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshHit {
+  /// Index of the triangle, its vertices are `indices[triangle * 3..triangle * 3 + 3]`
+  pub triangle: usize,
+  /// Weights of the triangle's three vertices at the hit point
+  pub barycentric: glam::Vec3,
+  /// Texture coordinates interpolated at the hit point
+  pub tex_coords: glam::Vec2,
+}
+
+impl Mesh2D {
+  /// Bounds of the vertices in mesh space
+  pub fn aabb(&self) -> Rect {
+    let mut min = glam::Vec2::splat(f32::INFINITY);
+    let mut max = glam::Vec2::splat(f32::NEG_INFINITY);
+    for vertex in &self.vertices {
+      let position = glam::Vec2::from(vertex.position);
+      min = min.min(position);
+      max = max.max(position);
+    }
+
+    if self.vertices.is_empty() {
+      return Rect::default();
+    }
+    Rect::from_min_max(min, max)
+  }
+
+  /// Bounds of the mesh once moved by `transform`
+  pub fn world_aabb(&self, transform: glam::Affine2) -> Rect {
+    let aabb = self.aabb();
+    let corners = [
+      aabb.min(),
+      glam::vec2(aabb.max().x, aabb.min().y),
+      aabb.max(),
+      glam::vec2(aabb.min().x, aabb.max().y),
+    ]
+    .map(|corner| transform.transform_point2(corner));
+
+    let min = corners.iter().fold(corners[0], |min, corner| min.min(*corner));
+    let max = corners.iter().fold(corners[0], |max, corner| max.max(*corner));
+    Rect::from_min_max(min, max)
+  }
+
+  /// Finds the triangle containing `point`, given in the space `transform` maps the mesh to
+  pub fn hit_test(&self, transform: glam::Affine2, point: glam::Vec2) -> Option<MeshHit> {
+    if transform.matrix2.determinant().abs() <= f32::EPSILON {
+      return None;
+    }
+    let local = transform.inverse().transform_point2(point);
+    if !self.aabb().contains(local) {
+      return None;
+    }
+
+    self.indices.chunks_exact(3).enumerate().find_map(|(triangle, indices)| {
+      let vertices = [
+        self.vertices[indices[0] as usize],
+        self.vertices[indices[1] as usize],
+        self.vertices[indices[2] as usize],
+      ];
+      let barycentric = barycentric(
+        local,
+        vertices[0].position.into(),
+        vertices[1].position.into(),
+        vertices[2].position.into(),
+      )?;
+
+      let tex_coords = glam::Vec2::from(vertices[0].tex_coords) * barycentric.x
+        + glam::Vec2::from(vertices[1].tex_coords) * barycentric.y
+        + glam::Vec2::from(vertices[2].tex_coords) * barycentric.z;
+
+      Some(MeshHit {
+        triangle,
+        barycentric,
+        tex_coords,
+      })
+    })
+  }
+
+  /// Distance along `direction` at which the ray enters the mesh's world bounds
+  pub fn ray_cast_aabb(
+    &self,
+    transform: glam::Affine2,
+    origin: glam::Vec2,
+    direction: glam::Vec2,
+  ) -> Option<f32> {
+    ray_aabb(origin, direction, self.world_aabb(transform))
+  }
+}
+
+/// Barycentric coordinates of `point` in the triangle, `None` if it lies outside
+fn barycentric(
+  point: glam::Vec2,
+  a: glam::Vec2,
+  b: glam::Vec2,
+  c: glam::Vec2,
+) -> Option<glam::Vec3> {
+  let ab = b - a;
+  let ac = c - a;
+  let ap = point - a;
+
+  let denominator = ab.perp_dot(ac);
+  if denominator.abs() <= f32::EPSILON {
+    return None;
+  }
+
+  let v = ap.perp_dot(ac) / denominator;
+  let w = ab.perp_dot(ap) / denominator;
+  let u = 1.0 - v - w;
+
+  if u < 0.0 || v < 0.0 || w < 0.0 {
+    return None;
+  }
+  Some(glam::vec3(u, v, w))
+}
+
+/// Slab test, returns the entry distance or 0 when starting inside the box
+pub fn ray_aabb(origin: glam::Vec2, direction: glam::Vec2, aabb: Rect) -> Option<f32> {
+  let mut near = f32::NEG_INFINITY;
+  let mut far = f32::INFINITY;
+  for axis in 0..2 {
+    let (min, max) = (aabb.min()[axis], aabb.max()[axis]);
+    // A ray parallel to the slab never enters nor leaves it, and 0 * inf would be NaN
+    if direction[axis] == 0.0 {
+      if origin[axis] < min || origin[axis] > max {
+        return None;
+      }
+      continue;
+    }
+
+    let t0 = (min - origin[axis]) / direction[axis];
+    let t1 = (max - origin[axis]) / direction[axis];
+    near = near.max(t0.min(t1));
+    far = far.min(t0.max(t1));
+  }
+
+  if near > far || far < 0.0 {
+    return None;
+  }
+  Some(near.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh::Vertex;
+
+  fn quad(device: &wgpu::Device) -> Mesh2D {
+    let corner = |x: f32, y: f32| Vertex {
+      position: [x, y],
+      tex_coords: [x + 0.5, 0.5 - y],
+    };
+    let vertices = vec![corner(-0.5, -0.5), corner(0.5, -0.5), corner(0.5, 0.5), corner(-0.5, 0.5)];
+    Mesh2D::new(vertices, vec![0, 1, 2, 0, 2, 3], device)
+  }
+
+  #[test]
+  fn barycentric_weights_points_inside_and_on_edges() {
+    let (a, b, c) = (glam::vec2(0.0, 0.0), glam::vec2(1.0, 0.0), glam::vec2(0.0, 1.0));
+    assert_eq!(barycentric(glam::vec2(0.25, 0.25), a, b, c), Some(glam::vec3(0.5, 0.25, 0.25)));
+    assert_eq!(barycentric(glam::vec2(0.5, 0.0), a, b, c), Some(glam::vec3(0.5, 0.5, 0.0)));
+    assert_eq!(barycentric(c, a, b, c), Some(glam::vec3(0.0, 0.0, 1.0)));
+    assert_eq!(barycentric(glam::vec2(0.75, 0.75), a, b, c), None);
+    assert_eq!(barycentric(glam::vec2(-0.1, 0.5), a, b, c), None);
+  }
+
+  #[test]
+  fn degenerate_triangles_are_never_hit() {
+    let (a, b) = (glam::vec2(0.0, 0.0), glam::vec2(1.0, 1.0));
+    assert_eq!(barycentric(glam::vec2(0.5, 0.5), a, b, glam::vec2(2.0, 2.0)), None);
+    assert_eq!(barycentric(a, a, a, a), None);
+  }
+
+  #[test]
+  fn hit_test_finds_the_triangle_and_its_tex_coords() {
+    let (device, _) = wgpu::Device::noop(&Default::default());
+    let mesh = quad(&device);
+    let transform = glam::Affine2::from_translation(glam::vec2(10.0, 0.0));
+
+    let hit = mesh.hit_test(transform, glam::vec2(10.25, -0.25)).unwrap();
+    assert_eq!(hit.triangle, 0);
+    assert!(hit.tex_coords.abs_diff_eq(glam::vec2(0.75, 0.75), 1e-6));
+
+    let hit = mesh.hit_test(transform, glam::vec2(9.75, 0.25)).unwrap();
+    assert_eq!(hit.triangle, 1);
+    assert!(hit.tex_coords.abs_diff_eq(glam::vec2(0.25, 0.25), 1e-6));
+
+    // The shared diagonal belongs to the first triangle
+    assert_eq!(mesh.hit_test(transform, glam::vec2(10.0, 0.0)).unwrap().triangle, 0);
+    assert_eq!(mesh.hit_test(transform, glam::vec2(0.0, 0.0)), None);
+    assert_eq!(mesh.hit_test(transform, glam::vec2(10.6, 0.0)), None);
+  }
+
+  #[test]
+  fn hit_test_misses_collapsed_meshes() {
+    let (device, _) = wgpu::Device::noop(&Default::default());
+    let mesh = quad(&device);
+    let flattened = glam::Affine2::from_scale(glam::vec2(1.0, 0.0));
+    assert_eq!(mesh.hit_test(flattened, glam::Vec2::ZERO), None);
+
+    let corner = |x: f32, y: f32| Vertex {
+      position: [x, y],
+      tex_coords: [0.0, 0.0],
+    };
+    let line = Mesh2D::new(vec![corner(0.0, 0.0), corner(1.0, 1.0), corner(2.0, 2.0)], vec![0, 1, 2], &device);
+    assert_eq!(line.hit_test(glam::Affine2::IDENTITY, glam::vec2(1.0, 1.0)), None);
+  }
+
+  #[test]
+  fn world_aabb_bounds_the_moved_corners() {
+    let (device, _) = wgpu::Device::noop(&Default::default());
+    let mesh = quad(&device);
+
+    let moved = mesh.world_aabb(glam::Affine2::from_scale_angle_translation(
+      glam::vec2(2.0, 1.0),
+      0.0,
+      glam::vec2(1.0, 1.0),
+    ));
+    assert_eq!((moved.min(), moved.max()), (glam::vec2(0.0, 0.5), glam::vec2(2.0, 1.5)));
+
+    let rotated = mesh.world_aabb(glam::Affine2::from_angle(std::f32::consts::FRAC_PI_4));
+    let half_diagonal = std::f32::consts::FRAC_1_SQRT_2;
+    assert!(rotated.min().abs_diff_eq(glam::Vec2::splat(-half_diagonal), 1e-6));
+    assert!(rotated.max().abs_diff_eq(glam::Vec2::splat(half_diagonal), 1e-6));
+  }
+
+  #[test]
+  fn ray_aabb_enters_the_box_or_misses_it() {
+    let aabb = Rect::from_min_max(glam::vec2(0.0, 0.0), glam::vec2(2.0, 2.0));
+    assert_eq!(ray_aabb(glam::vec2(-1.0, -1.0), glam::vec2(1.0, 1.0), aabb), Some(1.0));
+    assert_eq!(ray_aabb(glam::vec2(1.0, 1.0), glam::vec2(-1.0, 0.5), aabb), Some(0.0));
+    assert_eq!(ray_aabb(glam::vec2(3.0, 1.0), glam::vec2(1.0, 0.0), aabb), None);
+    assert_eq!(ray_aabb(glam::vec2(-1.0, 4.0), glam::vec2(1.0, -0.5), aabb), None);
+  }
+
+  #[test]
+  fn ray_aabb_handles_axis_parallel_rays() {
+    let aabb = Rect::from_min_max(glam::vec2(0.0, 0.0), glam::vec2(2.0, 2.0));
+    assert_eq!(ray_aabb(glam::vec2(-1.0, 1.0), glam::vec2(1.0, 0.0), aabb), Some(1.0));
+    assert_eq!(ray_aabb(glam::vec2(1.0, 5.0), glam::vec2(0.0, -2.0), aabb), Some(1.5));
+    assert_eq!(ray_aabb(glam::vec2(-1.0, 3.0), glam::vec2(1.0, 0.0), aabb), None);
+
+    // Starting on a slab boundary, where 0 * inf used to give NaN
+    assert_eq!(ray_aabb(glam::vec2(0.0, -1.0), glam::vec2(0.0, 1.0), aabb), Some(1.0));
+    assert_eq!(ray_aabb(glam::vec2(-1.0, 2.0), glam::vec2(1.0, 0.0), aabb), Some(1.0));
+    assert_eq!(ray_aabb(glam::vec2(1.0, 1.0), glam::Vec2::ZERO, aabb), Some(0.0));
+    assert_eq!(ray_aabb(glam::vec2(3.0, 1.0), glam::Vec2::ZERO, aabb), None);
+  }
+}
@@ -0,0 +1,44 @@
+use crate::rect::Rect;
+
+/// Per object data read by the vertex shader, from the instance buffer of a `Scene`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceData {
+  pub model: [[f32; 4]; 4],
+  pub texture_slot: u32,
+  /// Part of the texture array layer covered by the texture
+  pub uv_scale: [f32; 2],
+  /// Added to the scaled texture coordinates, moves the drawn part of the layer
+  pub uv_offset: [f32; 2],
+}
+
+impl InstanceData {
+  const ATTRIBS: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+    2 => Float32x4, 3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Uint32, 7 => Float32x2, 8 => Float32x2
+  ];
+
+  /// Texture coordinates are mapped to `uv_rect` of the `uv_scale` part of the layer
+  pub fn new(model: glam::Affine2, texture_slot: u32, uv_scale: glam::Vec2, uv_rect: Rect) -> Self {
+    let model = glam::Mat4::from_cols(
+      model.matrix2.x_axis.extend(0.0).extend(0.0),
+      model.matrix2.y_axis.extend(0.0).extend(0.0),
+      glam::Vec4::Z,
+      model.translation.extend(0.0).extend(1.0),
+    );
+
+    Self {
+      model: model.to_cols_array_2d(),
+      texture_slot,
+      uv_scale: (uv_scale * glam::vec2(uv_rect.width, uv_rect.height)).to_array(),
+      uv_offset: (uv_scale * uv_rect.min()).to_array(),
+    }
+  }
+
+  pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+      array_stride: core::mem::size_of::<Self>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Instance,
+      attributes: &Self::ATTRIBS,
+    }
+  }
+}
@@ -1,8 +1,16 @@
 use crate::mesh::vertex::Vertex;
+use std::sync::atomic::{AtomicU32, Ordering};
 use wgpu::util::DeviceExt;
 
+static NEXT_MESH_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Identifies the GPU buffers of a mesh, clones of a mesh share the same id.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(pub u32);
+
 #[derive(Debug, Clone)]
 pub struct Mesh2D {
+  pub id: MeshId,
   pub vertex_buffer: wgpu::Buffer,
   pub index_buffer: wgpu::Buffer,
   pub vertices: Vec<Vertex>,
@@ -12,6 +20,7 @@ pub struct Mesh2D {
 impl Mesh2D {
   pub fn new(vertices: Vec<Vertex>, indices: Vec<u16>, device: &wgpu::Device) -> Self {
     Self {
+      id: MeshId(NEXT_MESH_ID.fetch_add(1, Ordering::Relaxed)),
       vertex_buffer: device.create_buffer_init(
         &wgpu::util::BufferInitDescriptor {
           label: Some("Vertex Buffer"),
@@ -1,5 +1,11 @@
 pub mod mesh2d;
 pub mod vertex;
+pub mod instance;
+pub mod hit_test;
+pub mod nine_slice;
 
-pub use mesh2d::Mesh2D;
-pub use vertex::Vertex;
\ No newline at end of file
+pub use mesh2d::{Mesh2D, MeshId};
+pub use vertex::{SpriteVertex, Vertex};
+pub use instance::InstanceData;
+pub use hit_test::MeshHit;
+pub use nine_slice::{Insets, NineSlice, NineSliceSprite, SliceMode};
\ No newline at end of file
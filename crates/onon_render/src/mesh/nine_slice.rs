@@ -0,0 +1,340 @@
+use crate::{
+  mesh::{Mesh2D, Vertex},
+  scene::{ObjectId, Scene},
+};
+use anyhow::{Result, anyhow};
+
+/// How the edges or the center of a nine-slice fill their space
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SliceMode {
+  /// The source part is stretched over the whole space
+  Stretch,
+  /// The source part is repeated at its own size, the last copy being cut
+  Tile,
+}
+
+/// Widths of the borders of a texture, in pixels
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Insets {
+  pub left: f32,
+  pub right: f32,
+  pub top: f32,
+  pub bottom: f32,
+}
+
+impl Insets {
+  pub const fn new(left: f32, right: f32, top: f32, bottom: f32) -> Self {
+    Self { left, right, top, bottom }
+  }
+
+  pub const fn uniform(inset: f32) -> Self {
+    Self::new(inset, inset, inset, inset)
+  }
+}
+
+/// A texture cut into a 3x3 grid by its border `insets`, drawn at any `size` with the
+/// corners kept at their size, the edges and center filling the rest.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NineSlice {
+  /// Size of the texture in pixels
+  pub texture_size: glam::Vec2,
+  pub insets: Insets,
+  /// Size of the mesh, centered on its origin
+  pub size: glam::Vec2,
+  /// Mesh units per texture pixel, sizes the borders and tiles
+  pub border_scale: f32,
+  pub edge_mode: SliceMode,
+  pub center_mode: SliceMode,
+}
+
+/// Part of a nine-slice along one axis, `start` and `end` measured from the left or the top
+#[derive(Debug, Copy, Clone)]
+struct Span {
+  start: f32,
+  end: f32,
+  uv_start: f32,
+  uv_end: f32,
+}
+
+impl NineSlice {
+  /// Stretches the edges and center, borders are one unit per pixel
+  pub fn new(texture_size: glam::UVec2, insets: Insets, size: glam::Vec2) -> Self {
+    Self {
+      texture_size: texture_size.as_vec2(),
+      insets,
+      size,
+      border_scale: 1.0,
+      edge_mode: SliceMode::Stretch,
+      center_mode: SliceMode::Stretch,
+    }
+  }
+
+  /// Builds the quads, one per cell and per tile. Fails when the insets don't fit in the
+  /// texture or when tiling needs more vertices than `u16` indices reach.
+  pub fn mesh(&self, device: &wgpu::Device) -> Result<Mesh2D> {
+    let (vertices, indices) = self.geometry()?;
+    Ok(Mesh2D::new(vertices, indices, device))
+  }
+
+  fn geometry(&self) -> Result<(Vec<Vertex>, Vec<u16>)> {
+    let insets = self.insets;
+    if insets.left < 0.0 || insets.right < 0.0 || insets.top < 0.0 || insets.bottom < 0.0 {
+      return Err(anyhow!("Nine-slice insets can't be negative: {:?}", insets));
+    }
+    if insets.left + insets.right > self.texture_size.x || insets.top + insets.bottom > self.texture_size.y {
+      return Err(anyhow!("Insets {:?} don't fit in a {} texture", insets, self.texture_size));
+    }
+
+    let columns = |mode| self.axis(self.size.x, self.texture_size.x, insets.left, insets.right, mode);
+    let rows = |mode| self.axis(self.size.y, self.texture_size.y, insets.top, insets.bottom, mode);
+    let (edge_columns, center_columns) = (columns(self.edge_mode)?, columns(self.center_mode)?);
+    let (edge_rows, center_rows) = (rows(self.edge_mode)?, rows(self.center_mode)?);
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for row in 0..3 {
+      for column in 0..3 {
+        // Border rows and columns only repeat along the edge, the center along both axes
+        let x_spans = match row {
+          1 => &center_columns[column],
+          _ => &edge_columns[column],
+        };
+        let y_spans = match column {
+          1 => &center_rows[row],
+          _ => &edge_rows[row],
+        };
+        for y in y_spans {
+          for x in x_spans {
+            self.push_quad(&mut vertices, &mut indices, x, y)?;
+          }
+        }
+      }
+    }
+    Ok((vertices, indices))
+  }
+
+  /// Spans of the first border, the middle and the last border of an axis. Borders wider
+  /// than `length` together are shrunk to fit, leaving no middle.
+  fn axis(
+    &self,
+    length: f32,
+    texture_length: f32,
+    first: f32,
+    last: f32,
+    mode: SliceMode,
+  ) -> Result<[Vec<Span>; 3]> {
+    let length = length.max(0.0);
+    let texture_length = texture_length.max(1.0);
+    let mut first_length = first * self.border_scale;
+    let mut last_length = last * self.border_scale;
+    if first_length + last_length > length {
+      let shrink = length / (first_length + last_length);
+      first_length *= shrink;
+      last_length *= shrink;
+    }
+
+    let uv_first = first / texture_length;
+    let uv_last = 1.0 - last / texture_length;
+    let middle_start = first_length;
+    let middle_end = length - last_length;
+
+    let borders = [
+      Span {
+        start: 0.0,
+        end: first_length,
+        uv_start: 0.0,
+        uv_end: uv_first,
+      },
+      Span {
+        start: middle_end,
+        end: length,
+        uv_start: uv_last,
+        uv_end: 1.0,
+      },
+    ];
+
+    let tile_length = (texture_length - first - last) * self.border_scale;
+    let middle = match mode {
+      SliceMode::Tile if tile_length > 0.0 => {
+        // Every tile is a quad of four vertices
+        if (middle_end - middle_start) / tile_length > (u16::MAX / 4) as f32 {
+          let middle_length = middle_end - middle_start;
+          return Err(anyhow!("Tiling {middle_length} units with {tile_length} unit tiles needs too many vertices"));
+        }
+        let mut tiles = Vec::new();
+        let mut start = middle_start;
+        while start < middle_end {
+          let end = (start + tile_length).min(middle_end);
+          tiles.push(Span {
+            start,
+            end,
+            uv_start: uv_first,
+            uv_end: uv_first + (uv_last - uv_first) * (end - start) / tile_length,
+          });
+          start = end;
+        }
+        tiles
+      }
+      _ => vec![Span {
+        start: middle_start,
+        end: middle_end,
+        uv_start: uv_first,
+        uv_end: uv_last,
+      }],
+    };
+
+    let [first_border, last_border] = borders;
+    let spans = [vec![first_border], middle, vec![last_border]];
+    Ok(spans.map(|spans| spans.into_iter().filter(|span| span.end > span.start).collect()))
+  }
+
+  /// Counter clockwise quad, `x` measured from the left edge and `y` from the top one
+  fn push_quad(&self, vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>, x: &Span, y: &Span) -> Result<()> {
+    let base = vertices.len();
+    if base + 4 > u16::MAX as usize + 1 {
+      return Err(anyhow!("Nine-slice of size {} needs more than {} vertices", self.size, u16::MAX));
+    }
+
+    let origin = glam::vec2(-self.size.x, self.size.y) * 0.5;
+    let corner = |x: f32, u: f32, y: f32, v: f32| Vertex {
+      position: [origin.x + x, origin.y - y],
+      tex_coords: [u, v],
+    };
+    vertices.extend([
+      corner(x.start, x.uv_start, y.end, y.uv_end),
+      corner(x.end, x.uv_end, y.end, y.uv_end),
+      corner(x.end, x.uv_end, y.start, y.uv_start),
+      corner(x.start, x.uv_start, y.start, y.uv_start),
+    ]);
+    let base = base as u16;
+    indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    Ok(())
+  }
+}
+
+/// A nine-slice shown by a scene object, its mesh rebuilt by `update` whenever the
+/// description changed, such as a UI panel being resized
+#[derive(Debug, Clone)]
+pub struct NineSliceSprite {
+  pub slice: NineSlice,
+  built: Option<NineSlice>,
+}
+
+impl NineSliceSprite {
+  pub fn new(slice: NineSlice) -> Self {
+    Self { slice, built: None }
+  }
+
+  pub fn set_size(&mut self, size: glam::Vec2) {
+    self.slice.size = size;
+  }
+
+  /// Gives the object `id` of `scene` a new mesh when the slice changed since the last
+  /// call, returns whether it did
+  pub fn update(&mut self, device: &wgpu::Device, scene: &mut Scene, id: ObjectId) -> Result<bool> {
+    if self.built == Some(self.slice) || !scene.contains(id) {
+      return Ok(false);
+    }
+
+    let mesh = self.slice.mesh(device)?;
+    if let Some(object) = scene.get_mut(id) {
+      object.mesh = mesh;
+    }
+    self.built = Some(self.slice);
+    Ok(true)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn slice(size: glam::Vec2) -> NineSlice {
+    NineSlice::new(glam::uvec2(32, 32), Insets::uniform(8.0), size)
+  }
+
+  fn bounds(vertices: &[Vertex]) -> (glam::Vec2, glam::Vec2) {
+    vertices.iter().fold((glam::Vec2::MAX, glam::Vec2::MIN), |(min, max), vertex| {
+      let position = glam::Vec2::from(vertex.position);
+      (min.min(position), max.max(position))
+    })
+  }
+
+  #[test]
+  fn stretch_builds_nine_quads() {
+    let (vertices, indices) = slice(glam::vec2(100.0, 60.0)).geometry().unwrap();
+    assert_eq!(vertices.len(), 9 * 4);
+    assert_eq!(indices.len(), 9 * 6);
+    assert_eq!(bounds(&vertices), (glam::vec2(-50.0, -30.0), glam::vec2(50.0, 30.0)));
+
+    // The top left corner keeps its size and texture coordinates
+    let corner = &vertices[..4];
+    assert_eq!(corner[3].position, [-50.0, 30.0]);
+    assert_eq!(corner[3].tex_coords, [0.0, 0.0]);
+    assert_eq!(corner[1].position, [-42.0, 22.0]);
+    assert_eq!(corner[1].tex_coords, [0.25, 0.25]);
+  }
+
+  #[test]
+  fn borders_shrink_to_fit() {
+    let mut nine_slice = slice(glam::vec2(8.0, 40.0));
+    nine_slice.border_scale = 2.0;
+    let (vertices, _) = nine_slice.geometry().unwrap();
+    // No middle column, the 16 unit borders are halved to 4
+    assert_eq!(vertices.len(), 6 * 4);
+    assert_eq!(bounds(&vertices), (glam::vec2(-4.0, -20.0), glam::vec2(4.0, 20.0)));
+    let xs: Vec<f32> = vertices.iter().map(|vertex| vertex.position[0]).collect();
+    assert!(xs.iter().all(|&x| x == -4.0 || x == 0.0 || x == 4.0), "{xs:?}");
+    // Shrunk borders still show the whole border of the texture
+    let us: Vec<f32> = vertices.iter().map(|vertex| vertex.tex_coords[0]).collect();
+    assert!(us.iter().all(|&u| u == 0.0 || u == 0.25 || u == 0.75 || u == 1.0), "{us:?}");
+  }
+
+  #[test]
+  fn tiles_repeat_and_cut_the_last_one() {
+    let mut nine_slice = slice(glam::vec2(100.0, 32.0));
+    nine_slice.edge_mode = SliceMode::Tile;
+    let (vertices, _) = nine_slice.geometry().unwrap();
+    // 84 units of 16 unit tiles on the top and bottom edges, 5 whole and a cut one
+    assert_eq!(vertices.len() / 4, 2 * (2 + 6) + 3);
+
+    let last_tile = &vertices[6 * 4..7 * 4];
+    assert_eq!(last_tile[0].position[0], 38.0);
+    assert_eq!(last_tile[1].position[0], 42.0);
+    assert_eq!(last_tile[1].tex_coords[0], 0.375);
+
+    nine_slice.center_mode = SliceMode::Tile;
+    nine_slice.size = glam::vec2(100.0, 64.0);
+    let (vertices, _) = nine_slice.geometry().unwrap();
+    // 3 rows of tiles down the side edges and the center
+    assert_eq!(vertices.len() / 4, 2 * (2 + 6) + 3 + 3 + 6 * 3);
+  }
+
+  #[test]
+  fn too_many_tiles_are_refused() {
+    let mut nine_slice = NineSlice::new(glam::uvec2(3, 3), Insets::uniform(1.0), glam::vec2(100_000.0, 4.0));
+    nine_slice.edge_mode = SliceMode::Tile;
+    assert!(nine_slice.geometry().is_err());
+
+    // Few enough tiles per axis, but too many vertices for the whole mesh
+    nine_slice.edge_mode = SliceMode::Stretch;
+    nine_slice.center_mode = SliceMode::Tile;
+    nine_slice.size = glam::vec2(200.0, 200.0);
+    assert!(nine_slice.geometry().is_err());
+    nine_slice.size = glam::vec2(100.0, 100.0);
+    let (vertices, indices) = nine_slice.geometry().unwrap();
+    assert_eq!(vertices.len(), (8 + 98 * 98) * 4);
+    assert_eq!(indices.iter().copied().max(), Some(vertices.len() as u16 - 1));
+  }
+
+  #[test]
+  fn insets_must_fit_the_texture() {
+    let mut nine_slice = slice(glam::vec2(100.0, 100.0));
+    nine_slice.insets = Insets::new(20.0, 20.0, 8.0, 8.0);
+    assert!(nine_slice.geometry().is_err());
+    nine_slice.insets = Insets::new(-1.0, 8.0, 8.0, 8.0);
+    assert!(nine_slice.geometry().is_err());
+    nine_slice.insets = Insets::new(16.0, 16.0, 0.0, 0.0);
+    assert!(nine_slice.geometry().is_ok());
+  }
+}
@@ -17,3 +17,27 @@ impl Vertex {
     }
   }
 }
+
+/// Vertex used by the sprite batch, every quad carries its own
+/// tint and texture array layer so a whole batch can be drawn at once.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteVertex {
+  pub position: [f32; 2],
+  pub tex_coords: [f32; 2],
+  pub color: [f32; 4],
+  pub texture_slot: u32,
+}
+
+impl SpriteVertex {
+  const ATTRIBS: [wgpu::VertexAttribute; 4] =
+    wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Uint32];
+
+  pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+      array_stride: core::mem::size_of::<Self>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &Self::ATTRIBS,
+    }
+  }
+}
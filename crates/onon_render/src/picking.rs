@@ -0,0 +1,258 @@
+use std::{
+  collections::HashMap,
+  rc::Rc,
+  sync::{
+    Arc,
+    atomic::{AtomicU8, Ordering},
+  },
+};
+
+use winit::dpi::PhysicalSize;
+
+use crate::scene::ObjectId;
+
+pub const PICKING_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// Ticket returned by `Renderer::pick`, used to fetch the result once it's read back
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PickQuery(u64);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PickResult {
+  /// The ID buffer hasn't been read back yet
+  Pending,
+  Hit(ObjectId),
+  Miss,
+}
+
+enum RequestState {
+  /// Waiting for the next picking pass
+  Queued,
+  /// Copied into `buffer`, which gets mapped once the frame is submitted
+  Rendered {
+    buffer: wgpu::Buffer,
+    objects: Rc<HashMap<u32, ObjectId>>,
+    map_state: Option<Arc<AtomicU8>>,
+  },
+}
+
+struct PickRequest {
+  query: PickQuery,
+  x: u32,
+  y: u32,
+  state: RequestState,
+}
+
+/// Renders object ids into an `R32Uint` target when picks are queued, and
+/// reads the pixels under the queried positions back a frame later.
+pub struct PickingPass {
+  target: Option<(wgpu::Texture, wgpu::TextureView)>,
+  requests: Vec<PickRequest>,
+  results: HashMap<PickQuery, PickResult>,
+  next_query: u64,
+}
+
+impl PickingPass {
+  pub fn new() -> Self {
+    Self {
+      target: None,
+      requests: Vec::new(),
+      results: HashMap::new(),
+      next_query: 0,
+    }
+  }
+
+  /// Queues a pick at `x`, `y` in physical pixels of the surface
+  pub fn pick(&mut self, x: u32, y: u32) -> PickQuery {
+    let query = PickQuery(self.next_query);
+    self.next_query += 1;
+    self.requests.push(PickRequest {
+      query,
+      x,
+      y,
+      state: RequestState::Queued,
+    });
+    query
+  }
+
+  pub fn has_queued(&self) -> bool {
+    self
+      .requests
+      .iter()
+      .any(|request| matches!(request.state, RequestState::Queued))
+  }
+
+  /// Returns the view to render ids into, resized to the surface
+  pub fn target_view(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>) -> &wgpu::TextureView {
+    let outdated = match &self.target {
+      Some((texture, _)) => texture.width() != size.width || texture.height() != size.height,
+      None => true,
+    };
+
+    if outdated {
+      let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+          width: size.width.max(1),
+          height: size.height.max(1),
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: PICKING_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        label: Some("picking target"),
+        view_formats: &[],
+      });
+      let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+      self.target = Some((texture, view));
+    }
+
+    &self.target.as_ref().unwrap().1
+  }
+
+  /// Copies the picked pixels of the queued requests out of the rendered target.
+  /// `objects` maps the ids written this frame to their handles.
+  pub fn copy_queued(
+    &mut self,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    objects: HashMap<u32, ObjectId>,
+  ) {
+    let Some((texture, _)) = &self.target else {
+      return;
+    };
+    let objects = Rc::new(objects);
+
+    for request in &mut self.requests {
+      if !matches!(request.state, RequestState::Queued) {
+        continue;
+      }
+
+      if request.x >= texture.width() || request.y >= texture.height() {
+        self.results.insert(request.query, PickResult::Miss);
+        continue;
+      }
+
+      let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("picking readback"),
+        size: std::mem::size_of::<u32>() as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+      });
+
+      encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+          texture,
+          mip_level: 0,
+          origin: wgpu::Origin3d {
+            x: request.x,
+            y: request.y,
+            z: 0,
+          },
+          aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::TexelCopyBufferInfo {
+          buffer: &buffer,
+          layout: wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: None,
+            rows_per_image: None,
+          },
+        },
+        wgpu::Extent3d {
+          width: 1,
+          height: 1,
+          depth_or_array_layers: 1,
+        },
+      );
+
+      request.state = RequestState::Rendered {
+        buffer,
+        objects: objects.clone(),
+        map_state: None,
+      };
+    }
+
+    self.requests.retain(|request| !self.results.contains_key(&request.query));
+  }
+
+  /// Starts mapping the readback buffers, must be called after the frame was submitted
+  pub fn map_rendered(&mut self) {
+    for request in &mut self.requests {
+      if let RequestState::Rendered { buffer, map_state, .. } = &mut request.state
+        && map_state.is_none()
+      {
+        let state = Arc::new(AtomicU8::new(MAP_PENDING));
+        let callback_state = state.clone();
+        buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+          let value = if result.is_ok() { MAP_DONE } else { MAP_FAILED };
+          callback_state.store(value, Ordering::Release);
+        });
+        *map_state = Some(state);
+      }
+    }
+  }
+
+  /// Resolves the requests whose buffers finished mapping
+  pub fn collect(&mut self, device: &wgpu::Device) {
+    if self.requests.is_empty() {
+      return;
+    }
+    let _ = device.poll(wgpu::PollType::Poll);
+
+    let results = &mut self.results;
+    self.requests.retain(|request| {
+      let RequestState::Rendered { buffer, objects, map_state: Some(map_state) } = &request.state else {
+        return true;
+      };
+
+      let result = match map_state.load(Ordering::Acquire) {
+        MAP_PENDING => return true,
+        MAP_DONE => {
+          let id = {
+            let data = buffer.slice(..).get_mapped_range();
+            u32::from_ne_bytes([data[0], data[1], data[2], data[3]])
+          };
+          buffer.unmap();
+
+          match id.checked_sub(1).and_then(|index| objects.get(&index)) {
+            Some(&object) => PickResult::Hit(object),
+            None => PickResult::Miss,
+          }
+        }
+        _ => {
+          log::error!("Failed to read back pick {:?}", request.query);
+          PickResult::Miss
+        }
+      };
+
+      results.insert(request.query, result);
+      false
+    });
+  }
+
+  /// Returns the result of `query`, forgetting it once it isn't pending anymore.
+  /// Unknown queries are reported as misses.
+  pub fn take_result(&mut self, query: PickQuery) -> PickResult {
+    if let Some(result) = self.results.remove(&query) {
+      return result;
+    }
+
+    if self.requests.iter().any(|request| request.query == query) {
+      PickResult::Pending
+    } else {
+      PickResult::Miss
+    }
+  }
+}
+
+impl Default for PickingPass {
+  fn default() -> Self {
+    Self::new()
+  }
+}
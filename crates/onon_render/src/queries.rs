@@ -14,7 +14,13 @@ pub async fn query_adapter(instance: &Instance, surface: &Surface<'_>) -> Adapte
 pub async fn query_device(adapter: &Adapter) -> (Device, Queue) {
   adapter
     .request_device(&wgpu::DeviceDescriptor {
-      required_features: wgpu::Features::empty(),
+      // Textures use whichever block and high precision formats the adapter has
+      required_features: adapter.features()
+        & (wgpu::Features::TEXTURE_COMPRESSION_BC
+          | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+          | wgpu::Features::TEXTURE_COMPRESSION_ASTC
+          | wgpu::Features::TEXTURE_FORMAT_16BIT_NORM
+          | wgpu::Features::FLOAT32_FILTERABLE),
       required_limits: if cfg!(target_arch = "wasm32") {
         wgpu::Limits::downlevel_webgl2_defaults()
       } else {
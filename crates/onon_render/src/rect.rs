@@ -0,0 +1,41 @@
+/// Axis aligned rectangle, `x` and `y` being the minimum corner
+/// (the top left one in pixel space).
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Rect {
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+}
+
+impl Rect {
+  /// Covers 0 to 1 on both axes, such as a whole texture in texture coordinates
+  pub const UNIT: Self = Self::new(0.0, 0.0, 1.0, 1.0);
+
+  pub const fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+    Self { x, y, width, height }
+  }
+
+  pub fn from_min_max(min: glam::Vec2, max: glam::Vec2) -> Self {
+    Self::new(min.x, min.y, max.x - min.x, max.y - min.y)
+  }
+
+  pub fn min(&self) -> glam::Vec2 {
+    glam::vec2(self.x, self.y)
+  }
+
+  pub fn max(&self) -> glam::Vec2 {
+    glam::vec2(self.x + self.width, self.y + self.height)
+  }
+
+  pub fn center(&self) -> glam::Vec2 {
+    glam::vec2(self.x + self.width * 0.5, self.y + self.height * 0.5)
+  }
+
+  pub fn contains(&self, point: glam::Vec2) -> bool {
+    point.x >= self.x
+      && point.y >= self.y
+      && point.x <= self.x + self.width
+      && point.y <= self.y + self.height
+  }
+}
@@ -0,0 +1,47 @@
+/// Bitmask of the layers an object belongs to, or of the layers a camera renders.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RenderLayers(pub u32);
+
+impl RenderLayers {
+  pub const NONE: Self = Self(0);
+  pub const ALL: Self = Self(u32::MAX);
+  /// Layer every object is on unless told otherwise
+  pub const DEFAULT: Self = Self::layer(0);
+  /// Number of layers, numbered from 0
+  pub const COUNT: u8 = 32;
+
+  /// Mask containing only `layer`. Panics when `layer` isn't lower than `COUNT`.
+  pub const fn layer(layer: u8) -> Self {
+    Self(bit(layer))
+  }
+
+  /// Panics when `layer` isn't lower than `COUNT`
+  pub const fn with(self, layer: u8) -> Self {
+    Self(self.0 | bit(layer))
+  }
+
+  /// Panics when `layer` isn't lower than `COUNT`
+  pub const fn without(self, layer: u8) -> Self {
+    Self(self.0 & !bit(layer))
+  }
+
+  /// Layers past `COUNT` are never contained
+  pub const fn contains(self, layer: u8) -> bool {
+    layer < Self::COUNT && self.0 & 1 << layer != 0
+  }
+
+  pub const fn intersects(self, other: Self) -> bool {
+    self.0 & other.0 != 0
+  }
+}
+
+const fn bit(layer: u8) -> u32 {
+  assert!(layer < RenderLayers::COUNT, "Render layers go from 0 to 31");
+  1 << layer
+}
+
+impl Default for RenderLayers {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
\ No newline at end of file
@@ -1,27 +1,73 @@
-use crate::{mesh::Mesh2D, render_resource::texture_array::TextureArrayInfo};
+use crate::{
+  camera::Camera, material_manager::MaterialId, mesh::Mesh2D, rect::Rect, render_layers::RenderLayers,
+  render_resource::{SamplerId, TextureArrayId, render_pipeline::BlendMode},
+  transform::Transform,
+};
 
 pub struct RenderObject {
   pub mesh: Mesh2D,
-  pub pipeline_id: u32,
-  pub texture_array_info: Option<TextureArrayInfo>,
-  pub texture_slot: Option<u32>,
+  pub transform: Transform,
+  pub material: MaterialId,
+  /// Changed through `Scene::set_texture_array` or `Scene::set_texture_slot` once the
+  /// object is in a scene, which keep the reference counts of slots right
+  pub(crate) texture_array: Option<TextureArrayId>,
+  pub(crate) texture_slot: Option<u32>,
   pub texture_path: Option<std::path::PathBuf>,
+  /// Part of the layer covered by the texture, see `SlotRegion`.
+  /// Set by the renderer for textures it loads from `texture_path`.
+  pub uv_scale: glam::Vec2,
+  /// Part of the texture drawn, in texture coordinates from 0 to 1. The whole texture by
+  /// default, sprite sheet animations move it from frame to frame.
+  pub uv_rect: Rect,
+  /// Samples the texture array with this sampler instead of the material's or the array's own
+  pub sampler: Option<SamplerId>,
+  /// Blends with this mode instead of the material's
+  pub blend: Option<BlendMode>,
+  /// Layers are drawn in ascending order, before any other sorting
+  pub sort_layer: u8,
+  /// Bigger is further away, objects of a layer are drawn back to front
+  pub depth: f32,
+  /// Cameras only draw the object if they render one of its layers
+  pub layers: RenderLayers,
+  pub visible: bool,
 }
 
 impl RenderObject {
   pub fn new(
     mesh: Mesh2D,
-    pipeline_id: u32,
-    texture_array_info: Option<TextureArrayInfo>,
+    material: MaterialId,
+    texture_array: Option<TextureArrayId>,
     texture_path: Option<std::path::PathBuf>,
     texture_slot: Option<u32>
   ) -> Self {
     Self {
       mesh,
-      pipeline_id,
-      texture_array_info,
+      transform: Transform::IDENTITY,
+      material,
+      texture_array,
       texture_path,
       texture_slot,
+      uv_scale: glam::Vec2::ONE,
+      uv_rect: Rect::UNIT,
+      sampler: None,
+      blend: None,
+      sort_layer: 0,
+      depth: 0.0,
+      layers: RenderLayers::DEFAULT,
+      visible: true,
     }
   }
+
+  /// Texture array of the object, `None` being the renderer's default one
+  pub fn texture_array(&self) -> Option<TextureArrayId> {
+    self.texture_array
+  }
+
+  pub fn texture_slot(&self) -> Option<u32> {
+    self.texture_slot
+  }
+
+  pub fn is_visible_to(&self, camera: &Camera) -> bool {
+    self.visible && camera.sees(self.layers)
+  }
 }
@@ -0,0 +1,127 @@
+//! BC7 encoding of decoded Basis Universal images for devices that only sample BCn, every
+//! block in mode 6: one subset of RGBA endpoints with 4 bit indices
+
+/// Weights out of 64 of the second endpoint for each 4 bit index
+const WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Encodes RGBA8 `pixels` of `width` by `height` texels, edge blocks repeat their last texels
+pub fn encode(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+  let (width, height) = (width as usize, height as usize);
+  let mut output = Vec::with_capacity(width.div_ceil(4) * height.div_ceil(4) * 16);
+  for block_y in (0..height).step_by(4) {
+    for block_x in (0..width).step_by(4) {
+      let texels: [[u8; 4]; 16] = std::array::from_fn(|texel| {
+        let (x, y) = ((block_x + texel % 4).min(width - 1), (block_y + texel / 4).min(height - 1));
+        pixels[(y * width + x) * 4..][..4].try_into().unwrap()
+      });
+      output.extend_from_slice(&encode_block(&texels));
+    }
+  }
+  output
+}
+
+/// Endpoints at the ends of the principal axis of the texels, indices of the closest palette
+/// entries
+fn encode_block(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+  let texels = texels.map(|texel| glam::Vec4::from_array(texel.map(f32::from)));
+  let mean = texels.iter().sum::<glam::Vec4>() / 16.0;
+
+  // Power iteration on the covariance, starting from the widest channel range
+  let (low, high) = texels.iter().fold((glam::Vec4::MAX, glam::Vec4::MIN), |(low, high), &texel| {
+    (low.min(texel), high.max(texel))
+  });
+  let mut axis = high - low;
+  for _ in 0..8 {
+    let next = texels.iter().map(|&texel| (texel - mean) * (texel - mean).dot(axis)).sum::<glam::Vec4>();
+    axis = next.normalize_or_zero();
+  }
+  let (min, max) = texels.iter().fold((f32::MAX, f32::MIN), |(min, max), &texel| {
+    let projection = (texel - mean).dot(axis);
+    (min.min(projection), max.max(projection))
+  });
+  let mut endpoints = [mean + axis * min, mean + axis * max].map(quantize);
+
+  let palette: [[u32; 4]; 16] = std::array::from_fn(|index| {
+    let (e0, e1) = (endpoints[0].0, endpoints[1].0);
+    std::array::from_fn(|channel| ((64 - WEIGHTS[index]) * e0[channel] + WEIGHTS[index] * e1[channel] + 32) >> 6)
+  });
+  let mut indices = texels.map(|texel| {
+    (0..16)
+      .min_by_key(|&index| {
+        (0..4).map(|channel| (palette[index][channel] as f32 - texel[channel]).powi(2) as u32).sum::<u32>()
+      })
+      .unwrap_or(0)
+  });
+
+  // The first texel's index drops its top bit, which swapping the endpoints clears
+  if indices[0] >= 8 {
+    endpoints.swap(0, 1);
+    indices = indices.map(|index| 15 - index);
+  }
+
+  let mut data = 1u128 << 6;
+  let mut position = 7;
+  let mut write = |value: u32, count: u32| {
+    data |= (value as u128 & ((1 << count) - 1)) << position;
+    position += count;
+  };
+  for channel in 0..4 {
+    write(endpoints[0].0[channel] >> 1, 7);
+    write(endpoints[1].0[channel] >> 1, 7);
+  }
+  write(endpoints[0].1, 1);
+  write(endpoints[1].1, 1);
+  for (texel, &index) in indices.iter().enumerate() {
+    write(index as u32, if texel == 0 { 3 } else { 4 });
+  }
+  data.to_le_bytes()
+}
+
+/// Rounds an endpoint to 7 bits per channel and the shared low bit closer to it
+fn quantize(endpoint: glam::Vec4) -> ([u32; 4], u32) {
+  let round = |p_bit: u32| {
+    let values: [u32; 4] = endpoint
+      .to_array()
+      .map(|value| ((((value - p_bit as f32) / 2.0).round().clamp(0.0, 127.0) as u32) << 1) | p_bit);
+    let error: f32 = (0..4).map(|channel| (values[channel] as f32 - endpoint[channel]).powi(2)).sum();
+    (values, error)
+  };
+  let ((even, even_error), (odd, odd_error)) = (round(0), round(1));
+  match even_error <= odd_error {
+    true => (even, 0),
+    false => (odd, 1),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::render_resource::block_decode;
+
+  #[test]
+  fn gradients_decode_close_to_the_texels() {
+    let pixels: Vec<u8> = (0..6 * 5)
+      .flat_map(|texel| [texel * 8, 255 - texel * 8, 128, 255 - texel * 4])
+      .collect();
+    let encoded = encode(&pixels, 6, 5);
+    assert_eq!(encoded.len(), 4 * 16);
+
+    let (format, decoded) = block_decode::decode(wgpu::TextureFormat::Bc7RgbaUnorm, &encoded, 6, 5).unwrap();
+    assert_eq!(format, wgpu::TextureFormat::Rgba8Unorm);
+    // Within a step of the 16 entry palettes, which span about 150 levels of red per block
+    for (texel, (decoded, pixel)) in decoded.chunks(4).zip(pixels.chunks(4)).enumerate() {
+      for channel in 0..4 {
+        assert!((decoded[channel] as i32 - pixel[channel] as i32).abs() <= 8, "texel {texel}: {decoded:?} {pixel:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn solid_blocks_are_off_by_at_most_the_shared_low_bit() {
+    let encoded = encode(&[10, 200, 77, 255].repeat(16), 4, 4);
+    let (_, decoded) = block_decode::decode(wgpu::TextureFormat::Bc7RgbaUnorm, &encoded, 4, 4).unwrap();
+    for (decoded, expected) in decoded.iter().zip([10, 200, 77, 255].repeat(16)) {
+      assert!((*decoded as i32 - expected).abs() <= 1, "{decoded} {expected}");
+    }
+  }
+}
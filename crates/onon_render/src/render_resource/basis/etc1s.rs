@@ -0,0 +1,368 @@
+//! ETC1S images of BasisLZ supercompressed KTX2 files, decoded to the ETC1 blocks they are
+//! made of. The images share codebooks of endpoints and selectors, their blocks pick entries
+//! of them with Huffman coded symbols predicted from the neighboring blocks.
+
+use anyhow::{Context, Result, anyhow};
+use std::ops::Range;
+
+/// Where the slices of an image are in the data of its level. Files with alpha have an
+/// alpha slice, with the alpha in the green channel of its blocks.
+pub struct ImageDesc {
+  pub rgb: Range<usize>,
+  pub alpha: Option<Range<usize>>,
+}
+
+/// The codebooks and Huffman codes of the supercompression global data
+pub struct Codebooks {
+  /// The first four bytes of the ETC1 block of every endpoint: its 5 bit color and its
+  /// intensity table for both subblocks
+  endpoints: Vec<[u8; 4]>,
+  /// The last four bytes of the ETC1 block of every selector
+  selectors: Vec<[u8; 4]>,
+  endpoint_predictions: Huffman,
+  endpoint_deltas: Huffman,
+  selectors_or_history: Huffman,
+  history_runs: Huffman,
+  history_size: usize,
+}
+
+/// Symbol repeating the endpoint predictions of the last 2x2 group of blocks, the others are
+/// four 2 bit predictions
+const REPEAT_PREDICTIONS: u32 = 256;
+const MIN_REPEATS: u32 = 3;
+/// Runs of the first selector of the history shorter than this are coded block by block
+const MIN_HISTORY_RUN: u32 = 3;
+const HISTORY_RUN_SYMBOLS: u32 = 64;
+
+/// Parses the supercompression global data of a file of `image_count` images
+pub fn parse(data: &[u8], image_count: usize) -> Result<(Codebooks, Vec<ImageDesc>)> {
+  let u16_at = |offset: usize| {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+  };
+  let u32_at = |offset: usize| {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+  };
+  let invalid = || anyhow!("Invalid BasisLZ global data");
+
+  let (endpoint_count, selector_count) = (u16_at(0).ok_or_else(invalid)?, u16_at(2).ok_or_else(invalid)?);
+  let lengths = [u32_at(4), u32_at(8), u32_at(12)].map(|length| length.unwrap_or(0));
+  let images = (0..image_count)
+    .map(|image| {
+      let field = |index: usize| u32_at(20 + image * 20 + index * 4).ok_or_else(invalid);
+      let (rgb, alpha) = ((field(1)?, field(2)?), (field(3)?, field(4)?));
+      Ok(ImageDesc {
+        rgb: rgb.0..rgb.0 + rgb.1,
+        alpha: (alpha.1 > 0).then_some(alpha.0..alpha.0 + alpha.1),
+      })
+    })
+    .collect::<Result<Vec<_>>>()?;
+
+  let mut start = 20 + image_count * 20;
+  let [endpoints, selectors, tables] = lengths.map(|length| {
+    let range = start..start + length;
+    start += length;
+    data.get(range).unwrap_or_default()
+  });
+  if endpoint_count == 0 || selector_count == 0 || tables.is_empty() {
+    return Err(invalid());
+  }
+
+  let mut bits = Bits::new(tables);
+  let (endpoint_predictions, endpoint_deltas) = (bits.read_huffman()?, bits.read_huffman()?);
+  let (selectors_or_history, history_runs) = (bits.read_huffman()?, bits.read_huffman()?);
+  let codebooks = Codebooks {
+    endpoints: decode_endpoints(endpoints, endpoint_count)?,
+    selectors: decode_selectors(selectors, selector_count)?,
+    endpoint_predictions,
+    endpoint_deltas,
+    selectors_or_history,
+    history_runs,
+    history_size: bits.read(13) as usize,
+  };
+  if codebooks.history_size == 0 {
+    return Err(invalid());
+  }
+  Ok((codebooks, images))
+}
+
+/// Endpoints are coded as deltas from the previous one, with a code for the color channels
+/// picked by the previous value
+fn decode_endpoints(data: &[u8], count: usize) -> Result<Vec<[u8; 4]>> {
+  let mut bits = Bits::new(data);
+  let color_deltas = [bits.read_huffman()?, bits.read_huffman()?, bits.read_huffman()?];
+  let intensity_deltas = bits.read_huffman()?;
+  let grayscale = bits.read(1) == 1;
+
+  let mut color = [16; 3];
+  let mut intensity = 0;
+  (0..count)
+    .map(|_| {
+      intensity = (intensity + intensity_deltas.decode(&mut bits)?) & 7;
+      for value in &mut color[..if grayscale { 1 } else { 3 }] {
+        let deltas = match *value {
+          0..=9 => &color_deltas[0],
+          10..=21 => &color_deltas[1],
+          _ => &color_deltas[2],
+        };
+        *value = (*value + deltas.decode(&mut bits)?) & 31;
+      }
+      if grayscale {
+        color = [color[0]; 3];
+      }
+      // Differential mode without a delta, so both subblocks have the same color
+      let [r, g, b] = color.map(|value| (value << 3) as u8);
+      Ok([r, g, b, (intensity << 5 | intensity << 2 | 2) as u8])
+    })
+    .collect()
+}
+
+/// Selectors are rows of 2 bit indices of the intensity modifiers, from the most negative
+/// one up, stored as they are or XORed with the previous selector
+fn decode_selectors(data: &[u8], count: usize) -> Result<Vec<[u8; 4]>> {
+  let mut bits = Bits::new(data);
+  if bits.read(1) == 1 || bits.read(1) == 1 {
+    return Err(anyhow!("BasisLZ files with global selector codebooks aren't supported"));
+  }
+  let raw = bits.read(1) == 1;
+  let deltas = if raw { None } else { Some(bits.read_huffman()?) };
+
+  let mut rows = [0; 4];
+  (0..count)
+    .map(|index| {
+      for row in &mut rows {
+        *row = match (&deltas, index) {
+          (Some(deltas), 1..) => *row ^ deltas.decode(&mut bits)?,
+          _ => bits.read(8),
+        };
+      }
+      Ok(etc1_selectors(rows))
+    })
+    .collect()
+}
+
+/// ETC1 stores the most significant bits of the indices, then the least significant ones,
+/// down the columns. Its indices are the small positive modifier, the big one, and the
+/// negative ones in the same order.
+fn etc1_selectors(rows: [u32; 4]) -> [u8; 4] {
+  let (mut high, mut low) = (0u16, 0u16);
+  for (y, row) in rows.into_iter().enumerate() {
+    for x in 0..4 {
+      let index = [3, 2, 0, 1][(row >> (x * 2)) as usize & 3];
+      high |= (index >> 1) << (x * 4 + y);
+      low |= (index & 1) << (x * 4 + y);
+    }
+  }
+  let ([high_0, high_1], [low_0, low_1]) = (high.to_be_bytes(), low.to_be_bytes());
+  [high_0, high_1, low_0, low_1]
+}
+
+impl Codebooks {
+  /// ETC1 blocks of a slice of `blocks_x` by `blocks_y` blocks, row by row
+  pub fn decode_slice(&self, data: &[u8], blocks_x: usize, blocks_y: usize) -> Result<Vec<[u8; 8]>> {
+    let mut bits = Bits::new(data);
+    let invalid = || anyhow!("Invalid ETC1S slice");
+
+    // Endpoints of the previous row and the current one, and the predictions of the next row
+    let mut endpoint_rows = [vec![0; blocks_x], vec![0; blocks_x]];
+    let mut predictions_below = vec![0; blocks_x];
+    let (mut predictions, mut last_predictions, mut repeats) = (0, 0, 0);
+    let mut endpoint = 0;
+
+    // Approximately the most recently used selectors, a used one moves halfway to the front
+    let mut history = vec![0; self.history_size];
+    let mut history_next = self.history_size / 2;
+    let mut selector_run = 0;
+    let history_symbol = self.selectors.len() as u32;
+
+    let mut blocks = Vec::with_capacity(blocks_x * blocks_y);
+    for y in 0..blocks_y {
+      let (current, above) = (y % 2, 1 - y % 2);
+      for x in 0..blocks_x {
+        // Every 2x2 group of blocks gets the predictions of its four endpoints at once
+        if x % 2 == 0 {
+          predictions = match y % 2 {
+            0 => {
+              if repeats > 0 {
+                repeats -= 1;
+              } else {
+                match self.endpoint_predictions.decode(&mut bits)? {
+                  REPEAT_PREDICTIONS => repeats = bits.read_vlc(4) + MIN_REPEATS - 1,
+                  symbol => last_predictions = symbol,
+                }
+              }
+              predictions_below[x] = last_predictions >> 4;
+              last_predictions
+            }
+            _ => predictions_below[x],
+          };
+        }
+
+        // The endpoint of the left block, the one above or the one above on the left, or
+        // a delta from the previous one
+        endpoint = match predictions & 3 {
+          0 if x > 0 => endpoint,
+          1 if y > 0 => endpoint_rows[above][x],
+          2 if x > 0 && y > 0 => endpoint_rows[above][x - 1],
+          3 => (endpoint + self.endpoint_deltas.decode(&mut bits)? as usize) % self.endpoints.len(),
+          _ => return Err(invalid()),
+        };
+        predictions >>= 2;
+        endpoint_rows[current][x] = endpoint;
+
+        // Selectors come from the codebook, the history, or runs of the first history entry
+        let symbol = if selector_run > 0 {
+          selector_run -= 1;
+          history_symbol
+        } else {
+          match self.selectors_or_history.decode(&mut bits)? {
+            symbol if symbol == history_symbol + self.history_size as u32 => {
+              selector_run = match self.history_runs.decode(&mut bits)? {
+                symbol if symbol == HISTORY_RUN_SYMBOLS - 1 => bits.read_vlc(7) + MIN_HISTORY_RUN,
+                symbol => symbol + MIN_HISTORY_RUN,
+              } as usize;
+              if selector_run > blocks_x * blocks_y {
+                return Err(invalid());
+              }
+              selector_run -= 1;
+              history_symbol
+            }
+            symbol => symbol,
+          }
+        };
+        let selector = match symbol.checked_sub(history_symbol) {
+          Some(index) => {
+            let index = index as usize;
+            let selector = *history.get(index).ok_or_else(invalid)?;
+            history.swap(index / 2, index);
+            selector
+          }
+          None => {
+            if !history.is_empty() {
+              history[history_next] = symbol as usize;
+              history_next = if history_next + 1 == history.len() { history.len() / 2 } else { history_next + 1 };
+            }
+            symbol as usize
+          }
+        };
+
+        let [r, g, b, tables] = self.endpoints[endpoint];
+        let [high_0, high_1, low_0, low_1] = *self.selectors.get(selector).ok_or_else(invalid)?;
+        blocks.push([r, g, b, tables, high_0, high_1, low_0, low_1]);
+      }
+    }
+    Ok(blocks)
+  }
+}
+
+/// Reads the bits of a buffer from the lowest bit of its first byte up, zeros past its end
+struct Bits<'a> {
+  data: &'a [u8],
+  position: usize,
+}
+
+impl<'a> Bits<'a> {
+  fn new(data: &'a [u8]) -> Self {
+    Self { data, position: 0 }
+  }
+
+  fn read(&mut self, count: u32) -> u32 {
+    let mut value = 0;
+    for bit in 0..count {
+      let byte = self.data.get(self.position / 8).copied().unwrap_or(0);
+      value |= ((byte >> (self.position % 8)) as u32 & 1) << bit;
+      self.position += 1;
+    }
+    value
+  }
+
+  /// A variable length value in chunks of `chunk_bits` bits, each followed by a bit telling
+  /// whether another one follows
+  fn read_vlc(&mut self, chunk_bits: u32) -> u32 {
+    let mut value = 0;
+    for shift in (0..32).step_by(chunk_bits as usize) {
+      value |= self.read(chunk_bits) << shift;
+      if self.read(1) == 0 {
+        break;
+      }
+    }
+    value
+  }
+
+  /// Reads a Huffman code given by the code lengths of its symbols, themselves Huffman
+  /// coded with runs of zeros and repeats like Deflate
+  fn read_huffman(&mut self) -> Result<Huffman> {
+    const LENGTH_ORDER: [usize; 21] = [17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16];
+    let invalid = || anyhow!("Invalid Huffman code");
+
+    let symbol_count = self.read(14) as usize;
+    if symbol_count == 0 {
+      return Ok(Huffman::default());
+    }
+    let length_count = self.read(5) as usize;
+    if !(1..=21).contains(&length_count) {
+      return Err(invalid());
+    }
+    let mut length_lengths = [0; 21];
+    for &symbol in &LENGTH_ORDER[..length_count] {
+      length_lengths[symbol] = self.read(3) as u8;
+    }
+    let length_code = Huffman::new(&length_lengths)?;
+
+    let mut lengths = vec![0; symbol_count];
+    let mut index = 0;
+    while index < symbol_count {
+      let (length, count) = match length_code.decode(self)? {
+        length @ 0..=16 => (length as u8, 1),
+        17 => (0, self.read(3) + 3),
+        18 => (0, self.read(7) + 11),
+        code => {
+          let previous = *lengths[..index].last().filter(|&&length| length > 0).ok_or_else(invalid)?;
+          (previous, if code == 19 { self.read(2) + 3 } else { self.read(7) + 7 })
+        }
+      };
+      lengths.get_mut(index..index + count as usize).ok_or_else(invalid)?.fill(length);
+      index += count as usize;
+    }
+    Huffman::new(&lengths)
+  }
+}
+
+/// Canonical Huffman code, codes of the same length are consecutive in symbol order and
+/// start where the shorter ones end
+#[derive(Default)]
+struct Huffman {
+  /// Symbols with a code of each length
+  counts: [u32; 17],
+  /// Symbols sorted by code length
+  symbols: Vec<u32>,
+}
+
+impl Huffman {
+  fn new(lengths: &[u8]) -> Result<Self> {
+    let mut counts = [0; 17];
+    for &length in lengths {
+      *counts.get_mut(length as usize).context("Invalid Huffman code length")? += 1;
+    }
+    counts[0] = 0;
+    let mut symbols: Vec<u32> = (0..lengths.len() as u32).filter(|&symbol| lengths[symbol as usize] > 0).collect();
+    symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+    Ok(Self { counts, symbols })
+  }
+
+  /// Codes are read from their most significant bit
+  fn decode(&self, bits: &mut Bits) -> Result<u32> {
+    let (mut code, mut first, mut index) = (0, 0, 0);
+    for &count in &self.counts[1..] {
+      code |= bits.read(1);
+      if code < first + count {
+        return Ok(self.symbols[(index + code - first) as usize]);
+      }
+      index += count;
+      first = (first + count) << 1;
+      code <<= 1;
+    }
+    Err(anyhow!("Invalid Huffman code"))
+  }
+}
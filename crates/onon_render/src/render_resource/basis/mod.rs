@@ -0,0 +1,133 @@
+//! Transcoding of Basis Universal KTX2 files at load time: UASTC to ASTC 4x4, ETC1S to ETC2,
+//! and either of them to BC7 on devices that only sample BCn
+mod bc7;
+mod etc1s;
+mod uastc;
+
+use crate::render_resource::block_decode::etc;
+use crate::render_resource::compressed::mip_size;
+use anyhow::{Context, Result, anyhow};
+
+pub use bc7::encode as encode_bc7;
+
+/// UASTC and ETC1S data, supercompressed with BasisLZ for ETC1S
+pub fn is_basis_universal(reader: &ktx2::Reader<&[u8]>) -> bool {
+  reader.header().supercompression_scheme == Some(ktx2::SupercompressionScheme::BasisLZ)
+    || reader.dfd_blocks().any(|block| {
+      ktx2::DfdBlockBasic::parse(block.data).is_ok_and(|basic| {
+        matches!(basic.header.color_model, Some(ktx2::ColorModel::UASTC | ktx2::ColorModel::ETC1S))
+      })
+    })
+}
+
+/// Transcodes the `levels` of a Basis Universal file of a single 2D image, already inflated
+/// when they were supercompressed with Zstandard. UASTC becomes ASTC 4x4, ETC1S ETC2 RGB, or
+/// ETC2 RGBA when it has alpha.
+pub fn transcode(reader: &ktx2::Reader<&[u8]>, levels: Vec<Vec<u8>>) -> Result<(wgpu::TextureFormat, Vec<Vec<u8>>)> {
+  use wgpu::{AstcBlock, AstcChannel, TextureFormat as F};
+
+  let header = reader.header();
+  let srgb = reader.dfd_blocks().any(|block| {
+    ktx2::DfdBlockBasic::parse(block.data)
+      .is_ok_and(|basic| basic.header.transfer_function == Some(ktx2::TransferFunction::SRGB))
+  });
+
+  if header.supercompression_scheme != Some(ktx2::SupercompressionScheme::BasisLZ) {
+    let levels = levels
+      .iter()
+      .map(|level| {
+        level
+          .chunks(16)
+          .map(|block| uastc::transcode_block(block).context("Invalid UASTC block"))
+          .collect::<Result<Vec<_>>>()
+          .map(|blocks| blocks.concat())
+      })
+      .collect::<Result<Vec<_>>>()?;
+    let channel = if srgb { AstcChannel::UnormSrgb } else { AstcChannel::Unorm };
+    return Ok((F::Astc { block: AstcBlock::B4x4, channel }, levels));
+  }
+
+  let size = glam::uvec2(header.pixel_width, header.pixel_height.max(1));
+  let (codebooks, images) = etc1s::parse(reader.supercompression_global_data(), levels.len())?;
+  let alpha = images.iter().any(|image| image.alpha.is_some());
+  let levels = levels
+    .iter()
+    .zip(&images)
+    .enumerate()
+    .map(|(level, (data, image))| {
+      let blocks = mip_size(size, level as u32).map(|length| length.div_ceil(4));
+      let slice = |range: &std::ops::Range<usize>| {
+        let data = data.get(range.clone()).context("ETC1S slice past the end of its level")?;
+        codebooks.decode_slice(data, blocks.x as usize, blocks.y as usize)
+      };
+      let rgb = slice(&image.rgb)?;
+      match (alpha, &image.alpha) {
+        (false, _) => Ok(rgb.concat()),
+        (true, Some(range)) => {
+          let alpha = slice(range)?;
+          Ok(rgb.iter().zip(alpha).flat_map(|(rgb, alpha)| [eac_alpha(&alpha), *rgb].concat()).collect())
+        }
+        (true, None) => Err(anyhow!("ETC1S level {level} without its alpha slice")),
+      }
+    })
+    .collect::<Result<Vec<_>>>()?;
+
+  let format = match (alpha, srgb) {
+    (false, false) => F::Etc2Rgb8Unorm,
+    (false, true) => F::Etc2Rgb8UnormSrgb,
+    (true, false) => F::Etc2Rgba8Unorm,
+    (true, true) => F::Etc2Rgba8UnormSrgb,
+  };
+  Ok((format, levels))
+}
+
+/// EAC block of the alpha of an ETC1S alpha slice block, which is its green channel. Tries
+/// every modifier table with the multiplier and base spanning the alpha range.
+fn eac_alpha(block: &[u8; 8]) -> [u8; 8] {
+  let mut texels = [[0; 4]; 16];
+  etc::decode_etc2_rgb(block, &mut texels);
+  let alpha = texels.map(|texel| texel[1] as i32);
+  let (min, max) = (*alpha.iter().min().unwrap(), *alpha.iter().max().unwrap());
+
+  let mut best = (i32::MAX, [0; 8]);
+  for (table, modifiers) in etc::EAC_MODIFIERS.iter().enumerate() {
+    let (low, high) = (modifiers[3], modifiers[7]);
+    let multiplier = ((max - min) as f32 / (high - low) as f32).round() as i32;
+    for multiplier in (multiplier - 1).max(1)..=(multiplier + 1).min(15) {
+      let base = ((min + max) as f32 / 2.0 - (low + high) as f32 * multiplier as f32 / 2.0).round().clamp(0.0, 255.0);
+      let value = |index: usize| (base as i32 + modifiers[index] * multiplier).clamp(0, 255);
+      let mut error = 0;
+      let mut indices = 0u64;
+      for (texel, &alpha) in alpha.iter().enumerate() {
+        let index = (0..8).min_by_key(|&index| (value(index) - alpha).abs()).unwrap();
+        error += (value(index) - alpha).pow(2);
+        indices |= (index as u64) << (45 - 3 * etc::column_major(texel));
+      }
+      if error < best.0 {
+        let bits = (base as u64) << 56 | (multiplier as u64) << 52 | (table as u64) << 48 | indices;
+        best = (error, bits.to_be_bytes());
+      }
+    }
+  }
+  best.1
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn eac_alpha_keeps_the_alpha_slice_values() {
+    // Alpha slice block whose green channel, the alpha, is 126 or 130
+    let mut block = [15 << 3, 16 << 3, 15 << 3, 2, 0, 0, 0, 0];
+    block[4..].copy_from_slice(&[0x33, 0x33, 0, 0]);
+    let mut texels = [[0; 4]; 16];
+    etc::decode_etc2_rgb(&block, &mut texels);
+
+    let mut decoded = [[0; 4]; 16];
+    etc::decode_etc2_rgba(&[eac_alpha(&block), [0; 8]].concat(), &mut decoded);
+    for (texel, decoded) in texels.iter().zip(decoded) {
+      assert!((texel[1] as i32 - decoded[3] as i32).abs() <= 1, "{texel:?} {decoded:?}");
+    }
+  }
+}
@@ -0,0 +1,288 @@
+//! UASTC blocks, repacked as the ASTC 4x4 blocks they are a subset of
+
+use crate::render_resource::block_decode::astc;
+use std::sync::LazyLock;
+
+/// Layout of a UASTC mode, the number of bits and values of each field
+struct UastcMode {
+  /// Bits of the mode's code at the start of the block
+  code: u32,
+  /// Bits of the BC1 and ETC hints after the code, ASTC doesn't need them
+  hints: u32,
+  subsets: u32,
+  /// Bits of the common pattern picking the partitioning of modes with several subsets
+  pattern: u32,
+  dual_plane: bool,
+  /// Bits of the channel of the second plane, or its fixed channel
+  plane_channel: (u32, u32),
+  channels: usize,
+  endpoint_levels: u32,
+  weight_bits: u32,
+  /// ASTC block mode of the 4x4 weight grid of the mode's weights
+  block_mode: u32,
+}
+
+/// `uastc_mode(code, hints, (subsets, pattern), plane_channel, channels, (endpoint_levels, weight_bits), block_mode)`
+const fn uastc_mode(
+  code: u32,
+  hints: u32,
+  (subsets, pattern): (u32, u32),
+  plane_channel: Option<(u32, u32)>,
+  channels: usize,
+  (endpoint_levels, weight_bits): (u32, u32),
+  block_mode: u32,
+) -> UastcMode {
+  UastcMode {
+    code,
+    hints,
+    subsets,
+    pattern,
+    dual_plane: plane_channel.is_some(),
+    plane_channel: match plane_channel {
+      Some(plane_channel) => plane_channel,
+      None => (0, 0),
+    },
+    channels,
+    endpoint_levels,
+    weight_bits,
+    block_mode,
+  }
+}
+
+/// The UASTC modes, the fields of the solid color one, 8, aren't used
+const UASTC_MODES: [UastcMode; 19] = [
+  uastc_mode(4, 15, (1, 0), None, 3, (192, 4), 0x242),
+  uastc_mode(6, 15, (1, 0), None, 3, (256, 2), 0x42),
+  uastc_mode(5, 15, (2, 5), None, 3, (16, 3), 0x53),
+  uastc_mode(5, 15, (3, 4), None, 3, (12, 2), 0x42),
+  uastc_mode(5, 15, (2, 5), None, 3, (40, 2), 0x42),
+  uastc_mode(5, 15, (1, 0), None, 3, (256, 3), 0x53),
+  uastc_mode(5, 15, (1, 0), Some((2, 0)), 3, (160, 2), 0x442),
+  uastc_mode(5, 15, (2, 5), None, 3, (40, 2), 0x42),
+  uastc_mode(5, 0, (1, 0), None, 4, (0, 0), 0),
+  uastc_mode(5, 23, (2, 5), None, 4, (16, 2), 0x42),
+  uastc_mode(3, 17, (1, 0), None, 4, (48, 4), 0x242),
+  uastc_mode(2, 17, (1, 0), Some((2, 0)), 4, (48, 2), 0x442),
+  uastc_mode(3, 17, (1, 0), None, 4, (192, 3), 0x53),
+  uastc_mode(5, 23, (1, 0), Some((2, 0)), 4, (256, 1), 0x441),
+  uastc_mode(5, 23, (1, 0), None, 4, (256, 2), 0x42),
+  uastc_mode(7, 23, (1, 0), None, 2, (256, 4), 0x242),
+  uastc_mode(6, 23, (2, 5), None, 2, (256, 2), 0x42),
+  uastc_mode(6, 23, (1, 0), Some((0, 3)), 2, (256, 2), 0x442),
+  uastc_mode(4, 15, (1, 0), None, 3, (32, 5), 0x253),
+];
+
+const SOLID_COLOR_MODE: usize = 8;
+
+/// Modes by the low 7 bits of the first byte of a block, 19 is reserved
+#[rustfmt::skip]
+const MODES: [u8; 128] = [
+  11, 0, 10, 3, 11, 15, 12, 7, 11, 18, 10, 5, 11, 14, 12, 9, 11, 0, 10, 4, 11, 16, 12, 8, 11, 18, 10, 6, 11, 2, 12, 13,
+  11, 0, 10, 3, 11, 17, 12, 7, 11, 18, 10, 5, 11, 14, 12, 9, 11, 0, 10, 4, 11, 1, 12, 8, 11, 18, 10, 6, 11, 2, 12, 13,
+  11, 0, 10, 3, 11, 19, 12, 7, 11, 18, 10, 5, 11, 14, 12, 9, 11, 0, 10, 4, 11, 16, 12, 8, 11, 18, 10, 6, 11, 2, 12, 13,
+  11, 0, 10, 3, 11, 17, 12, 7, 11, 18, 10, 5, 11, 14, 12, 9, 11, 0, 10, 4, 11, 1, 12, 8, 11, 18, 10, 6, 11, 2, 12, 13,
+];
+
+/// ASTC partitioning seeds of the common patterns of two subsets, shared with BC7
+const PATTERNS_2: [u32; 30] = [
+  28, 20, 16, 29, 91, 9, 107, 72, 149, 204, 50, 114, 496, 17, 78, 39, 252, 828, 43, 156, 116, 210, 476, 273, 684, 359,
+  246, 195, 694, 524,
+];
+
+/// ASTC partitioning seeds of the common patterns of three subsets, shared with BC7
+const PATTERNS_3: [u32; 11] = [260, 74, 32, 156, 183, 15, 745, 0, 335, 902, 254];
+
+/// ASTC partitioning seeds of mode 7's two subsets, close to BC7 patterns of three subsets
+const PATTERNS_7: [u32; 19] = [
+  36, 48, 61, 137, 161, 183, 226, 281, 302, 307, 479, 495, 593, 594, 605, 799, 812, 988, 993,
+];
+
+/// Packed bits of every combination of five trits and of three quints, the smallest ones
+/// so the bits a last partial group leaves out are zeros
+static PACKED_TRITS: LazyLock<[u8; 243]> = LazyLock::new(|| {
+  let mut packed = [0; 243];
+  for bits in (0..256).rev() {
+    let trits = astc::decode_trits(bits);
+    packed[trits.iter().rev().fold(0, |index, &trit| index * 3 + trit) as usize] = bits as u8;
+  }
+  packed
+});
+static PACKED_QUINTS: LazyLock<[u8; 125]> = LazyLock::new(|| {
+  let mut packed = [0; 125];
+  for bits in (0..128).rev() {
+    let quints = astc::decode_quints(bits);
+    packed[quints.iter().rev().fold(0, |index, &quint| index * 5 + quint) as usize] = bits as u8;
+  }
+  packed
+});
+
+/// Repacks a 16 byte UASTC block as an ASTC 4x4 one, `None` for reserved modes and patterns
+pub fn transcode_block(block: &[u8]) -> Option<[u8; 16]> {
+  let data = u128::from_le_bytes(block.get(..16)?.try_into().ok()?);
+  let mut position = 0;
+  let mut read = |count: u32| {
+    let value = data.checked_shr(position).unwrap_or(0) as u32 & ((1u64 << count) - 1) as u32;
+    position += count;
+    value
+  };
+
+  let mode_index = MODES[(data & 0x7F) as usize] as usize;
+  let mode = UASTC_MODES.get(mode_index)?;
+  read(mode.code + mode.hints);
+  if mode_index == SOLID_COLOR_MODE {
+    return Some(solid_color_block([read(8), read(8), read(8), read(8)]));
+  }
+
+  let pattern = read(mode.pattern) as usize;
+  let seed = match (mode.subsets, mode_index) {
+    (1, _) => 0,
+    (3, _) => *PATTERNS_3.get(pattern)?,
+    (_, 7) => *PATTERNS_7.get(pattern)?,
+    _ => *PATTERNS_2.get(pattern)?,
+  };
+  let plane_channel = read(mode.plane_channel.0) | mode.plane_channel.1;
+
+  // Trits and quints are packed in groups before the plain bits of every value
+  let value_count = mode.channels * 2 * mode.subsets as usize;
+  let (digits, group, base) = match mode.endpoint_levels {
+    levels if levels.is_multiple_of(3) => (levels / 3, 5, 3u32),
+    levels if levels.is_multiple_of(5) => (levels / 5, 3, 5),
+    levels => (levels, 1, 1),
+  };
+  let bits = digits.trailing_zeros();
+  let groups: Vec<u32> = match base {
+    1 => Vec::new(),
+    _ => (0..value_count.div_ceil(group))
+      .map(|index| {
+        let count = (value_count - index * group).min(group);
+        read(match (base, count) {
+          (3, 1) => 2,
+          (3, 2) => 4,
+          (3, 3) => 5,
+          (3, 4) => 7,
+          (3, _) => 8,
+          (_, 1) => 3,
+          (_, 2) => 5,
+          (_, _) => 7,
+        })
+      })
+      .collect(),
+  };
+  let mut endpoints: Vec<u32> = (0..value_count)
+    .map(|index| {
+      let digit = match base {
+        1 => 0,
+        _ => groups[index / group] / base.pow((index % group) as u32) % base,
+      };
+      read(bits) | (digit << bits)
+    })
+    .collect();
+
+  let partitions: [usize; 16] =
+    std::array::from_fn(|texel| astc::select_partition(seed, texel as u32 % 4, texel as u32 / 4, mode.subsets, true));
+  let is_anchor = |texel: usize| partitions.iter().position(|&partition| partition == partitions[texel]) == Some(texel);
+  let weight_count = if mode.dual_plane { 32 } else { 16 };
+  let mut weights: Vec<u32> = (0..weight_count)
+    .map(|index| {
+      // The first weight of every subset drops its top bit, it's always 0
+      let texel = if mode.dual_plane { index / 2 } else { index };
+      read(mode.weight_bits - is_anchor(texel) as u32)
+    })
+    .collect();
+
+  // ASTC blue contracts RGB endpoints whose second one is the darker, swap those
+  if mode.channels >= 3 {
+    for subset in 0..mode.subsets as usize {
+      let values = &mut endpoints[subset * mode.channels * 2..][..mode.channels * 2];
+      let sum = |endpoint: usize| {
+        (0..3).map(|channel| astc::unquantize_color(values[channel * 2 + endpoint], mode.endpoint_levels)).sum::<u32>()
+      };
+      if sum(1) < sum(0) {
+        for channel in 0..mode.channels {
+          values.swap(channel * 2, channel * 2 + 1);
+        }
+        let weight_max = (1 << mode.weight_bits) - 1;
+        for (index, weight) in weights.iter_mut().enumerate() {
+          let texel = if mode.dual_plane { index / 2 } else { index };
+          if partitions[texel] == subset {
+            *weight = weight_max - *weight;
+          }
+        }
+      }
+    }
+  }
+
+  Some(astc_block(mode, seed, plane_channel, &endpoints, &weights))
+}
+
+/// ASTC void extent block of a single color
+fn solid_color_block(color: [u32; 4]) -> [u8; 16] {
+  let mut block = [0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0];
+  for (channel, value) in color.into_iter().enumerate() {
+    block[8 + channel * 2..][..2].fill(value as u8);
+  }
+  block
+}
+
+fn astc_block(mode: &UastcMode, seed: u32, plane_channel: u32, endpoints: &[u32], weights: &[u32]) -> [u8; 16] {
+  let mut data = mode.block_mode as u128;
+  let mut position = 11;
+  let mut write = |value: u32, count: u32| {
+    data |= ((value & ((1u64 << count) - 1) as u32) as u128) << position;
+    position += count;
+  };
+
+  // The channels give the direct LDR color endpoint modes: luminance alpha, RGB or RGBA
+  let endpoint_mode = (mode.channels as u32 - 1) * 4;
+  write(mode.subsets - 1, 2);
+  match mode.subsets {
+    1 => write(endpoint_mode, 4),
+    // Every subset uses the same mode, which the two low bits of zero tell
+    _ => {
+      write(seed, 10);
+      write(endpoint_mode << 2, 6);
+    }
+  }
+  write_integers(&mut write, endpoints, mode.endpoint_levels);
+
+  // Weights are stored from the top of the block down, with their bits reversed, and the
+  // channel of the second plane right below them
+  let weight_bits = weights.len() as u32 * mode.weight_bits;
+  if mode.dual_plane {
+    data |= (plane_channel as u128) << (128 - weight_bits - 2);
+  }
+  let weights = weights
+    .iter()
+    .enumerate()
+    .fold(0u128, |packed, (index, &weight)| packed | (weight as u128) << (index as u32 * mode.weight_bits));
+  (data | weights.reverse_bits()).to_le_bytes()
+}
+
+/// Writes `values` of `levels` levels like ASTC encodes integers, trits packed five to eight
+/// bits and quints three to seven bits spread between the plain bits of the values
+fn write_integers(write: &mut impl FnMut(u32, u32), values: &[u32], levels: u32) {
+  if levels.is_multiple_of(3) || levels.is_multiple_of(5) {
+    let (group, base, layout): (usize, u32, &[u32]) = match levels.is_multiple_of(3) {
+      true => (5, 3, &[2, 2, 1, 2, 1]),
+      false => (3, 5, &[3, 2, 2]),
+    };
+    let bits = (levels / base).trailing_zeros();
+    for values in values.chunks(group) {
+      let index = values.iter().rev().fold(0, |index, &value| index * base + (value >> bits));
+      let mut packed = match base {
+        3 => PACKED_TRITS[index as usize],
+        _ => PACKED_QUINTS[index as usize],
+      } as u32;
+      for (position, &size) in layout.iter().enumerate() {
+        write(values.get(position).copied().unwrap_or(0), bits);
+        write(packed, size);
+        packed >>= size;
+      }
+    }
+  } else {
+    let bits = levels.trailing_zeros();
+    for &value in values {
+      write(value, bits);
+    }
+  }
+}
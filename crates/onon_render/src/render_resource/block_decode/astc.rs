@@ -0,0 +1,617 @@
+use super::bits;
+
+/// What LDR decoders show for invalid blocks and HDR ones
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// Levels of the weight ranges, by high precision bit and range
+const WEIGHT_LEVELS: [[u32; 6]; 2] = [[2, 3, 4, 5, 6, 8], [10, 12, 16, 20, 24, 32]];
+
+/// Levels of the color endpoint ranges, the largest one fitting in the block is used
+const COLOR_LEVELS: [u32; 17] = [6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256];
+
+/// Decodes a 128 bit block of `block` texels, interpolating sRGB endpoints like the
+/// hardware does when `srgb`. Texels of invalid blocks get the error color.
+pub fn decode(block: &[u8], (block_width, block_height): (u32, u32), srgb: bool, texels: &mut [[u8; 4]]) {
+  if decode_block(block, block_width, block_height, srgb, texels).is_none() {
+    texels.fill(ERROR_COLOR);
+  }
+}
+
+struct BlockMode {
+  width: u32,
+  height: u32,
+  dual_plane: bool,
+  weight_levels: u32,
+}
+
+fn decode_block(block: &[u8], block_width: u32, block_height: u32, srgb: bool, texels: &mut [[u8; 4]]) -> Option<()> {
+  let data = u128::from_le_bytes(block.get(..16)?.try_into().ok()?);
+  let read = |start: u32, count: u32| bits(data, start, count);
+  if read(0, 9) == 0x1FC {
+    return decode_void_extent(data, texels);
+  }
+
+  let mode = block_mode(read(0, 11))?;
+  let partitions = read(11, 2) as usize + 1;
+  let plane_count = if mode.dual_plane { 2 } else { 1 };
+  let weight_count = (mode.width * mode.height) as usize * plane_count;
+  let weight_bits = encoded_bits(weight_count, mode.weight_levels);
+  if mode.width > block_width
+    || mode.height > block_height
+    || (partitions == 4 && mode.dual_plane)
+    || weight_count > 64
+    || !(24..=96).contains(&weight_bits)
+  {
+    return None;
+  }
+
+  // Fields that don't fit at the start of the block are stored right below the weights
+  let mut below_weights = 128 - weight_bits;
+  let (endpoint_modes, color_start) = match partitions {
+    1 => ([read(13, 4); 4], 17),
+    _ => {
+      let encoded = read(23, 6);
+      if encoded & 3 == 0 {
+        ([encoded >> 2; 4], 29)
+      } else {
+        let extra_bits = 3 * partitions as u32 - 4;
+        below_weights = below_weights.checked_sub(extra_bits)?;
+        let encoded = encoded | (read(below_weights, extra_bits) << 6);
+        // Every partition picks the class of the first field or the next one, and a mode in it
+        let class = (encoded & 3) - 1;
+        let modes = std::array::from_fn(|partition| {
+          let class = class + ((encoded >> (2 + partition)) & 1);
+          (class << 2) | ((encoded >> (2 + partitions + 2 * partition)) & 3)
+        });
+        (modes, 29)
+      }
+    }
+  };
+  let plane_channel = match mode.dual_plane {
+    true => {
+      below_weights = below_weights.checked_sub(2)?;
+      Some(read(below_weights, 2) as usize)
+    }
+    false => None,
+  };
+
+  let value_count: usize = endpoint_modes[..partitions].iter().map(|mode| ((mode >> 2) as usize + 1) * 2).sum();
+  if value_count > 18 {
+    return None;
+  }
+  let color_bits = below_weights.checked_sub(color_start)?;
+  let color_levels = *COLOR_LEVELS
+    .iter()
+    .rev()
+    .find(|&&levels| encoded_bits(value_count, levels) <= color_bits)?;
+  let values: Vec<i32> = decode_integers(data, color_start, value_count, color_levels)
+    .into_iter()
+    .map(|value| unquantize_color(value, color_levels) as i32)
+    .collect();
+
+  let mut endpoints = [([0; 4], [0; 4]); 4];
+  let mut values = values.as_slice();
+  for (partition, &endpoint_mode) in endpoint_modes[..partitions].iter().enumerate() {
+    let (partition_values, rest) = values.split_at(((endpoint_mode >> 2) as usize + 1) * 2);
+    endpoints[partition] = decode_endpoints(endpoint_mode, partition_values)?;
+    values = rest;
+  }
+
+  // Weights are stored backwards from the end of the block
+  let weights: Vec<u32> = decode_integers(data.reverse_bits(), 0, weight_count, mode.weight_levels)
+    .into_iter()
+    .map(|value| unquantize_weight(value, mode.weight_levels))
+    .collect();
+
+  let seed = read(13, 10);
+  let small_block = block_width * block_height < 31;
+  for (texel, output) in texels.iter_mut().enumerate() {
+    let (x, y) = (texel as u32 % block_width, texel as u32 / block_width);
+    let partition = match partitions {
+      1 => 0,
+      _ => select_partition(seed, x, y, partitions as u32, small_block),
+    };
+    let (e0, e1) = endpoints[partition];
+    let plane_weights: [u32; 2] = std::array::from_fn(|plane| {
+      infill_weight(&weights, plane, plane_count, &mode, (block_width, block_height), (x, y))
+    });
+
+    for channel in 0..4 {
+      let weight = match plane_channel {
+        Some(plane_channel) if plane_channel == channel => plane_weights[1],
+        _ => plane_weights[0],
+      };
+      // Endpoints are 16 bit, sRGB ones centered on their 8 bit value
+      let expand = |value: i32| match srgb {
+        true => (value as u32) << 8 | 0x80,
+        false => (value as u32) << 8 | value as u32,
+      };
+      let value = (expand(e0[channel]) * (64 - weight) + expand(e1[channel]) * weight + 32) >> 6;
+      output[channel] = (value >> 8) as u8;
+    }
+  }
+  Some(())
+}
+
+/// A block of a single color, given as 16 bit unorm values. HDR ones are errors here.
+fn decode_void_extent(data: u128, texels: &mut [[u8; 4]]) -> Option<()> {
+  let read = |start: u32, count: u32| bits(data, start, count);
+  if read(9, 1) == 1 {
+    return None;
+  }
+  let [s_low, s_high, t_low, t_high] = [12, 25, 38, 51].map(|start| read(start, 13));
+  let no_extent = [s_low, s_high, t_low, t_high].iter().all(|&coordinate| coordinate == 0x1FFF);
+  if !no_extent && (s_low >= s_high || t_low >= t_high) {
+    return None;
+  }
+
+  let color = [64, 80, 96, 112].map(|start| (read(start, 16) >> 8) as u8);
+  texels.fill(color);
+  Some(())
+}
+
+fn block_mode(mode: u32) -> Option<BlockMode> {
+  let bit = |index: u32| (mode >> index) & 1;
+  let a = (mode >> 5) & 3;
+  let mut high_precision = bit(9) == 1;
+  let mut dual_plane = bit(10) == 1;
+
+  let (width, height, range) = if mode & 3 != 0 {
+    let b = (mode >> 7) & 3;
+    let (width, height) = match (mode >> 2) & 3 {
+      0 => (b + 4, a + 2),
+      1 => (b + 8, a + 2),
+      2 => (a + 2, b + 8),
+      _ if bit(8) == 0 => (a + 2, (b & 1) + 6),
+      _ => ((b & 1) + 2, a + 2),
+    };
+    (width, height, bit(4) | ((mode & 3) << 1))
+  } else {
+    if (mode >> 2) & 3 == 0 {
+      return None;
+    }
+    let (width, height) = match (mode >> 7) & 3 {
+      0 => (12, a + 2),
+      1 => (a + 2, 12),
+      2 => {
+        // The precision and dual plane bits hold the height
+        high_precision = false;
+        dual_plane = false;
+        (a + 6, ((mode >> 9) & 3) + 6)
+      }
+      _ => match a {
+        0 => (6, 10),
+        1 => (10, 6),
+        _ => return None,
+      },
+    };
+    (width, height, bit(4) | (((mode >> 2) & 3) << 1))
+  };
+
+  Some(BlockMode {
+    width,
+    height,
+    dual_plane,
+    weight_levels: WEIGHT_LEVELS[high_precision as usize][range as usize - 2],
+  })
+}
+
+/// How values of `levels` levels are encoded: with a trit, a quint or neither, and a
+/// number of plain bits
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Encoding {
+  Bits,
+  Trits,
+  Quints,
+}
+
+fn encoding(levels: u32) -> (Encoding, u32) {
+  if levels.is_multiple_of(3) {
+    (Encoding::Trits, (levels / 3).trailing_zeros())
+  } else if levels.is_multiple_of(5) {
+    (Encoding::Quints, (levels / 5).trailing_zeros())
+  } else {
+    (Encoding::Bits, levels.trailing_zeros())
+  }
+}
+
+/// Bits taken by `count` values of `levels` levels
+fn encoded_bits(count: usize, levels: u32) -> u32 {
+  let count = count as u32;
+  let (encoding, bits) = encoding(levels);
+  count * bits
+    + match encoding {
+      Encoding::Bits => 0,
+      Encoding::Trits => (8 * count).div_ceil(5),
+      Encoding::Quints => (7 * count).div_ceil(3),
+    }
+}
+
+/// Reads `count` values of `levels` levels encoded from bit `start` of `data`. Trits are
+/// packed five to eight bits and quints three to seven bits, spread between the plain bits
+/// of the values. The last group's missing bits are zeros.
+fn decode_integers(data: u128, start: u32, count: usize, levels: u32) -> Vec<u32> {
+  let (encoding, bit_count) = encoding(levels);
+  let end = start + encoded_bits(count, levels);
+  let mut position = start;
+  let mut read = |count: u32| {
+    let value = bits(data, position, count.min(end.saturating_sub(position)));
+    position += count;
+    value
+  };
+
+  let mut values = Vec::with_capacity(count + 4);
+  while values.len() < count {
+    match encoding {
+      Encoding::Bits => values.push(read(bit_count)),
+      Encoding::Trits => {
+        let mut low = [0; 5];
+        let mut packed = 0;
+        for (index, (shift, size)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)].into_iter().enumerate() {
+          low[index] = read(bit_count);
+          packed |= read(size) << shift;
+        }
+        let trits = decode_trits(packed);
+        values.extend((0..5).map(|index| (trits[index] << bit_count) | low[index]));
+      }
+      Encoding::Quints => {
+        let mut low = [0; 3];
+        let mut packed = 0;
+        for (index, (shift, size)) in [(0, 3), (3, 2), (5, 2)].into_iter().enumerate() {
+          low[index] = read(bit_count);
+          packed |= read(size) << shift;
+        }
+        let quints = decode_quints(packed);
+        values.extend((0..3).map(|index| (quints[index] << bit_count) | low[index]));
+      }
+    }
+  }
+  values.truncate(count);
+  values
+}
+
+pub(crate) fn decode_trits(packed: u32) -> [u32; 5] {
+  let bit = |value: u32, index: u32| (value >> index) & 1;
+  let (c, t4, t3) = if (packed >> 2) & 7 == 7 {
+    ((((packed >> 5) & 7) << 2) | (packed & 3), 2, 2)
+  } else if (packed >> 5) & 3 == 3 {
+    (packed & 0x1F, 2, bit(packed, 7))
+  } else {
+    (packed & 0x1F, bit(packed, 7), (packed >> 5) & 3)
+  };
+  let (t2, t1, t0) = if c & 3 == 3 {
+    (2, bit(c, 4), (bit(c, 3) << 1) | (bit(c, 2) & !bit(c, 3) & 1))
+  } else if (c >> 2) & 3 == 3 {
+    (2, 2, c & 3)
+  } else {
+    (bit(c, 4), (c >> 2) & 3, (bit(c, 1) << 1) | (bit(c, 0) & !bit(c, 1) & 1))
+  };
+  [t0, t1, t2, t3, t4]
+}
+
+pub(crate) fn decode_quints(packed: u32) -> [u32; 3] {
+  let bit = |index: u32| (packed >> index) & 1;
+  if (packed >> 1) & 3 == 3 && (packed >> 5) & 3 == 0 {
+    let q2 = (bit(0) << 2) | ((bit(4) & !bit(0) & 1) << 1) | (bit(3) & !bit(0) & 1);
+    return [4, 4, q2];
+  }
+  let (q2, c) = if (packed >> 1) & 3 == 3 {
+    (4, (((packed >> 3) & 3) << 3) | ((!(packed >> 5) & 3) << 1) | (packed & 1))
+  } else {
+    ((packed >> 5) & 3, packed & 0x1F)
+  };
+  let (q1, q0) = if c & 7 == 5 { (4, (c >> 3) & 3) } else { ((c >> 3) & 3, c & 7) };
+  [q0, q1, q2]
+}
+
+/// Repeats the `bits` bit `value` to fill `to` bits
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+  let mut result = 0;
+  let mut shift = to as i32 - bits as i32;
+  while shift > -(bits as i32) {
+    result |= match shift >= 0 {
+      true => value << shift,
+      false => value >> -shift,
+    };
+    shift -= bits as i32;
+  }
+  result
+}
+
+/// Color endpoint value from 0 to 255
+pub(crate) fn unquantize_color(value: u32, levels: u32) -> u32 {
+  let (encoding, bits) = encoding(levels);
+  if encoding == Encoding::Bits {
+    return replicate(value, bits, 8);
+  }
+
+  let (digit, low) = (value >> bits, value & ((1 << bits) - 1));
+  let a = if low & 1 == 1 { 0x1FF } else { 0 };
+  let high = low >> 1;
+  let (b, c) = match (encoding, bits) {
+    (Encoding::Trits, 1) => (0, 204),
+    (Encoding::Quints, 1) => (0, 113),
+    (Encoding::Trits, 2) => ((high << 8) | (high << 4) | (high << 2) | (high << 1), 93),
+    (Encoding::Quints, 2) => ((high << 8) | (high << 3) | (high << 2), 54),
+    (Encoding::Trits, 3) => ((high << 7) | (high << 2) | high, 44),
+    (Encoding::Quints, 3) => ((high << 7) | (high << 1) | (high >> 1), 26),
+    (Encoding::Trits, 4) => ((high << 6) | high, 22),
+    (Encoding::Quints, 4) => ((high << 6) | (high >> 1), 13),
+    (Encoding::Trits, 5) => ((high << 5) | (high >> 2), 11),
+    (Encoding::Quints, 5) => ((high << 5) | (high >> 3), 6),
+    (_, _) => ((high << 4) | (high >> 4), 5),
+  };
+  let t = (digit * c + b) ^ a;
+  (a & 0x80) | (t >> 2)
+}
+
+/// Weight from 0 to 64
+fn unquantize_weight(value: u32, levels: u32) -> u32 {
+  let (encoding, bits) = encoding(levels);
+  let weight = match (encoding, bits) {
+    (Encoding::Bits, _) => replicate(value, bits, 6),
+    (Encoding::Trits, 0) => [0, 32, 63][value as usize],
+    (Encoding::Quints, 0) => [0, 16, 32, 47, 63][value as usize],
+    _ => {
+      let (digit, low) = (value >> bits, value & ((1 << bits) - 1));
+      let a = if low & 1 == 1 { 0x7F } else { 0 };
+      let high = low >> 1;
+      let (b, c) = match (encoding, bits) {
+        (Encoding::Trits, 1) => (0, 50),
+        (Encoding::Quints, 1) => (0, 28),
+        (Encoding::Trits, 2) => ((high << 6) | (high << 2) | high, 23),
+        (Encoding::Quints, 2) => ((high << 6) | (high << 1), 13),
+        (_, _) => ((high << 5) | high, 11),
+      };
+      let t = (digit * c + b) ^ a;
+      (a & 0x20) | (t >> 2)
+    }
+  };
+  if weight > 32 { weight + 1 } else { weight }
+}
+
+/// Endpoints of the LDR color endpoint modes, `None` for HDR ones
+fn decode_endpoints(mode: u32, values: &[i32]) -> Option<([i32; 4], [i32; 4])> {
+  let mut v = [0; 8];
+  v[..values.len()].copy_from_slice(values);
+
+  let (e0, e1) = match mode {
+    // Luminance
+    0 => ([v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]),
+    1 => {
+      let l0 = (v[0] >> 2) | (v[1] & 0xC0);
+      let l1 = (l0 + (v[1] & 0x3F)).min(255);
+      ([l0, l0, l0, 255], [l1, l1, l1, 255])
+    }
+    // Luminance and alpha
+    4 => ([v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]),
+    5 => {
+      bit_transfer_signed(&mut v, 1, 0);
+      bit_transfer_signed(&mut v, 3, 2);
+      let l1 = v[0] + v[1];
+      ([v[0], v[0], v[0], v[2]], [l1, l1, l1, v[2] + v[3]])
+    }
+    // RGB scaled
+    6 => (
+      [(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, 255],
+      [v[0], v[1], v[2], 255],
+    ),
+    // RGB and RGBA
+    8 | 12 => {
+      let (a0, a1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+      if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+        ([v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1])
+      } else {
+        (blue_contract([v[1], v[3], v[5], a1]), blue_contract([v[0], v[2], v[4], a0]))
+      }
+    }
+    9 | 13 => {
+      for pair in 0..if mode == 13 { 4 } else { 3 } {
+        bit_transfer_signed(&mut v, pair * 2 + 1, pair * 2);
+      }
+      let (a0, a1) = if mode == 13 { (v[6], v[6] + v[7]) } else { (255, 255) };
+      let base = [v[0], v[2], v[4], a0];
+      let offset = [v[0] + v[1], v[2] + v[3], v[4] + v[5], a1];
+      if v[1] + v[3] + v[5] >= 0 {
+        (base, offset)
+      } else {
+        (blue_contract(offset), blue_contract(base))
+      }
+    }
+    // RGB scaled with two alphas
+    10 => (
+      [(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, v[4]],
+      [v[0], v[1], v[2], v[5]],
+    ),
+    _ => return None,
+  };
+  Some((e0.map(|value| value.clamp(0, 255)), e1.map(|value| value.clamp(0, 255))))
+}
+
+/// Moves the top bit of `v[high]` to `v[low]`, leaving a signed 6 bit offset in `v[high]`
+fn bit_transfer_signed(v: &mut [i32; 8], high: usize, low: usize) {
+  v[low] = (v[low] >> 1) | (v[high] & 0x80);
+  v[high] = (v[high] >> 1) & 0x3F;
+  if v[high] & 0x20 != 0 {
+    v[high] -= 0x40;
+  }
+}
+
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+  [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// Partition of the texel at `x`, `y` of the partitioning `seed`
+pub(crate) fn select_partition(seed: u32, x: u32, y: u32, partitions: u32, small_block: bool) -> usize {
+  let (x, y) = if small_block { (x << 1, y << 1) } else { (x, y) };
+  let seed = seed + (partitions - 1) * 1024;
+  let random = hash52(seed);
+
+  let mut seeds: [u32; 12] = std::array::from_fn(|index| {
+    let shift = [0, 4, 8, 12, 16, 20, 24, 28, 18, 22, 26, 30][index];
+    let value = match index {
+      11 => random.rotate_left(2),
+      _ => random >> shift,
+    } & 0xF;
+    value * value
+  });
+  let (sh1, sh2) = match seed & 1 {
+    1 => (if seed & 2 != 0 { 4 } else { 5 }, if partitions == 3 { 6 } else { 5 }),
+    _ => (if partitions == 3 { 6 } else { 5 }, if seed & 2 != 0 { 4 } else { 5 }),
+  };
+  let sh3 = if seed & 0x10 != 0 { sh1 } else { sh2 };
+  for (index, seed) in seeds.iter_mut().enumerate() {
+    *seed >>= match index {
+      0..8 if index % 2 == 0 => sh1,
+      0..8 => sh2,
+      _ => sh3,
+    };
+  }
+
+  // Texels are in the first of the 2D planes of the 3D partitioning
+  let a = (seeds[0] * x + seeds[1] * y + (random >> 14)) & 0x3F;
+  let b = (seeds[2] * x + seeds[3] * y + (random >> 10)) & 0x3F;
+  let c = if partitions < 3 { 0 } else { (seeds[4] * x + seeds[5] * y + (random >> 6)) & 0x3F };
+  let d = if partitions < 4 { 0 } else { (seeds[6] * x + seeds[7] * y + (random >> 2)) & 0x3F };
+
+  if a >= b && a >= c && a >= d {
+    0
+  } else if b >= c && b >= d {
+    1
+  } else if c >= d {
+    2
+  } else {
+    3
+  }
+}
+
+fn hash52(mut value: u32) -> u32 {
+  value ^= value >> 15;
+  value = value.wrapping_sub(value << 17);
+  value = value.wrapping_add(value << 7);
+  value = value.wrapping_add(value << 4);
+  value ^= value >> 5;
+  value = value.wrapping_add(value << 16);
+  value ^= value >> 7;
+  value ^= value >> 3;
+  value ^= value << 6;
+  value ^= value >> 17;
+  value
+}
+
+/// Weight of `plane` at the texel `x`, `y` of a `block` sized block, interpolated between
+/// the four nearest points of the weight grid
+fn infill_weight(
+  weights: &[u32],
+  plane: usize,
+  plane_count: usize,
+  mode: &BlockMode,
+  block: (u32, u32),
+  (x, y): (u32, u32),
+) -> u32 {
+  if plane >= plane_count {
+    return 0;
+  }
+  let scale_x = (1024 + block.0 / 2) / (block.0 - 1);
+  let scale_y = (1024 + block.1 / 2) / (block.1 - 1);
+  let grid_x = (scale_x * x * (mode.width - 1) + 32) >> 6;
+  let grid_y = (scale_y * y * (mode.height - 1) + 32) >> 6;
+  let (column, fraction_x) = (grid_x >> 4, grid_x & 0xF);
+  let (row, fraction_y) = (grid_y >> 4, grid_y & 0xF);
+
+  let weight = |column: u32, row: u32| {
+    let index = (row * mode.width + column) as usize * plane_count + plane;
+    match column < mode.width && row < mode.height {
+      true => weights[index],
+      false => 0,
+    }
+  };
+  let w11 = (fraction_x * fraction_y + 8) >> 4;
+  let w10 = fraction_y - w11;
+  let w01 = fraction_x - w11;
+  let w00 = 16 + w11 - fraction_x - fraction_y;
+  (weight(column, row) * w00
+    + weight(column + 1, row) * w01
+    + weight(column, row + 1) * w10
+    + weight(column + 1, row + 1) * w11
+    + 8)
+    >> 4
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode_block(block: [u8; 16], dims: (u32, u32), srgb: bool) -> Vec<[u8; 4]> {
+    let mut texels = vec![[0; 4]; (dims.0 * dims.1) as usize];
+    decode(&block, dims, srgb, &mut texels);
+    texels
+  }
+
+  #[test]
+  fn void_extent_blocks_have_one_color() {
+    let mut block = [0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0];
+    block[8..].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x80, 0x00, 0x00, 0xFF, 0xFF]);
+    assert_eq!(decode_block(block, (6, 6), false), [[255, 128, 0, 255]; 36]);
+
+    // HDR
+    block[1] = 0xFF;
+    assert_eq!(decode_block(block, (6, 6), false), [ERROR_COLOR; 36]);
+  }
+
+  #[test]
+  fn invalid_blocks_get_the_error_color() {
+    // Reserved block mode
+    assert_eq!(decode_block([0; 16], (4, 4), false), [ERROR_COLOR; 16]);
+    // 12x2 weights don't fit 4x4 blocks
+    let mut block = [0; 16];
+    block[0] = 0b0000_0100;
+    assert_eq!(decode_block(block, (4, 4), false), [ERROR_COLOR; 16]);
+  }
+
+  // Expected texels come from a GPU decoding the same blocks
+
+  #[test]
+  fn three_partitions() {
+    let block = [0xce, 0xf1, 0x18, 0x88, 0x61, 0x63, 0xeb, 0xc8, 0x49, 0x0b, 0xed, 0xa8, 0x0f, 0x97, 0x82, 0x39];
+    #[rustfmt::skip]
+    let expected = [
+      [48, 48, 48, 199], [131, 131, 131, 111], [126, 126, 126, 116], [94, 94, 94, 127],
+      [132, 132, 132, 110], [142, 142, 142, 100], [152, 152, 152, 90], [28, 28, 28, 146],
+      [162, 162, 162, 81], [152, 152, 152, 90], [147, 147, 147, 95], [94, 94, 94, 127],
+      [165, 165, 165, 108], [132, 132, 132, 110], [127, 127, 127, 115], [231, 231, 231, 89],
+    ];
+    assert_eq!(decode_block(block, (4, 4), false), expected);
+  }
+
+  #[test]
+  fn dual_plane() {
+    let block = [0xbe, 0x85, 0xae, 0x2d, 0x8d, 0xe6, 0xd9, 0x7a, 0xbc, 0x07, 0xe3, 0x0a, 0x08, 0xb4, 0x1d, 0x5f];
+    #[rustfmt::skip]
+    let expected = [
+      [183, 183, 166, 157], [171, 171, 189, 186], [171, 171, 189, 186], [183, 183, 166, 157],
+      [160, 160, 178, 216], [157, 157, 192, 224], [169, 169, 198, 192], [205, 205, 200, 97],
+      [165, 165, 193, 203], [168, 168, 195, 195], [181, 181, 195, 162], [210, 210, 200, 83],
+      [199, 199, 215, 113], [210, 210, 193, 83], [210, 210, 178, 83], [199, 199, 166, 113],
+    ];
+    assert_eq!(decode_block(block, (4, 4), false), expected);
+  }
+
+  #[test]
+  fn weights_are_interpolated_over_bigger_blocks() {
+    let block = [0x9d, 0x04, 0x32, 0xa8, 0x57, 0xad, 0x78, 0x52, 0xce, 0x15, 0x1b, 0xa1, 0x9c, 0x55, 0xb7, 0xa6];
+    let texels = decode_block(block, (8, 8), false);
+    #[rustfmt::skip]
+    let first_row = [
+      [118, 118, 118, 255], [107, 107, 107, 255], [89, 89, 89, 255], [77, 77, 77, 255],
+      [66, 66, 66, 255], [54, 54, 54, 255], [36, 36, 36, 255], [25, 25, 25, 255],
+    ];
+    #[rustfmt::skip]
+    let last_row = [
+      [118, 25, 118, 255], [130, 36, 130, 255], [148, 54, 148, 255], [160, 66, 160, 255],
+      [171, 77, 171, 255], [183, 89, 183, 255], [201, 107, 201, 255], [212, 118, 212, 255],
+    ];
+    assert_eq!(texels[..8], first_row);
+    assert_eq!(texels[56..], last_row);
+  }
+}
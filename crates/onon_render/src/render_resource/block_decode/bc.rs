@@ -0,0 +1,820 @@
+use super::BitReader;
+use half::f16;
+
+pub fn decode_bc1(block: &[u8], texels: &mut [[u8; 4]]) {
+  decode_color(block, texels, true);
+}
+
+pub fn decode_bc2(block: &[u8], texels: &mut [[u8; 4]]) {
+  decode_color(&block[8..], texels, false);
+  let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+  for (index, texel) in texels.iter_mut().enumerate() {
+    texel[3] = ((alpha >> (index * 4)) & 0xF) as u8 * 17;
+  }
+}
+
+pub fn decode_bc3(block: &[u8], texels: &mut [[u8; 4]]) {
+  decode_color(&block[8..], texels, false);
+  let alpha = decode_channel(&block[..8]);
+  for (texel, alpha) in texels.iter_mut().zip(alpha) {
+    texel[3] = alpha;
+  }
+}
+
+pub fn decode_bc4(block: &[u8], texels: &mut [[u8; 4]]) {
+  let red = decode_channel(block);
+  for (texel, red) in texels.iter_mut().zip(red) {
+    *texel = [red, 0, 0, 255];
+  }
+}
+
+pub fn decode_bc5(block: &[u8], texels: &mut [[u8; 4]]) {
+  let red = decode_channel(&block[..8]);
+  let green = decode_channel(&block[8..]);
+  for (index, texel) in texels.iter_mut().enumerate() {
+    *texel = [red[index], green[index], 0, 255];
+  }
+}
+
+/// Texels are `i8`s stored as bytes, for `Rgba8Snorm`
+pub fn decode_bc4_snorm(block: &[u8], texels: &mut [[u8; 4]]) {
+  let red = decode_signed_channel(block);
+  for (texel, red) in texels.iter_mut().zip(red) {
+    *texel = [red as u8, 0, 0, 127];
+  }
+}
+
+pub fn decode_bc5_snorm(block: &[u8], texels: &mut [[u8; 4]]) {
+  let red = decode_signed_channel(&block[..8]);
+  let green = decode_signed_channel(&block[8..]);
+  for (index, texel) in texels.iter_mut().enumerate() {
+    *texel = [red[index] as u8, green[index] as u8, 0, 127];
+  }
+}
+
+/// Color part shared by BC1 to BC3. Only BC1 switches to 3 colors and
+/// transparent black when the first endpoint isn't the bigger one.
+fn decode_color(block: &[u8], texels: &mut [[u8; 4]], bc1: bool) {
+  let color_0 = u16::from_le_bytes([block[0], block[1]]);
+  let color_1 = u16::from_le_bytes([block[2], block[3]]);
+  let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+  let c0 = rgb565(color_0);
+  let c1 = rgb565(color_1);
+  let mix = |weight_0: u32, weight_1: u32| -> [u8; 4] {
+    let total = weight_0 + weight_1;
+    let channel = |i: usize| ((c0[i] as u32 * weight_0 + c1[i] as u32 * weight_1) / total) as u8;
+    [channel(0), channel(1), channel(2), 255]
+  };
+
+  let palette = if !bc1 || color_0 > color_1 {
+    [c0, c1, mix(2, 1), mix(1, 2)]
+  } else {
+    [c0, c1, mix(1, 1), [0, 0, 0, 0]]
+  };
+
+  for (index, texel) in texels.iter_mut().enumerate() {
+    *texel = palette[((indices >> (index * 2)) & 0b11) as usize];
+  }
+}
+
+/// 8 byte block of BC3 alpha, BC4 and BC5 channels
+fn decode_channel(block: &[u8]) -> [u8; 16] {
+  let a0 = block[0] as u32;
+  let a1 = block[1] as u32;
+  let mut palette = [0u8; 8];
+  palette[0] = a0 as u8;
+  palette[1] = a1 as u8;
+  if a0 > a1 {
+    for i in 1..7 {
+      palette[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+    }
+  } else {
+    for i in 1..5 {
+      palette[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+    }
+    palette[6] = 0;
+    palette[7] = 255;
+  }
+
+  channel_indices(block).map(|index| palette[index])
+}
+
+/// `decode_channel` of signed BC4 and BC5, where -128 stands for -127
+fn decode_signed_channel(block: &[u8]) -> [i8; 16] {
+  let a0 = (block[0] as i8).max(-127) as i32;
+  let a1 = (block[1] as i8).max(-127) as i32;
+  let mut palette = [0i8; 8];
+  palette[0] = a0 as i8;
+  palette[1] = a1 as i8;
+  let mix = |weight_0: i32, weight_1: i32| {
+    let total = weight_0 + weight_1;
+    let sum = a0 * weight_0 + a1 * weight_1;
+    // Rounded to the nearest, away from zero
+    ((sum + sum.signum() * total / 2) / total) as i8
+  };
+  if a0 > a1 {
+    for i in 1..7 {
+      palette[i + 1] = mix(7 - i as i32, i as i32);
+    }
+  } else {
+    for i in 1..5 {
+      palette[i + 1] = mix(5 - i as i32, i as i32);
+    }
+    palette[6] = -127;
+    palette[7] = 127;
+  }
+
+  channel_indices(block).map(|index| palette[index])
+}
+
+/// 3 bit palette indices of the 16 texels of a channel block
+fn channel_indices(block: &[u8]) -> [usize; 16] {
+  let mut bits = [0u8; 8];
+  bits[..6].copy_from_slice(&block[2..8]);
+  let indices = u64::from_le_bytes(bits);
+  std::array::from_fn(|index| ((indices >> (index * 3)) & 0b111) as usize)
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+  let r = ((color >> 11) & 0x1F) as u8;
+  let g = ((color >> 5) & 0x3F) as u8;
+  let b = (color & 0x1F) as u8;
+  [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), 255]
+}
+
+/// Layout of a BC7 mode, the number of bits of each field
+struct Bc7Mode {
+  subsets: usize,
+  partition: u32,
+  rotation: u32,
+  index_selection: u32,
+  color: u32,
+  alpha: u32,
+  /// A P-bit per endpoint, appended to each of its channels
+  endpoint_p_bits: bool,
+  /// A P-bit per subset, shared by both of its endpoints
+  shared_p_bits: bool,
+  index: u32,
+  /// Second set of indices of modes 4 and 5, for alpha or color
+  second_index: u32,
+}
+
+/// `bc7_mode(subsets, partition, rotation, index_selection, (color, alpha), p_bits, indices)`
+const fn bc7_mode(
+  subsets: usize,
+  partition: u32,
+  rotation: u32,
+  index_selection: u32,
+  (color, alpha): (u32, u32),
+  p_bits: (bool, bool),
+  (index, second_index): (u32, u32),
+) -> Bc7Mode {
+  Bc7Mode {
+    subsets,
+    partition,
+    rotation,
+    index_selection,
+    color,
+    alpha,
+    endpoint_p_bits: p_bits.0,
+    shared_p_bits: p_bits.1,
+    index,
+    second_index,
+  }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+  bc7_mode(3, 4, 0, 0, (4, 0), (true, false), (3, 0)),
+  bc7_mode(2, 6, 0, 0, (6, 0), (false, true), (3, 0)),
+  bc7_mode(3, 6, 0, 0, (5, 0), (false, false), (2, 0)),
+  bc7_mode(2, 6, 0, 0, (7, 0), (true, false), (2, 0)),
+  bc7_mode(1, 0, 2, 1, (5, 6), (false, false), (2, 3)),
+  bc7_mode(1, 0, 2, 0, (7, 8), (false, false), (2, 2)),
+  bc7_mode(1, 0, 0, 0, (7, 7), (true, false), (4, 0)),
+  bc7_mode(2, 6, 0, 0, (5, 5), (true, false), (2, 0)),
+];
+
+pub fn decode_bc7(block: &[u8], texels: &mut [[u8; 4]]) {
+  let mut reader = BitReader::new(block);
+  // The mode is the number of zeros before the first one, blocks without any are reserved
+  let Some(mode) = (0..8).find(|_| reader.read(1) == 1) else {
+    texels.fill([0; 4]);
+    return;
+  };
+  let mode = &BC7_MODES[mode];
+
+  let partition = reader.read(mode.partition) as usize;
+  let rotation = reader.read(mode.rotation);
+  let index_selection = reader.read(mode.index_selection);
+
+  let endpoint_count = mode.subsets * 2;
+  let mut endpoints = [[0u32; 4]; 6];
+  for channel in 0..3 {
+    for endpoint in &mut endpoints[..endpoint_count] {
+      endpoint[channel] = reader.read(mode.color);
+    }
+  }
+  for endpoint in &mut endpoints[..endpoint_count] {
+    endpoint[3] = reader.read(mode.alpha);
+  }
+
+  let channels = if mode.alpha > 0 { 4 } else { 3 };
+  let p_bit = mode.endpoint_p_bits || mode.shared_p_bits;
+  if p_bit {
+    let mut p_bits = [0; 6];
+    for endpoint in 0..endpoint_count {
+      p_bits[endpoint] = match mode.shared_p_bits {
+        true if endpoint % 2 == 1 => p_bits[endpoint - 1],
+        _ => reader.read(1),
+      };
+    }
+    for (endpoint, p_bit) in endpoints[..endpoint_count].iter_mut().zip(p_bits) {
+      for value in &mut endpoint[..channels] {
+        *value = (*value << 1) | p_bit;
+      }
+    }
+  }
+  let color_bits = mode.color + p_bit as u32;
+  let alpha_bits = mode.alpha + p_bit as u32;
+  for endpoint in &mut endpoints[..endpoint_count] {
+    for value in &mut endpoint[..3] {
+      *value = expand(*value, color_bits);
+    }
+    endpoint[3] = match mode.alpha {
+      0 => 255,
+      _ => expand(endpoint[3], alpha_bits),
+    };
+  }
+
+  let subset_of = |texel: usize| match mode.subsets {
+    1 => 0,
+    2 => ((PARTITIONS_2[partition] >> texel) & 1) as usize,
+    _ => PARTITIONS_3[partition][texel] as usize,
+  };
+  let anchors = match mode.subsets {
+    1 => [0, 0, 0],
+    2 => [0, ANCHORS_2[partition], 0],
+    _ => [0, ANCHORS_3_SECOND[partition], ANCHORS_3_THIRD[partition]],
+  };
+  // Anchors drop the top bit of their index, which is always zero
+  let indices: [u32; 16] = std::array::from_fn(|texel| {
+    let anchor = anchors[..mode.subsets].contains(&(texel as u8));
+    reader.read(mode.index - anchor as u32)
+  });
+  let second_indices: [u32; 16] = std::array::from_fn(|texel| match mode.second_index {
+    0 => 0,
+    bits => reader.read(bits - (texel == 0) as u32),
+  });
+
+  for (texel, output) in texels.iter_mut().enumerate() {
+    let subset = subset_of(texel);
+    let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+    let (first, second) = ((indices[texel], mode.index), (second_indices[texel], mode.second_index));
+    let ((color_index, color_bits), (alpha_index, alpha_bits)) = match (mode.second_index, index_selection) {
+      (0, _) => (first, first),
+      (_, 0) => (first, second),
+      _ => (second, first),
+    };
+    let color_weight = weight(color_bits, color_index);
+    let alpha_weight = weight(alpha_bits, alpha_index);
+
+    let mut texel = [0u8; 4];
+    for channel in 0..4 {
+      let weight = if channel == 3 { alpha_weight } else { color_weight };
+      texel[channel] = (((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6) as u8;
+    }
+    match rotation {
+      1 => texel.swap(0, 3),
+      2 => texel.swap(1, 3),
+      3 => texel.swap(2, 3),
+      _ => {}
+    }
+    *output = texel;
+  }
+}
+
+/// Extends a `bits` bit value to 8 bits by repeating its top bits
+fn expand(value: u32, bits: u32) -> u32 {
+  (value << (8 - bits)) | (value >> (2 * bits - 8))
+}
+
+/// Weight out of 64 of the second endpoint for a `bits` bit index
+fn weight(bits: u32, index: u32) -> u32 {
+  const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+  const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+  const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+  match bits {
+    2 => WEIGHTS_2[index as usize],
+    3 => WEIGHTS_3[index as usize],
+    _ => WEIGHTS_4[index as usize],
+  }
+}
+
+/// Fields of BC6H endpoints, the four endpoints `w`, `x`, `y` and `z` by channel,
+/// followed by the partition
+const RW: u8 = 0;
+const GW: u8 = 1;
+const BW: u8 = 2;
+const RX: u8 = 3;
+const GX: u8 = 4;
+const BX: u8 = 5;
+const RY: u8 = 6;
+const GY: u8 = 7;
+const BY: u8 = 8;
+const RZ: u8 = 9;
+const GZ: u8 = 10;
+const BZ: u8 = 11;
+const D: u8 = 12;
+
+/// A BC6H mode, its header laid out as runs of bits `(field, first, last)` in the order
+/// they are read. `first` is above `last` for the runs stored backwards.
+struct Bc6hMode {
+  transformed: bool,
+  endpoint_bits: u32,
+  delta_bits: [u32; 3],
+  layout: &'static [(u8, u8, u8)],
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 10,
+    delta_bits: [5, 5, 5],
+    layout: &[
+      (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (GZ, 4, 4),
+      (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+      (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 7,
+    delta_bits: [6, 6, 6],
+    layout: &[
+      (GY, 5, 5), (GZ, 4, 5), (RW, 0, 6), (BZ, 0, 1), (BY, 4, 4), (GW, 0, 6), (BY, 5, 5), (BZ, 2, 2),
+      (GY, 4, 4), (BW, 0, 6), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5), (GY, 0, 3), (GX, 0, 5),
+      (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [5, 4, 4],
+    layout: &[
+      (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (RW, 10, 10), (GY, 0, 3), (GX, 0, 3), (GW, 10, 10),
+      (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2),
+      (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [4, 5, 4],
+    layout: &[
+      (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4),
+      (GW, 10, 10), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 3), (BZ, 0, 0),
+      (BZ, 2, 2), (RZ, 0, 3), (GY, 4, 4), (BZ, 3, 3), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [4, 4, 5],
+    layout: &[
+      (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (BY, 4, 4), (GY, 0, 3), (GX, 0, 3),
+      (GW, 10, 10), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BW, 10, 10), (BY, 0, 3), (RY, 0, 3), (BZ, 1, 2),
+      (RZ, 0, 3), (BZ, 4, 4), (BZ, 3, 3), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 9,
+    delta_bits: [5, 5, 5],
+    layout: &[
+      (RW, 0, 8), (BY, 4, 4), (GW, 0, 8), (GY, 4, 4), (BW, 0, 8), (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4),
+      (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+      (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [6, 5, 5],
+    layout: &[
+      (RW, 0, 7), (GZ, 4, 4), (BY, 4, 4), (GW, 0, 7), (BZ, 2, 2), (GY, 4, 4), (BW, 0, 7), (BZ, 3, 4),
+      (RX, 0, 5), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3),
+      (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [5, 6, 5],
+    layout: &[
+      (RW, 0, 7), (BZ, 0, 0), (BY, 4, 4), (GW, 0, 7), (GY, 5, 5), (GY, 4, 4), (BW, 0, 7), (GZ, 5, 5),
+      (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+      (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [5, 5, 6],
+    layout: &[
+      (RW, 0, 7), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 7), (BY, 5, 5), (GY, 4, 4), (BW, 0, 7), (BZ, 5, 5),
+      (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 5),
+      (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: false,
+    endpoint_bits: 6,
+    delta_bits: [6, 6, 6],
+    layout: &[
+      (RW, 0, 5), (GZ, 4, 4), (BZ, 0, 1), (BY, 4, 4), (GW, 0, 5), (GY, 5, 5), (BY, 5, 5), (BZ, 2, 2),
+      (GY, 4, 4), (BW, 0, 5), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5), (GY, 0, 3),
+      (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+    ],
+  },
+  Bc6hMode {
+    transformed: false,
+    endpoint_bits: 10,
+    delta_bits: [10, 10, 10],
+    layout: &[(RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 9), (GX, 0, 9), (BX, 0, 9)],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [9, 9, 9],
+    layout: &[
+      (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 8), (RW, 10, 10), (GX, 0, 8), (GW, 10, 10), (BX, 0, 8),
+      (BW, 10, 10),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 12,
+    delta_bits: [8, 8, 8],
+    layout: &[
+      (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 7), (RW, 11, 10), (GX, 0, 7), (GW, 11, 10), (BX, 0, 7),
+      (BW, 11, 10),
+    ],
+  },
+  Bc6hMode {
+    transformed: true,
+    endpoint_bits: 16,
+    delta_bits: [4, 4, 4],
+    layout: &[
+      (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 15, 10), (GX, 0, 3), (GW, 15, 10), (BX, 0, 3),
+      (BW, 15, 10),
+    ],
+  },
+];
+
+pub fn decode_bc6h_unsigned(block: &[u8], texels: &mut [[f16; 4]]) {
+  decode_bc6h(block, texels, false);
+}
+
+pub fn decode_bc6h_signed(block: &[u8], texels: &mut [[f16; 4]]) {
+  decode_bc6h(block, texels, true);
+}
+
+fn decode_bc6h(block: &[u8], texels: &mut [[f16; 4]], signed: bool) {
+  let mut reader = BitReader::new(block);
+  let mut mode = reader.read(2);
+  if mode > 1 {
+    mode |= reader.read(3) << 2;
+  }
+  // Two bit modes, then five bit ones ending in 10 and in 11, the rest is reserved
+  let mode = match mode {
+    0 | 1 => mode as usize,
+    _ if mode & 0b11 == 0b10 => 2 + (mode >> 2) as usize,
+    3 | 7 | 11 | 15 => 10 + (mode >> 2) as usize,
+    _ => {
+      texels.fill([f16::ZERO, f16::ZERO, f16::ZERO, f16::ONE]);
+      return;
+    }
+  };
+  let mode = &BC6H_MODES[mode];
+
+  let mut fields = [0u32; 13];
+  for &(field, first, last) in mode.layout {
+    for step in 0..=first.abs_diff(last) {
+      let bit = if first <= last { first + step } else { first - step };
+      fields[field as usize] |= reader.read(1) << bit;
+    }
+  }
+
+  let subsets = if mode.layout.iter().any(|&(field, ..)| field == D) { 2 } else { 1 };
+  let bits = mode.endpoint_bits;
+  let mut endpoints = [[0i32; 3]; 4];
+  for channel in 0..3 {
+    let base = fields[channel];
+    endpoints[0][channel] = if signed { sign_extend(base, bits) } else { base as i32 };
+    for endpoint in 1..subsets * 2 {
+      let value = fields[endpoint * 3 + channel];
+      // Transformed modes store the other endpoints as differences from the first
+      let value = match mode.transformed {
+        true => (base as i32 + sign_extend(value, mode.delta_bits[channel])) as u32 & ((1 << bits) - 1),
+        false => value,
+      };
+      endpoints[endpoint][channel] = if signed { sign_extend(value, bits) } else { value as i32 };
+    }
+  }
+  for value in endpoints.iter_mut().flatten() {
+    *value = match signed {
+      true => unquantize_signed(*value, bits),
+      false => unquantize_unsigned(*value, bits),
+    };
+  }
+
+  let partition = fields[D as usize] as usize;
+  let index_bits = if subsets == 2 { 3 } else { 4 };
+  let anchor = if subsets == 2 { ANCHORS_2[partition] as usize } else { 0 };
+  for (texel, output) in texels.iter_mut().enumerate() {
+    let index = reader.read(index_bits - (texel == 0 || texel == anchor) as u32);
+    let subset = match subsets {
+      2 => ((PARTITIONS_2[partition] >> texel) & 1) as usize,
+      _ => 0,
+    };
+    let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+    let weight = weight(index_bits, index) as i32;
+    let channel = |channel: usize| {
+      let value = ((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6;
+      // Scales the unquantized range to the largest finite half float
+      let bits = match signed {
+        true if value < 0 => 0x8000 | ((-value * 31) >> 5),
+        true => (value * 31) >> 5,
+        false => (value * 31) >> 6,
+      };
+      f16::from_bits(bits as u16)
+    };
+    *output = [channel(0), channel(1), channel(2), f16::ONE];
+  }
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+  let shift = 32 - bits;
+  ((value << shift) as i32) >> shift
+}
+
+fn unquantize_unsigned(value: i32, bits: u32) -> i32 {
+  if bits >= 15 || value == 0 {
+    value
+  } else if value == (1 << bits) - 1 {
+    0xFFFF
+  } else {
+    ((value << 16) + 0x8000) >> bits
+  }
+}
+
+fn unquantize_signed(value: i32, bits: u32) -> i32 {
+  if bits >= 16 {
+    return value;
+  }
+  let magnitude = value.abs();
+  let unquantized = if magnitude == 0 {
+    0
+  } else if magnitude >= (1 << (bits - 1)) - 1 {
+    0x7FFF
+  } else {
+    ((magnitude << 15) + 0x4000) >> (bits - 1)
+  };
+  unquantized * value.signum()
+}
+
+/// Subsets of the texels of the 2 subset partitions of BC6H and BC7, a bit per texel
+const PARTITIONS_2: [u16; 64] = [
+  0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00,
+  0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE, 0x088C, 0x3110, 0x6666, 0x366C,
+  0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8,
+  0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660, 0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+  0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// Subsets of the texels of the 3 subset partitions of BC7
+const PARTITIONS_3: [[u8; 16]; 64] = [
+  [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+  [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+  [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+  [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+  [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+  [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+  [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+  [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+  [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+  [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+  [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+  [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+  [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+  [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+  [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+  [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+  [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+  [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+  [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+  [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+  [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+  [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+  [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+  [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+  [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+  [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+  [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+  [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+  [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+  [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+  [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+  [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+  [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+  [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+  [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+  [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+  [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+  [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+  [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+  [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+  [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+  [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+  [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+  [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+  [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+  [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+  [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+  [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+  [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+  [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+  [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+  [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Texel whose index drops its top bit in the second subset of 2 subset partitions
+const ANCHORS_2: [u8; 64] = [
+  15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+  15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor texels of the second subset of 3 subset partitions
+const ANCHORS_3_SECOND: [u8; 64] = [
+  3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15, 8, 15, 3,
+  5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+];
+
+/// Anchor texels of the third subset of 3 subset partitions
+const ANCHORS_3_THIRD: [u8; 64] = [
+  15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+  15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Packs `(value, bits)` fields from the lowest bit up
+  fn pack(fields: &[(u32, u32)]) -> [u8; 16] {
+    let mut block = 0u128;
+    let mut position = 0;
+    for &(value, bits) in fields {
+      block |= (value as u128) << position;
+      position += bits;
+    }
+    block.to_le_bytes()
+  }
+
+  fn decode(decoder: fn(&[u8], &mut [[u8; 4]]), block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = [[0; 4]; 16];
+    decoder(block, &mut texels);
+    texels
+  }
+
+  #[test]
+  fn bc1_interpolates_four_colors() {
+    // Red and blue, texels 0 to 3 use indices 0 to 3
+    let texels = decode(decode_bc1, &[0x00, 0xF8, 0x1F, 0x00, 0b11100100, 0, 0, 0]);
+    assert_eq!(texels[..4], [[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255]]);
+  }
+
+  #[test]
+  fn bc1_has_three_colors_and_transparent_black_when_the_first_is_smaller() {
+    let texels = decode(decode_bc1, &[0x1F, 0x00, 0x00, 0xF8, 0b11100100, 0, 0, 0]);
+    assert_eq!(texels[..4], [[0, 0, 255, 255], [255, 0, 0, 255], [127, 0, 127, 255], [0, 0, 0, 0]]);
+  }
+
+  #[test]
+  fn bc2_and_bc3_always_use_four_colors() {
+    // Black then white, all texels use index 3
+    let color = [0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    let bc2 = decode(decode_bc2, &[[0xFF; 8], color].concat());
+    assert_eq!(bc2[0], [170, 170, 170, 255]);
+    let bc3 = decode(decode_bc3, &[[0xFF, 0xFF, 0, 0, 0, 0, 0, 0], color].concat());
+    assert_eq!(bc3[0], [170, 170, 170, 255]);
+  }
+
+  #[test]
+  fn bc2_alpha_is_explicit() {
+    let alpha = [0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE];
+    let texels = decode(decode_bc2, &[alpha, [0xFF, 0xFF, 0, 0, 0, 0, 0, 0]].concat());
+    for (index, texel) in texels.iter().enumerate() {
+      assert_eq!(*texel, [255, 255, 255, index as u8 * 17]);
+    }
+  }
+
+  #[test]
+  fn bc4_has_eight_values_when_the_first_is_bigger() {
+    // Texels 0 to 7 use indices 0 to 7
+    let texels = decode(decode_bc4, &[255, 0, 0b10001000, 0b11000110, 0b11111010, 0, 0, 0]);
+    let red = texels[..8].iter().map(|texel| texel[0]).collect::<Vec<_>>();
+    assert_eq!(red, [255, 0, 218, 182, 145, 109, 72, 36]);
+    assert_eq!(texels[0], [255, 0, 0, 255]);
+  }
+
+  #[test]
+  fn bc4_has_six_values_and_the_extremes_when_the_first_is_smaller() {
+    let texels = decode(decode_bc4, &[0, 255, 0b10001000, 0b11000110, 0b11111010, 0, 0, 0]);
+    let red = texels[..8].iter().map(|texel| texel[0]).collect::<Vec<_>>();
+    assert_eq!(red, [0, 255, 51, 102, 153, 204, 0, 255]);
+  }
+
+  #[test]
+  fn bc4_snorm_clamps_minus_128_and_rounds_away_from_zero() {
+    let texels = decode(decode_bc4_snorm, &[0x80, 127, 0b10001000, 0b11000110, 0b11111010, 0, 0, 0]);
+    let red = texels[..8].iter().map(|texel| texel[0] as i8).collect::<Vec<_>>();
+    assert_eq!(red, [-127, 127, -76, -25, 25, 76, -127, 127]);
+    assert_eq!(texels[0][3], 127);
+  }
+
+  #[test]
+  fn bc5_decodes_red_and_green() {
+    let block = [[255, 0, 0, 0, 0, 0, 0, 0], [0, 255, 0, 0, 0, 0, 0, 0]].concat();
+    assert_eq!(decode(decode_bc5, &block)[0], [255, 0, 0, 255]);
+    let block = [[0x81, 0, 0, 0, 0, 0, 0, 0], [127, 0, 0, 0, 0, 0, 0, 0]].concat();
+    assert_eq!(decode(decode_bc5_snorm, &block)[0].map(|value| value as i8), [-127, 127, 0, 127]);
+  }
+
+  #[test]
+  fn bc7_mode_6_interpolates_with_p_bits() {
+    // Red to black endpoints with both p-bits set, texels 0 to 2 use indices 0, 15 and 8
+    let block = pack(&[
+      (1 << 6, 7),
+      (127, 7),
+      (0, 7),
+      (0, 28),
+      (127, 7),
+      (127, 7),
+      (1, 1),
+      (1, 1),
+      (0, 3),
+      (15, 4),
+      (8, 4),
+    ]);
+    let texels = decode(decode_bc7, &block);
+    assert_eq!(texels[..3], [[255, 1, 1, 255], [1, 1, 1, 255], [120, 1, 1, 255]]);
+  }
+
+  #[test]
+  fn bc7_reserved_mode_is_transparent_black() {
+    assert_eq!(decode(decode_bc7, &[0; 16]), [[0; 4]; 16]);
+  }
+
+  #[test]
+  fn bc6h_mode_11_reaches_the_f16_range() {
+    let decode = |decoder: fn(&[u8], &mut [[f16; 4]]), endpoints: (u32, u32)| {
+      // Texels 0 and 1 use the first and the last endpoint
+      let block = pack(&[
+        (0b00011, 5),
+        (endpoints.0, 10),
+        (endpoints.0, 10),
+        (endpoints.0, 10),
+        (endpoints.1, 10),
+        (endpoints.1, 10),
+        (endpoints.1, 10),
+        (0, 3),
+        (15, 4),
+      ]);
+      let mut texels = [[f16::ZERO; 4]; 16];
+      decoder(&block, &mut texels);
+      [texels[0], texels[1]]
+    };
+
+    let [first, last] = decode(decode_bc6h_unsigned, (0, 1023));
+    assert_eq!(first, [f16::ZERO, f16::ZERO, f16::ZERO, f16::ONE]);
+    assert_eq!(last, [f16::MAX, f16::MAX, f16::MAX, f16::ONE]);
+    // -511 and 511
+    let [first, last] = decode(decode_bc6h_signed, (0x201, 0x1FF));
+    assert_eq!(first, [f16::MIN, f16::MIN, f16::MIN, f16::ONE]);
+    assert_eq!(last, [f16::MAX, f16::MAX, f16::MAX, f16::ONE]);
+  }
+}
@@ -0,0 +1,302 @@
+use half::f16;
+
+/// Intensity modifiers of the individual and differential modes, the small and the big one
+const MODIFIERS: [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+
+/// Distances between the paint colors of the T and H modes
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+pub(crate) const EAC_MODIFIERS: [[i32; 8]; 16] = [
+  [-3, -6, -9, -15, 2, 5, 8, 14],
+  [-3, -7, -10, -13, 2, 6, 9, 12],
+  [-2, -5, -8, -13, 1, 4, 7, 12],
+  [-2, -4, -6, -13, 1, 3, 5, 12],
+  [-3, -6, -8, -12, 2, 5, 7, 11],
+  [-3, -7, -9, -11, 2, 6, 8, 10],
+  [-4, -7, -8, -11, 3, 6, 7, 10],
+  [-3, -5, -8, -11, 2, 4, 7, 10],
+  [-2, -6, -8, -10, 1, 5, 7, 9],
+  [-2, -5, -8, -10, 1, 4, 7, 9],
+  [-2, -4, -8, -10, 1, 3, 7, 9],
+  [-2, -5, -7, -10, 1, 4, 6, 9],
+  [-3, -4, -7, -10, 2, 3, 6, 9],
+  [-1, -2, -3, -10, 0, 1, 2, 9],
+  [-4, -6, -8, -9, 3, 5, 7, 8],
+  [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+pub fn decode_etc2_rgb(block: &[u8], texels: &mut [[u8; 4]]) {
+  decode_color(block, texels, false);
+}
+
+pub fn decode_etc2_rgb_a1(block: &[u8], texels: &mut [[u8; 4]]) {
+  decode_color(block, texels, true);
+}
+
+pub fn decode_etc2_rgba(block: &[u8], texels: &mut [[u8; 4]]) {
+  decode_color(&block[8..], texels, false);
+  let alpha = decode_channel(&block[..8], |base, modifier, multiplier| (base + modifier * multiplier).clamp(0, 255));
+  for (texel, alpha) in texels.iter_mut().zip(alpha) {
+    texel[3] = alpha as u8;
+  }
+}
+
+pub fn decode_eac_r11(block: &[u8], texels: &mut [[f16; 4]]) {
+  let red = decode_r11(block, false);
+  for (texel, red) in texels.iter_mut().zip(red) {
+    *texel = [red, f16::ZERO, f16::ZERO, f16::ONE];
+  }
+}
+
+pub fn decode_eac_r11_snorm(block: &[u8], texels: &mut [[f16; 4]]) {
+  let red = decode_r11(block, true);
+  for (texel, red) in texels.iter_mut().zip(red) {
+    *texel = [red, f16::ZERO, f16::ZERO, f16::ONE];
+  }
+}
+
+pub fn decode_eac_rg11(block: &[u8], texels: &mut [[f16; 4]]) {
+  let red = decode_r11(&block[..8], false);
+  let green = decode_r11(&block[8..], false);
+  for (index, texel) in texels.iter_mut().enumerate() {
+    *texel = [red[index], green[index], f16::ZERO, f16::ONE];
+  }
+}
+
+pub fn decode_eac_rg11_snorm(block: &[u8], texels: &mut [[f16; 4]]) {
+  let red = decode_r11(&block[..8], true);
+  let green = decode_r11(&block[8..], true);
+  for (index, texel) in texels.iter_mut().enumerate() {
+    *texel = [red[index], green[index], f16::ZERO, f16::ONE];
+  }
+}
+
+/// Texel of the block numbered down the columns like ETC does, from one numbered along the rows
+pub(crate) fn column_major(texel: usize) -> usize {
+  (texel % 4) * 4 + texel / 4
+}
+
+/// ETC2 RGB block, also used by RGBA8. With `punchthrough`, for RGB8A1, there's no individual
+/// mode and the differential bit tells opaque blocks from ones with transparent texels.
+fn decode_color(block: &[u8], texels: &mut [[u8; 4]], punchthrough: bool) {
+  let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+  let byte = |index: usize| block[index] as i32;
+  let differential = (bits >> 33) & 1 == 1;
+  let opaque = differential || !punchthrough;
+  let indices: [usize; 16] = std::array::from_fn(|texel| {
+    let texel = column_major(texel);
+    ((((bits >> (texel + 16)) & 1) << 1) | ((bits >> texel) & 1)) as usize
+  });
+
+  let (base_0, base_1) = if differential || punchthrough {
+    let base = [byte(0) >> 3, byte(1) >> 3, byte(2) >> 3];
+    let delta = [byte(0) & 7, byte(1) & 7, byte(2) & 7].map(|delta| (delta << 29) >> 29);
+    let other = [base[0] + delta[0], base[1] + delta[1], base[2] + delta[2]];
+    // Overflowing channels select the modes ETC2 added
+    if !(0..32).contains(&other[0]) {
+      return decode_t_or_h(block, texels, &indices, opaque, false);
+    }
+    if !(0..32).contains(&other[1]) {
+      return decode_t_or_h(block, texels, &indices, opaque, true);
+    }
+    if !(0..32).contains(&other[2]) {
+      return decode_planar(bits, texels);
+    }
+    (base.map(|value| extend(value, 5)), other.map(|value| extend(value, 5)))
+  } else {
+    let base = [byte(0) >> 4, byte(1) >> 4, byte(2) >> 4];
+    let other = [byte(0) & 15, byte(1) & 15, byte(2) & 15];
+    (base.map(|value| extend(value, 4)), other.map(|value| extend(value, 4)))
+  };
+
+  let tables = [(byte(3) >> 5) as usize, ((byte(3) >> 2) & 7) as usize];
+  let flip = byte(3) & 1 == 1;
+  for (texel, output) in texels.iter_mut().enumerate() {
+    let (x, y) = (texel % 4, texel / 4);
+    // Two 2x4 subblocks side by side, or two 4x2 ones on top of each other when flipped
+    let second = if flip { y >= 2 } else { x >= 2 };
+    let (base, table) = if second { (base_1, tables[1]) } else { (base_0, tables[0]) };
+    let index = indices[texel];
+    if !opaque && index == 2 {
+      *output = [0; 4];
+      continue;
+    }
+    let modifier = match index {
+      0 if !opaque => 0,
+      _ => MODIFIERS[table][index & 1] * if index & 2 == 0 { 1 } else { -1 },
+    };
+    *output = color(base.map(|value| value + modifier));
+  }
+}
+
+/// The T mode paints with a color and three around another, the H mode with two around each
+fn decode_t_or_h(block: &[u8], texels: &mut [[u8; 4]], indices: &[usize; 16], opaque: bool, h: bool) {
+  let byte = |index: usize| block[index] as i32;
+  let paints = if h {
+    let first = [
+      (byte(0) >> 3) & 15,
+      ((byte(0) & 7) << 1) | ((byte(1) >> 4) & 1),
+      (byte(1) & 8) | ((byte(1) & 3) << 1) | (byte(2) >> 7),
+    ];
+    let second = [(byte(2) >> 3) & 15, ((byte(2) & 7) << 1) | (byte(3) >> 7), (byte(3) >> 3) & 15];
+    // The last bit of the distance is whether the first color is the bigger one
+    let order = (first[0] << 8 | first[1] << 4 | first[2]) >= (second[0] << 8 | second[1] << 4 | second[2]);
+    let distance = DISTANCES[((byte(3) & 4) | ((byte(3) & 1) << 1) | order as i32) as usize];
+    let (first, second) = (first.map(|value| extend(value, 4)), second.map(|value| extend(value, 4)));
+    [
+      first.map(|value| value + distance),
+      first.map(|value| value - distance),
+      second.map(|value| value + distance),
+      second.map(|value| value - distance),
+    ]
+  } else {
+    let first = [(((byte(0) >> 3) & 3) << 2) | (byte(0) & 3), byte(1) >> 4, byte(1) & 15];
+    let second = [byte(2) >> 4, byte(2) & 15, byte(3) >> 4];
+    let distance = DISTANCES[((((byte(3) >> 2) & 3) << 1) | (byte(3) & 1)) as usize];
+    let (first, second) = (first.map(|value| extend(value, 4)), second.map(|value| extend(value, 4)));
+    [first, second.map(|value| value + distance), second, second.map(|value| value - distance)]
+  };
+
+  for (output, &index) in texels.iter_mut().zip(indices) {
+    *output = match index {
+      2 if !opaque => [0; 4],
+      _ => color(paints[index]),
+    };
+  }
+}
+
+/// Colors interpolated between the colors at the origin, the right and the bottom of the
+/// block. Planar blocks are always opaque.
+fn decode_planar(bits: u64, texels: &mut [[u8; 4]]) {
+  let field = |shift: u32, count: u32| ((bits >> shift) & ((1 << count) - 1)) as i32;
+  let origin = [
+    extend(field(57, 6), 6),
+    extend(field(56, 1) << 6 | field(49, 6), 7),
+    extend(field(48, 1) << 5 | field(43, 2) << 3 | field(39, 3), 6),
+  ];
+  let horizontal = [extend(field(34, 5) << 1 | field(32, 1), 6), extend(field(25, 7), 7), extend(field(19, 6), 6)];
+  let vertical = [extend(field(13, 6), 6), extend(field(6, 7), 7), extend(field(0, 6), 6)];
+
+  for (texel, output) in texels.iter_mut().enumerate() {
+    let (x, y) = ((texel % 4) as i32, (texel / 4) as i32);
+    *output = color(std::array::from_fn(|channel| {
+      let o = origin[channel];
+      (x * (horizontal[channel] - o) + y * (vertical[channel] - o) + 4 * o + 2) >> 2
+    }));
+  }
+}
+
+/// Extends a `bits` bit value to 8 bits by repeating its top bits
+fn extend(value: i32, bits: u32) -> i32 {
+  (value << (8 - bits)) | (value >> (2 * bits - 8))
+}
+
+fn color(rgb: [i32; 3]) -> [u8; 4] {
+  let [r, g, b] = rgb.map(|value| value.clamp(0, 255) as u8);
+  [r, g, b, 255]
+}
+
+/// Values of an EAC block, the base value, modifier and multiplier of each texel are
+/// combined by `value`
+fn decode_channel(block: &[u8], value: impl Fn(i32, i32, i32) -> i32) -> [i32; 16] {
+  let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+  let base = block[0];
+  let multiplier = (block[1] >> 4) as i32;
+  let modifiers = EAC_MODIFIERS[(block[1] & 15) as usize];
+  std::array::from_fn(|texel| {
+    let index = (bits >> (45 - 3 * column_major(texel))) & 7;
+    value(base as i32, modifiers[index as usize], multiplier)
+  })
+}
+
+/// 11 bit EAC channel, from 0 to 1 or from -1 to 1 when signed
+fn decode_r11(block: &[u8], signed: bool) -> [f16; 16] {
+  // A zero multiplier steps by one 11 bit unit instead of eight
+  let step = |multiplier: i32| if multiplier == 0 { 1 } else { multiplier * 8 };
+  let values = match signed {
+    true => decode_channel(block, |base, modifier, multiplier| {
+      // -128 stands for -127
+      let base = (base as u8 as i8).max(-127) as i32 * 8;
+      (base + modifier * step(multiplier)).clamp(-1023, 1023)
+    }),
+    false => decode_channel(block, |base, modifier, multiplier| {
+      (base * 8 + 4 + modifier * step(multiplier)).clamp(0, 2047)
+    }),
+  };
+  let scale = if signed { 1023.0 } else { 2047.0 };
+  values.map(|value| f16::from_f32(value as f32 / scale))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode(decoder: fn(&[u8], &mut [[u8; 4]]), block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = [[0; 4]; 16];
+    decoder(block, &mut texels);
+    texels
+  }
+
+  fn decode_float(decoder: fn(&[u8], &mut [[f16; 4]]), block: &[u8]) -> [[f16; 4]; 16] {
+    let mut texels = [[f16::ZERO; 4]; 16];
+    decoder(block, &mut texels);
+    texels
+  }
+
+  #[test]
+  fn individual_mode_has_two_4_bit_colors_side_by_side() {
+    // Every texel adds the small modifier of table 0, 2
+    let texels = decode(decode_etc2_rgb, &[0xF0, 0x80, 0x00, 0x00, 0, 0, 0, 0]);
+    assert_eq!(texels[0], [255, 138, 2, 255]);
+    assert_eq!(texels[1], [255, 138, 2, 255]);
+    assert_eq!(texels[2], [2, 2, 2, 255]);
+    assert_eq!(texels[15], [2, 2, 2, 255]);
+  }
+
+  #[test]
+  fn differential_mode_flips_to_subblocks_on_top_of_each_other() {
+    // Red 16 + 3 and green 16 - 4 in 5 bits
+    let texels = decode(decode_etc2_rgb, &[0x83, 0x84, 0x00, 0x03, 0, 0, 0, 0]);
+    assert_eq!(texels[3], [134, 134, 2, 255]);
+    assert_eq!(texels[8], [158, 101, 2, 255]);
+  }
+
+  #[test]
+  fn punchthrough_blocks_without_the_differential_bit_have_transparent_texels() {
+    // Every texel uses index 2
+    let block = [0, 0, 0, 0, 0xFF, 0xFF, 0, 0];
+    assert_eq!(decode(decode_etc2_rgb_a1, &block), [[0; 4]; 16]);
+    assert_eq!(decode(decode_etc2_rgb, &block)[0], [0, 0, 0, 255]);
+  }
+
+  #[test]
+  fn rgba8_alpha_comes_first() {
+    let alpha = [200, 0, 0, 0, 0, 0, 0, 0];
+    let texels = decode(decode_etc2_rgba, &[alpha, [0xF0, 0x80, 0x00, 0x00, 0, 0, 0, 0]].concat());
+    assert_eq!(texels[0], [255, 138, 2, 200]);
+  }
+
+  #[test]
+  fn eac_clamps_to_the_11_bit_range() {
+    // Biggest multiplier and modifier on 255, then the smallest modifier on 0
+    let one = [255, 0xF0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    let zero = [0, 0x10, 0x6D, 0xB6, 0xDB, 0x6D, 0xB6, 0xDB];
+    assert_eq!(decode_float(decode_eac_r11, &one), [[f16::ONE, f16::ZERO, f16::ZERO, f16::ONE]; 16]);
+    assert_eq!(decode_float(decode_eac_rg11, &[one, zero].concat())[5], [f16::ONE, f16::ZERO, f16::ZERO, f16::ONE]);
+  }
+
+  #[test]
+  fn eac_snorm_clamps_minus_128() {
+    let minus_one = [0x80, 0x10, 0x6D, 0xB6, 0xDB, 0x6D, 0xB6, 0xDB];
+    assert_eq!(decode_float(decode_eac_r11_snorm, &minus_one)[0], [f16::NEG_ONE, f16::ZERO, f16::ZERO, f16::ONE]);
+    let texels = decode_float(decode_eac_rg11_snorm, &[[127, 0xF0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], minus_one].concat());
+    assert_eq!(texels[9], [f16::ONE, f16::NEG_ONE, f16::ZERO, f16::ONE]);
+  }
+
+  #[test]
+  fn eac_zero_multiplier_steps_by_one() {
+    // 128 * 8 + 4, plus the modifier 2 of index 4
+    let texels = decode_float(decode_eac_r11, &[128, 0x00, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24]);
+    assert_eq!(texels[0][0], f16::from_f32(1030.0 / 2047.0));
+  }
+}
@@ -0,0 +1,139 @@
+//! CPU decoding of block compressed formats, for devices that can't sample them
+pub(crate) mod astc;
+mod bc;
+pub(crate) mod etc;
+
+use half::f16;
+
+/// Texels of a decoded image, in the precision of their format
+enum Texels {
+  Rgba8(Vec<[u8; 4]>),
+  Float(Vec<[f16; 4]>),
+}
+
+/// Decodes a `width` x `height` image of the BC, ETC2, EAC or LDR ASTC format `format`.
+///
+/// Returns its texels along with the uncompressed format they are in, or `None` for
+/// formats it can't decode and data too short for the image. Color formats decode to
+/// RGBA8, keeping sRGB ones sRGB and signed ones signed, BC6H and EAC to `Rgba16Float`
+/// to keep their range and precision.
+pub fn decode(
+  format: wgpu::TextureFormat,
+  data: &[u8],
+  width: u32,
+  height: u32,
+) -> Option<(wgpu::TextureFormat, Vec<u8>)> {
+  use wgpu::{AstcChannel, TextureFormat as F};
+
+  let block = format.block_dimensions();
+  let block_size = format.block_copy_size(None)? as usize;
+  let rgba8 = |decode: fn(&[u8], &mut [[u8; 4]])| {
+    decode_blocks(data, width, height, block, block_size, decode).map(Texels::Rgba8)
+  };
+  let float = |decode: fn(&[u8], &mut [[f16; 4]])| {
+    decode_blocks(data, width, height, block, block_size, decode).map(Texels::Float)
+  };
+
+  let (texels, output) = match format {
+    F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => (rgba8(bc::decode_bc1)?, F::Rgba8Unorm),
+    F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => (rgba8(bc::decode_bc2)?, F::Rgba8Unorm),
+    F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => (rgba8(bc::decode_bc3)?, F::Rgba8Unorm),
+    F::Bc4RUnorm => (rgba8(bc::decode_bc4)?, F::Rgba8Unorm),
+    F::Bc4RSnorm => (rgba8(bc::decode_bc4_snorm)?, F::Rgba8Snorm),
+    F::Bc5RgUnorm => (rgba8(bc::decode_bc5)?, F::Rgba8Unorm),
+    F::Bc5RgSnorm => (rgba8(bc::decode_bc5_snorm)?, F::Rgba8Snorm),
+    F::Bc6hRgbUfloat => (float(bc::decode_bc6h_unsigned)?, F::Rgba16Float),
+    F::Bc6hRgbFloat => (float(bc::decode_bc6h_signed)?, F::Rgba16Float),
+    F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => (rgba8(bc::decode_bc7)?, F::Rgba8Unorm),
+    F::Etc2Rgb8Unorm | F::Etc2Rgb8UnormSrgb => (rgba8(etc::decode_etc2_rgb)?, F::Rgba8Unorm),
+    F::Etc2Rgb8A1Unorm | F::Etc2Rgb8A1UnormSrgb => (rgba8(etc::decode_etc2_rgb_a1)?, F::Rgba8Unorm),
+    F::Etc2Rgba8Unorm | F::Etc2Rgba8UnormSrgb => (rgba8(etc::decode_etc2_rgba)?, F::Rgba8Unorm),
+    F::EacR11Unorm => (float(etc::decode_eac_r11)?, F::Rgba16Float),
+    F::EacR11Snorm => (float(etc::decode_eac_r11_snorm)?, F::Rgba16Float),
+    F::EacRg11Unorm => (float(etc::decode_eac_rg11)?, F::Rgba16Float),
+    F::EacRg11Snorm => (float(etc::decode_eac_rg11_snorm)?, F::Rgba16Float),
+    F::Astc {
+      channel: AstcChannel::Unorm | AstcChannel::UnormSrgb,
+      ..
+    } => {
+      let texels = decode_blocks(data, width, height, block, block_size, |data, texels| {
+        astc::decode(data, block, format.is_srgb(), texels)
+      })?;
+      (Texels::Rgba8(texels), F::Rgba8Unorm)
+    }
+    _ => return None,
+  };
+
+  let output = match format.is_srgb() {
+    true => output.add_srgb_suffix(),
+    false => output,
+  };
+  let pixels = match texels {
+    Texels::Rgba8(texels) => texels.into_flattened(),
+    Texels::Float(texels) => texels.into_iter().flatten().flat_map(f16::to_le_bytes).collect(),
+  };
+  Some((output, pixels))
+}
+
+/// Runs `decode` on every `block_size` byte block of `block` texels, which fills the
+/// texels of the block row by row. Texels past the right and bottom edges are dropped.
+fn decode_blocks<T: Copy + Default>(
+  data: &[u8],
+  width: u32,
+  height: u32,
+  block: (u32, u32),
+  block_size: usize,
+  decode: impl Fn(&[u8], &mut [[T; 4]]),
+) -> Option<Vec<[T; 4]>> {
+  let (width, height) = (width as usize, height as usize);
+  let (block_width, block_height) = (block.0 as usize, block.1 as usize);
+  let blocks_x = width.div_ceil(block_width);
+  let blocks_y = height.div_ceil(block_height);
+  if data.len() < blocks_x * blocks_y * block_size {
+    return None;
+  }
+
+  let mut pixels = vec![[T::default(); 4]; width * height];
+  let mut texels = vec![[T::default(); 4]; block_width * block_height];
+  for (index, block) in data.chunks_exact(block_size).take(blocks_x * blocks_y).enumerate() {
+    decode(block, &mut texels);
+
+    let (left, top) = (index % blocks_x * block_width, index / blocks_x * block_height);
+    for (row, texels) in texels.chunks_exact(block_width).enumerate().take(height - top) {
+      let start = (top + row) * width + left;
+      let count = block_width.min(width - left);
+      pixels[start..start + count].copy_from_slice(&texels[..count]);
+    }
+  }
+  Some(pixels)
+}
+
+/// Reads the bits of a 128 bit little endian block from the lowest up. Reading past
+/// the end gives zeros.
+struct BitReader {
+  bits: u128,
+  position: u32,
+}
+
+impl BitReader {
+  fn new(block: &[u8]) -> Self {
+    let mut bytes = [0; 16];
+    bytes[..block.len().min(16)].copy_from_slice(&block[..block.len().min(16)]);
+    Self {
+      bits: u128::from_le_bytes(bytes),
+      position: 0,
+    }
+  }
+
+  fn read(&mut self, count: u32) -> u32 {
+    let value = bits(self.bits, self.position, count);
+    self.position += count;
+    value
+  }
+}
+
+/// `count` bits of `value` from `start` up, at most 32
+fn bits(value: u128, start: u32, count: u32) -> u32 {
+  let mask = (1u64 << count) - 1;
+  (value.checked_shr(start).unwrap_or(0) as u64 & mask) as u32
+}
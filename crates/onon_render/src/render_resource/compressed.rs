@@ -0,0 +1,466 @@
+use crate::render_resource::{basis, block_decode};
+use anyhow::{Context, Result, anyhow};
+use std::io::Read;
+
+/// Returns true for the containers loaded through `CompressedImage` instead of `image`
+pub fn is_compressed_file(path: &std::path::Path) -> bool {
+  let extension = path.extension().and_then(|extension| extension.to_str());
+  matches!(extension.map(str::to_ascii_lowercase).as_deref(), Some("ktx2" | "dds"))
+}
+
+const KTX2_MAGIC: &[u8] = b"\xABKTX 20\xBB\r\n\x1A\n";
+const DDS_MAGIC: &[u8] = b"DDS ";
+
+/// Returns true for KTX2 and DDS data, told apart from other images by their magic bytes
+pub fn is_compressed_data(bytes: &[u8]) -> bool {
+  bytes.starts_with(KTX2_MAGIC) || bytes.starts_with(DDS_MAGIC)
+}
+
+/// A 2D image kept in its GPU format, with its mip levels as stored in the file
+#[derive(Clone)]
+pub struct CompressedImage {
+  pub format: wgpu::TextureFormat,
+  pub size: glam::UVec2,
+  /// Level 0 first, each tightly packed rows of blocks
+  pub levels: Vec<Vec<u8>>,
+  /// Transcoded from Basis Universal, so BC7 can stand in for the format on devices without it
+  pub transcoded: bool,
+}
+
+impl CompressedImage {
+  /// Parses a KTX2 or DDS file, telling them apart by `path`'s extension
+  pub fn from_bytes(bytes: &[u8], path: &std::path::Path) -> Result<Self> {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+      "ktx2" => Self::from_ktx2(bytes),
+      "dds" => Self::from_dds(bytes),
+      _ => Err(anyhow!("{:?} isn't a KTX2 or DDS file", path)),
+    }
+  }
+
+  /// Parses KTX2 or DDS data, telling them apart by their magic bytes
+  pub fn from_memory(bytes: &[u8]) -> Result<Self> {
+    if bytes.starts_with(KTX2_MAGIC) {
+      Self::from_ktx2(bytes)
+    } else if bytes.starts_with(DDS_MAGIC) {
+      Self::from_dds(bytes)
+    } else {
+      Err(anyhow!("Data isn't a KTX2 or DDS file"))
+    }
+  }
+
+  /// Levels supercompressed with Zstandard are inflated, ZLIB supercompression is rejected.
+  ///
+  /// Basis Universal files are transcoded, UASTC to ASTC 4x4 and ETC1S to ETC2, see
+  /// [`Self::for_device`] for devices without those formats.
+  pub fn from_ktx2(bytes: &[u8]) -> Result<Self> {
+    let reader = ktx2::Reader::new(bytes).map_err(|error| anyhow!("Invalid KTX2 file: {error}"))?;
+    let header = reader.header();
+
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+      return Err(anyhow!("Only single 2D images are supported in KTX2 files"));
+    }
+    let transcoded = basis::is_basis_universal(&reader);
+    let levels = reader
+      .levels()
+      .map(|level| match header.supercompression_scheme {
+        None => Ok(level.data.to_vec()),
+        Some(ktx2::SupercompressionScheme::Zstandard) => {
+          let mut data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+          ruzstd::decoding::StreamingDecoder::new(level.data)
+            .map_err(|error| anyhow!("Invalid Zstandard level: {error}"))?
+            .read_to_end(&mut data)?;
+          Ok(data)
+        }
+        // The levels of BasisLZ files are only inflated by transcoding them
+        Some(ktx2::SupercompressionScheme::BasisLZ) if transcoded => Ok(level.data.to_vec()),
+        Some(scheme) => Err(anyhow!("Unsupported KTX2 supercompression {:?}", scheme)),
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    let (format, levels) = match transcoded {
+      true => basis::transcode(&reader, levels)?,
+      false => (header.format.context("KTX2 file without a format").and_then(ktx2_format)?, levels),
+    };
+    Ok(Self {
+      format,
+      size: glam::uvec2(header.pixel_width, header.pixel_height.max(1)),
+      levels,
+      transcoded,
+    })
+  }
+
+  pub fn from_dds(bytes: &[u8]) -> Result<Self> {
+    let dds = ddsfile::Dds::read(bytes)?;
+    if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+      return Err(anyhow!("Only single 2D images are supported in DDS files"));
+    }
+
+    let format = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+      (Some(format), _) => dxgi_format(format)?,
+      (None, Some(format)) => d3d_format(format)?,
+      (None, None) => return Err(anyhow!("DDS file without a known format")),
+    };
+
+    let size = glam::uvec2(dds.get_width(), dds.get_height());
+    let mut data = dds.get_data(0)?;
+    let mut levels = Vec::new();
+    for level in 0..dds.get_num_mipmap_levels().max(1) {
+      let length = level_byte_size(format, mip_size(size, level)) as usize;
+      if data.len() < length {
+        break;
+      }
+      let (level_data, rest) = data.split_at(length);
+      levels.push(level_data.to_vec());
+      data = rest;
+    }
+
+    Ok(Self {
+      format,
+      size,
+      levels,
+      transcoded: false,
+    })
+  }
+
+  pub fn is_compressed(&self) -> bool {
+    self.format.is_compressed()
+  }
+
+  /// True when the levels go all the way down to 1x1
+  pub fn has_full_mip_chain(&self) -> bool {
+    let size = wgpu::Extent3d {
+      width: self.size.x,
+      height: self.size.y,
+      depth_or_array_layers: 1,
+    };
+    self.levels.len() as u32 >= size.max_mips(wgpu::TextureDimension::D2)
+  }
+
+  /// Keeps the image as is when the device can sample its format, otherwise decompresses it
+  /// on the CPU. Transcoded Basis Universal images are encoded to BC7 when the device samples BCn.
+  pub fn for_device(self, device: &wgpu::Device) -> Result<Self> {
+    let features = device.features();
+    if features.contains(self.format.required_features()) {
+      Ok(self)
+    } else if self.transcoded && features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+      self.decompress()?.into_bc7()
+    } else {
+      self.decompress()
+    }
+  }
+
+  /// Encodes every level of an RGBA8 image to BC7
+  fn into_bc7(self) -> Result<Self> {
+    let format = match self.format {
+      wgpu::TextureFormat::Rgba8Unorm => wgpu::TextureFormat::Bc7RgbaUnorm,
+      wgpu::TextureFormat::Rgba8UnormSrgb => wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+      format => return Err(anyhow!("{:?} images can't be encoded to BC7", format)),
+    };
+    let levels = self
+      .levels
+      .iter()
+      .enumerate()
+      .map(|(level, data)| {
+        let size = mip_size(self.size, level as u32);
+        basis::encode_bc7(data, size.x, size.y)
+      })
+      .collect();
+    Ok(Self { format, levels, ..self })
+  }
+
+  /// Decodes every level to RGBA8, or to `Rgba16Float` for BC6H and EAC, see
+  /// [`block_decode::decode`]. HDR ASTC can't be decoded.
+  pub fn decompress(self) -> Result<Self> {
+    if !self.is_compressed() {
+      return Ok(self);
+    }
+
+    let mut output = None;
+    let levels = self
+      .levels
+      .iter()
+      .enumerate()
+      .map(|(level, data)| {
+        let size = mip_size(self.size, level as u32);
+        let (format, pixels) = block_decode::decode(self.format, data, size.x, size.y)
+          .context(format!("{:?} textures can't be decoded on the CPU", self.format))?;
+        output = Some(format);
+        Ok(pixels)
+      })
+      .collect::<Result<Vec<_>>>()?;
+
+    Ok(Self {
+      format: output.context("Image without any level")?,
+      levels,
+      ..self
+    })
+  }
+
+  /// First level of an uncompressed image, e.g. a decompressed one. Signed texels become
+  /// floats from -1 to 1.
+  pub fn to_image(&self) -> Result<image::DynamicImage> {
+    use wgpu::TextureFormat as F;
+
+    let (width, height) = (self.size.x, self.size.y);
+    let level = self.levels.first().context("Image without any level")?.clone();
+    let image = match self.format {
+      F::Rgba8Unorm | F::Rgba8UnormSrgb => image::RgbaImage::from_raw(width, height, level).map(Into::into),
+      F::Rgba8Snorm => {
+        let texels = level.iter().map(|&value| (value as i8).max(-127) as f32 / 127.0).collect();
+        image::Rgba32FImage::from_raw(width, height, texels).map(Into::into)
+      }
+      F::Rgba16Float => {
+        let texels = level
+          .chunks_exact(2)
+          .map(|bytes| half::f16::from_le_bytes([bytes[0], bytes[1]]).to_f32())
+          .collect();
+        image::Rgba32FImage::from_raw(width, height, texels).map(Into::into)
+      }
+      format => return Err(anyhow!("{:?} images can't be converted", format)),
+    };
+    image.context("Image level is too small")
+  }
+}
+
+/// Size of mip `level` of an image of `size`
+pub fn mip_size(size: glam::UVec2, level: u32) -> glam::UVec2 {
+  (size >> level).max(glam::UVec2::ONE)
+}
+
+/// Bytes taken by an image of `size`, rounded up to whole blocks for compressed formats
+pub fn level_byte_size(format: wgpu::TextureFormat, size: glam::UVec2) -> u64 {
+  let (block_width, block_height) = format.block_dimensions();
+  let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+  size.x.div_ceil(block_width) as u64 * size.y.div_ceil(block_height) as u64 * block_size
+}
+
+fn ktx2_format(format: ktx2::Format) -> Result<wgpu::TextureFormat> {
+  use ktx2::Format as K;
+  use wgpu::{AstcBlock, AstcChannel, TextureFormat as F};
+
+  let astc = |block, srgb| F::Astc {
+    block,
+    channel: if srgb { AstcChannel::UnormSrgb } else { AstcChannel::Unorm },
+  };
+
+  Ok(match format {
+    K::R8G8B8A8_UNORM => F::Rgba8Unorm,
+    K::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+    K::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+    K::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+    K::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+    K::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+    K::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+    K::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+    K::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+    K::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+    K::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+    K::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+    K::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+    K::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+    K::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+    K::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+    K::ETC2_R8G8B8_UNORM_BLOCK => F::Etc2Rgb8Unorm,
+    K::ETC2_R8G8B8_SRGB_BLOCK => F::Etc2Rgb8UnormSrgb,
+    K::ETC2_R8G8B8A1_UNORM_BLOCK => F::Etc2Rgb8A1Unorm,
+    K::ETC2_R8G8B8A1_SRGB_BLOCK => F::Etc2Rgb8A1UnormSrgb,
+    K::ETC2_R8G8B8A8_UNORM_BLOCK => F::Etc2Rgba8Unorm,
+    K::ETC2_R8G8B8A8_SRGB_BLOCK => F::Etc2Rgba8UnormSrgb,
+    K::EAC_R11_UNORM_BLOCK => F::EacR11Unorm,
+    K::EAC_R11_SNORM_BLOCK => F::EacR11Snorm,
+    K::EAC_R11G11_UNORM_BLOCK => F::EacRg11Unorm,
+    K::EAC_R11G11_SNORM_BLOCK => F::EacRg11Snorm,
+    K::ASTC_4x4_UNORM_BLOCK => astc(AstcBlock::B4x4, false),
+    K::ASTC_4x4_SRGB_BLOCK => astc(AstcBlock::B4x4, true),
+    K::ASTC_5x4_UNORM_BLOCK => astc(AstcBlock::B5x4, false),
+    K::ASTC_5x4_SRGB_BLOCK => astc(AstcBlock::B5x4, true),
+    K::ASTC_5x5_UNORM_BLOCK => astc(AstcBlock::B5x5, false),
+    K::ASTC_5x5_SRGB_BLOCK => astc(AstcBlock::B5x5, true),
+    K::ASTC_6x5_UNORM_BLOCK => astc(AstcBlock::B6x5, false),
+    K::ASTC_6x5_SRGB_BLOCK => astc(AstcBlock::B6x5, true),
+    K::ASTC_6x6_UNORM_BLOCK => astc(AstcBlock::B6x6, false),
+    K::ASTC_6x6_SRGB_BLOCK => astc(AstcBlock::B6x6, true),
+    K::ASTC_8x5_UNORM_BLOCK => astc(AstcBlock::B8x5, false),
+    K::ASTC_8x5_SRGB_BLOCK => astc(AstcBlock::B8x5, true),
+    K::ASTC_8x6_UNORM_BLOCK => astc(AstcBlock::B8x6, false),
+    K::ASTC_8x6_SRGB_BLOCK => astc(AstcBlock::B8x6, true),
+    K::ASTC_8x8_UNORM_BLOCK => astc(AstcBlock::B8x8, false),
+    K::ASTC_8x8_SRGB_BLOCK => astc(AstcBlock::B8x8, true),
+    K::ASTC_10x5_UNORM_BLOCK => astc(AstcBlock::B10x5, false),
+    K::ASTC_10x5_SRGB_BLOCK => astc(AstcBlock::B10x5, true),
+    K::ASTC_10x6_UNORM_BLOCK => astc(AstcBlock::B10x6, false),
+    K::ASTC_10x6_SRGB_BLOCK => astc(AstcBlock::B10x6, true),
+    K::ASTC_10x8_UNORM_BLOCK => astc(AstcBlock::B10x8, false),
+    K::ASTC_10x8_SRGB_BLOCK => astc(AstcBlock::B10x8, true),
+    K::ASTC_10x10_UNORM_BLOCK => astc(AstcBlock::B10x10, false),
+    K::ASTC_10x10_SRGB_BLOCK => astc(AstcBlock::B10x10, true),
+    K::ASTC_12x10_UNORM_BLOCK => astc(AstcBlock::B12x10, false),
+    K::ASTC_12x10_SRGB_BLOCK => astc(AstcBlock::B12x10, true),
+    K::ASTC_12x12_UNORM_BLOCK => astc(AstcBlock::B12x12, false),
+    K::ASTC_12x12_SRGB_BLOCK => astc(AstcBlock::B12x12, true),
+    _ => return Err(anyhow!("Unsupported KTX2 format {:?}", format)),
+  })
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Result<wgpu::TextureFormat> {
+  use ddsfile::DxgiFormat as D;
+  use wgpu::TextureFormat as F;
+
+  Ok(match format {
+    D::R8G8B8A8_UNorm => F::Rgba8Unorm,
+    D::R8G8B8A8_UNorm_sRGB => F::Rgba8UnormSrgb,
+    D::BC1_UNorm => F::Bc1RgbaUnorm,
+    D::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+    D::BC2_UNorm => F::Bc2RgbaUnorm,
+    D::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+    D::BC3_UNorm => F::Bc3RgbaUnorm,
+    D::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+    D::BC4_UNorm => F::Bc4RUnorm,
+    D::BC4_SNorm => F::Bc4RSnorm,
+    D::BC5_UNorm => F::Bc5RgUnorm,
+    D::BC5_SNorm => F::Bc5RgSnorm,
+    D::BC6H_UF16 => F::Bc6hRgbUfloat,
+    D::BC6H_SF16 => F::Bc6hRgbFloat,
+    D::BC7_UNorm => F::Bc7RgbaUnorm,
+    D::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+    _ => return Err(anyhow!("Unsupported DDS format {:?}", format)),
+  })
+}
+
+fn d3d_format(format: ddsfile::D3DFormat) -> Result<wgpu::TextureFormat> {
+  use ddsfile::D3DFormat as D;
+  use wgpu::TextureFormat as F;
+
+  Ok(match format {
+    D::A8B8G8R8 => F::Rgba8Unorm,
+    D::DXT1 => F::Bc1RgbaUnorm,
+    D::DXT2 | D::DXT3 => F::Bc2RgbaUnorm,
+    D::DXT4 | D::DXT5 => F::Bc3RgbaUnorm,
+    _ => return Err(anyhow!("Unsupported DDS format {:?}", format)),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A 4x4 KTX2 file of one 16 byte block, with a basic data format descriptor of `color_model`
+  fn ktx2_file(
+    format: Option<ktx2::Format>,
+    scheme: Option<ktx2::SupercompressionScheme>,
+    color_model: ktx2::ColorModel,
+  ) -> Vec<u8> {
+    let dfd_offset = ktx2::Header::LENGTH + 24;
+    let dfd_length = 4 + ktx2::DfdHeader::LENGTH + ktx2::DfdBlockHeaderBasic::LENGTH;
+    let header = ktx2::Header {
+      format,
+      type_size: 1,
+      pixel_width: 4,
+      pixel_height: 4,
+      pixel_depth: 0,
+      layer_count: 0,
+      face_count: 1,
+      level_count: 1,
+      supercompression_scheme: scheme,
+      index: ktx2::Index {
+        dfd_byte_offset: dfd_offset as u32,
+        dfd_byte_length: dfd_length as u32,
+        kvd_byte_offset: 0,
+        kvd_byte_length: 0,
+        sgd_byte_offset: 0,
+        sgd_byte_length: 0,
+      },
+    };
+    let block_dimension = std::num::NonZeroU8::new(4).unwrap();
+    let basic = ktx2::DfdBlockHeaderBasic {
+      color_model: Some(color_model),
+      color_primaries: None,
+      transfer_function: None,
+      flags: ktx2::DataFormatFlags::empty(),
+      texel_block_dimensions: [block_dimension, block_dimension, std::num::NonZeroU8::MIN, std::num::NonZeroU8::MIN],
+      bytes_planes: [16, 0, 0, 0, 0, 0, 0, 0],
+    };
+
+    let mut bytes = header.as_bytes().to_vec();
+    for value in [(dfd_offset + dfd_length) as u64, 16, 16] {
+      bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend((dfd_length as u32).to_le_bytes());
+    bytes.extend(ktx2::DfdHeader::BASIC.as_bytes((dfd_length - 4) as u16));
+    bytes.extend(basic.as_bytes());
+    bytes.extend([0; 16]);
+    bytes
+  }
+
+  #[test]
+  fn ktx2_blocks_are_read() {
+    let file = ktx2_file(Some(ktx2::Format::BC7_UNORM_BLOCK), None, ktx2::ColorModel::BC7);
+    let image = CompressedImage::from_memory(&file).unwrap();
+    assert_eq!(image.format, wgpu::TextureFormat::Bc7RgbaUnorm);
+    assert_eq!(image.size, glam::uvec2(4, 4));
+    assert_eq!(image.levels, [vec![0; 16]]);
+  }
+
+  /// Texels at the corners of the quadrants of an 8x8 image, red, half transparent green,
+  /// blue and mostly transparent white in the Basis Universal test files
+  fn quadrants(image: &CompressedImage) -> [[u8; 4]; 4] {
+    let level = &image.levels[0];
+    [(0, 0), (4, 0), (0, 4), (4, 4)].map(|(x, y)| level[(y * 8 + x) * 4..][..4].try_into().unwrap())
+  }
+
+  fn assert_quadrants(image: &CompressedImage, tolerance: i32) {
+    let expected = [[255, 0, 0, 255], [0, 255, 0, 128], [0, 0, 255, 255], [255, 255, 255, 64]];
+    for (texel, expected) in quadrants(image).into_iter().zip(expected) {
+      let close = texel.iter().zip(expected).all(|(&value, expected)| (value as i32 - expected).abs() <= tolerance);
+      assert!(close, "{texel:?} isn't {expected:?}");
+    }
+  }
+
+  #[test]
+  fn uastc_files_are_transcoded_to_astc() {
+    let image = CompressedImage::from_memory(include_bytes!("../../test_data/uastc_quad.ktx2")).unwrap();
+    assert!(image.transcoded);
+    let format = wgpu::TextureFormat::Astc {
+      block: wgpu::AstcBlock::B4x4,
+      channel: wgpu::AstcChannel::UnormSrgb,
+    };
+    assert_eq!(image.format, format);
+    assert_eq!(image.levels[0].len(), 4 * 16);
+    assert_quadrants(&image.decompress().unwrap(), 0);
+  }
+
+  #[test]
+  fn etc1s_files_are_transcoded_to_etc2() {
+    let image = CompressedImage::from_memory(include_bytes!("../../test_data/etc1s_quad.ktx2")).unwrap();
+    assert!(image.transcoded);
+    assert_eq!(image.format, wgpu::TextureFormat::Etc2Rgba8UnormSrgb);
+    assert_eq!(image.levels[0].len(), 4 * 16);
+    assert_quadrants(&image.decompress().unwrap(), 2);
+  }
+
+  #[test]
+  fn transcoded_images_encode_to_bc7() {
+    let image = CompressedImage::from_memory(include_bytes!("../../test_data/uastc_quad.ktx2")).unwrap();
+    let encoded = image.decompress().unwrap().into_bc7().unwrap();
+    assert_eq!(encoded.format, wgpu::TextureFormat::Bc7RgbaUnormSrgb);
+    assert_quadrants(&encoded.decompress().unwrap(), 1);
+  }
+
+  #[test]
+  fn decompressing_keeps_srgb_and_float_formats() {
+    let image = |format| CompressedImage {
+      format,
+      size: glam::uvec2(2, 2),
+      levels: vec![vec![0; 16]],
+      transcoded: false,
+    };
+    let decompressed = image(wgpu::TextureFormat::Bc7RgbaUnormSrgb).decompress().unwrap();
+    assert_eq!(decompressed.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+    assert_eq!(decompressed.levels[0].len(), 2 * 2 * 4);
+
+    let decompressed = image(wgpu::TextureFormat::Bc6hRgbUfloat).decompress().unwrap();
+    assert_eq!(decompressed.format, wgpu::TextureFormat::Rgba16Float);
+    assert!(matches!(decompressed.to_image().unwrap(), image::DynamicImage::ImageRgba32F(_)));
+  }
+}
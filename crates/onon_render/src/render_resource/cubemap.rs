@@ -0,0 +1,287 @@
+use crate::render_resource::{MipmapGenerator, image_format, render_pipeline::PipelineBuilder};
+use anyhow::{Result, anyhow};
+
+/// A cube texture, its six square faces stored as layers in +X, -X, +Y, -Y, +Z, -Z order.
+/// Materials sample it by passing `view` as a `MaterialParam::Texture` bound to a `texture_cube`.
+pub struct Cubemap {
+  pub texture: wgpu::Texture,
+  /// `Cube` view of every face and mip level
+  pub view: wgpu::TextureView,
+  /// Width and height of a face
+  pub size: u32,
+  pub format: wgpu::TextureFormat,
+}
+
+impl Cubemap {
+  pub const FACES: u32 = 6;
+
+  /// Equirectangular images are converted into faces of this format
+  pub const EQUIRECT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+  /// Builds a cubemap from six square images of the same size, in +X, -X, +Y, -Y, +Z, -Z order.
+  /// The format keeps the precision of the first face, as `image_format::source_format` picks it.
+  pub fn from_faces(device: &wgpu::Device, queue: &wgpu::Queue, faces: &[image::DynamicImage; 6]) -> Result<Self> {
+    let size = faces[0].width();
+    if let Some(face) = faces.iter().find(|face| face.width() != size || face.height() != size) {
+      return Err(anyhow!(
+        "Cubemap faces must be square and {size}x{size} like the first one, not {}x{}",
+        face.width(),
+        face.height()
+      ));
+    }
+
+    let format = image_format::source_format(faces[0].color(), device.features());
+    let cubemap = Self::create(device, size, format)?;
+    for (layer, face) in faces.iter().enumerate() {
+      image_format::check_source(face.color(), format, false)?;
+      let pixels = image_format::pixels_for_format(face, format)?;
+      queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+          texture: &cubemap.texture,
+          mip_level: 0,
+          origin: wgpu::Origin3d {
+            x: 0,
+            y: 0,
+            z: layer as u32,
+          },
+          aspect: wgpu::TextureAspect::All,
+        },
+        &pixels,
+        wgpu::TexelCopyBufferLayout {
+          offset: 0,
+          bytes_per_row: Some(size * format.block_copy_size(None).unwrap_or(4)),
+          rows_per_image: Some(size),
+        },
+        wgpu::Extent3d {
+          width: size,
+          height: size,
+          depth_or_array_layers: 1,
+        },
+      );
+    }
+
+    cubemap.generate_mipmaps(device, queue);
+    Ok(cubemap)
+  }
+
+  /// Projects an equirectangular (latitude/longitude) image, usually an HDR panorama, onto
+  /// the faces of a `face_size` cubemap. The conversion is rendered on the GPU.
+  pub fn from_equirectangular(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &image::DynamicImage,
+    face_size: u32,
+  ) -> Result<Self> {
+    let format = Self::EQUIRECT_FORMAT;
+    let source_size = wgpu::Extent3d {
+      width: image.width(),
+      height: image.height(),
+      depth_or_array_layers: 1,
+    };
+    let max_size = device.limits().max_texture_dimension_2d;
+    if source_size.width == 0 || source_size.width.max(source_size.height) > max_size {
+      return Err(anyhow!("Equirectangular images are 1 to {max_size} pixels wide, not {}x{}", image.width(), image.height()));
+    }
+
+    let source = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Equirectangular Source"),
+      size: source_size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    });
+    queue.write_texture(
+      source.as_image_copy(),
+      &image_format::pixels_for_format(image, format)?,
+      wgpu::TexelCopyBufferLayout {
+        offset: 0,
+        bytes_per_row: Some(source_size.width * format.block_copy_size(None).unwrap_or(8)),
+        rows_per_image: Some(source_size.height),
+      },
+      source_size,
+    );
+
+    let cubemap = Self::create(device, face_size, format)?;
+    let converter = EquirectConverter::new(device, format);
+    converter.convert(device, queue, &source, &cubemap.texture);
+
+    cubemap.generate_mipmaps(device, queue);
+    Ok(cubemap)
+  }
+
+  /// Mips are only allocated when the `MipmapGenerator` can render them for `format`
+  fn create(device: &wgpu::Device, size: u32, format: wgpu::TextureFormat) -> Result<Self> {
+    let max_size = device.limits().max_texture_dimension_2d;
+    if size == 0 || size > max_size {
+      return Err(anyhow!("Cubemap faces are 1 to {max_size} pixels wide, not {size}"));
+    }
+
+    let extent = wgpu::Extent3d {
+      width: size,
+      height: size,
+      depth_or_array_layers: Self::FACES,
+    };
+    let format_features = format.guaranteed_format_features(device.features());
+    let mip_level_count = match MipmapGenerator::supports(format, device.features()) {
+      true => MipmapGenerator::level_count(extent),
+      false => 1,
+    };
+    // Mips and the equirectangular conversion render into the faces
+    let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+    if format_features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT) {
+      usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Cubemap"),
+      size: extent,
+      mip_level_count,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+      label: Some("Cubemap View"),
+      dimension: Some(wgpu::TextureViewDimension::Cube),
+      ..Default::default()
+    });
+
+    Ok(Self {
+      texture,
+      view,
+      size,
+      format,
+    })
+  }
+
+  fn generate_mipmaps(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    if self.texture.mip_level_count() > 1 {
+      MipmapGenerator::new(device, self.format).generate(device, queue, &self.texture, 0..Self::FACES);
+    }
+  }
+}
+
+/// Renders each cube face from an equirectangular texture, one fullscreen triangle per face
+struct EquirectConverter {
+  pipeline: wgpu::RenderPipeline,
+  bind_group_layout: wgpu::BindGroupLayout,
+  sampler: wgpu::Sampler,
+}
+
+impl EquirectConverter {
+  fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Equirect Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Equirect Pipeline Layout"),
+      bind_group_layouts: &[&bind_group_layout],
+      immediate_size: 0,
+    });
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../../../../shaders/equirect_to_cube.wgsl"));
+
+    let mut pipeline_builder = PipelineBuilder::new();
+    pipeline_builder.set_layout(&layout);
+    pipeline_builder.add_target_with_blend(format, None);
+    pipeline_builder.set_vertex_buffers(Vec::new());
+    pipeline_builder.set_vertex(&shader, "vs_main");
+    pipeline_builder.set_fragment(&shader, "fs_main");
+    let pipeline = pipeline_builder.create_pipeline(device).unwrap();
+
+    // Wraps around the longitude seam, clamps at the poles
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      label: Some("Equirect Sampler"),
+      address_mode_u: wgpu::AddressMode::Repeat,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      ..Default::default()
+    });
+
+    Self {
+      pipeline,
+      bind_group_layout,
+      sampler,
+    }
+  }
+
+  /// Fills the first level of the six faces of `cube`
+  fn convert(&self, device: &wgpu::Device, queue: &wgpu::Queue, source: &wgpu::Texture, cube: &wgpu::Texture) {
+    let source_view = source.create_view(&Default::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("equirect_bind_group"),
+      layout: &self.bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&source_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&self.sampler),
+        },
+      ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Equirect Encoder"),
+    });
+
+    for face in 0..Cubemap::FACES {
+      let target = cube.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("cube face"),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: 0,
+        mip_level_count: Some(1),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        ..Default::default()
+      });
+
+      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Equirect Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: &target,
+          depth_slice: None,
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            store: wgpu::StoreOp::Store,
+          },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+        multiview_mask: None,
+      });
+      render_pass.set_pipeline(&self.pipeline);
+      render_pass.set_bind_group(0, &bind_group, &[]);
+      // The shader reads the face from the instance index
+      render_pass.draw(0..3, face..face + 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+  }
+}
@@ -1,3 +1,5 @@
+use crate::render_resource::RenderTarget;
+
 pub struct FrameContext {
   pub encoder: wgpu::CommandEncoder,
   pub output: wgpu::SurfaceTexture,
@@ -15,18 +17,45 @@ impl FrameContext {
   }
 
   pub fn create_render_pass<'a>(&'a mut self, view: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+    self.begin_pass(view, None, wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+  }
+
+  /// Pass drawing into `target`, cleared to `clear` first or drawn over when `None`.
+  /// Its depth texture, if any, is cleared to 1.
+  pub fn create_target_pass<'a>(
+    &'a mut self,
+    target: &'a RenderTarget,
+    clear: Option<wgpu::Color>,
+  ) -> wgpu::RenderPass<'a> {
+    let load = clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear);
+    self.begin_pass(target.color_view(), target.depth_view(), load)
+  }
+
+  fn begin_pass<'a>(
+    &'a mut self,
+    view: &'a wgpu::TextureView,
+    depth_view: Option<&'a wgpu::TextureView>,
+    load: wgpu::LoadOp<wgpu::Color>,
+  ) -> wgpu::RenderPass<'a> {
     self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
       label: Some("Render Pass"),
       color_attachments: &[Some(wgpu::RenderPassColorAttachment {
         view: view,
         resolve_target: None,
         ops: wgpu::Operations {
-          load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+          load,
           store: wgpu::StoreOp::Store,
         },
         depth_slice: None,
       })],
-      depth_stencil_attachment: None,
+      depth_stencil_attachment: depth_view.map(|view| wgpu::RenderPassDepthStencilAttachment {
+        view,
+        depth_ops: Some(wgpu::Operations {
+          load: wgpu::LoadOp::Clear(1.0),
+          store: wgpu::StoreOp::Store,
+        }),
+        stencil_ops: None,
+      }),
       timestamp_writes: None,
       occlusion_query_set: None,
       multiview_mask: None
@@ -0,0 +1,194 @@
+use crate::render_resource::compressed::{self, CompressedImage};
+use anyhow::{Result, anyhow};
+
+/// An image decoded from its file, ready to be uploaded to a texture array
+pub enum DecodedImage {
+  Image(image::DynamicImage),
+  Compressed(CompressedImage),
+}
+
+/// Decodes the encoded file `bytes`, the format being guessed from the data.
+/// Does no GPU work, so it can run on any thread.
+pub fn decode(bytes: &[u8]) -> Result<DecodedImage> {
+  if compressed::is_compressed_data(bytes) {
+    Ok(DecodedImage::Compressed(CompressedImage::from_memory(bytes)?))
+  } else {
+    Ok(DecodedImage::Image(image::load_from_memory(bytes)?))
+  }
+}
+
+/// Texture format keeping the precision of decoded images: float images (HDR, EXR)
+/// become `Rgba32Float` when the device can filter it and `Rgba16Float` otherwise,
+/// 16-bit images `Rgba16Unorm` with `TEXTURE_FORMAT_16BIT_NORM` and `Rgba16Float`
+/// otherwise, and everything else `Rgba8UnormSrgb`.
+/// `Rgba16Unorm` gets the texels as they are, while no 16-bit format decodes sRGB,
+/// so without the feature they are linearized into a float format, see `pixels_for_format`.
+pub fn source_format(color_type: image::ColorType, features: wgpu::Features) -> wgpu::TextureFormat {
+  use image::ColorType as C;
+
+  match color_type {
+    C::Rgb32F | C::Rgba32F if features.contains(wgpu::Features::FLOAT32_FILTERABLE) => {
+      wgpu::TextureFormat::Rgba32Float
+    }
+    C::L16 | C::La16 | C::Rgb16 | C::Rgba16 if features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM) => {
+      wgpu::TextureFormat::Rgba16Unorm
+    }
+    C::Rgb32F | C::Rgba32F | C::L16 | C::La16 | C::Rgb16 | C::Rgba16 => wgpu::TextureFormat::Rgba16Float,
+    _ => wgpu::TextureFormat::Rgba8UnormSrgb,
+  }
+}
+
+/// Converts `image` to tightly packed rows of `format` texels.
+/// Formats with fewer than four channels keep the first ones, red for `R8Unorm` masks
+/// and red and green for `Rg8Unorm` normal map XY. Luma and alpha images fill
+/// two channel formats with their luma and alpha.
+///
+/// Float formats are linear: the colors of 8 and 16-bit images are taken as sRGB encoded
+/// and linearized in `Rgba16Float` and `Rgba32Float`, as `Rgba8UnormSrgb` would decode them.
+/// 16-bit unorm formats keep the values of 16-bit images unchanged.
+/// Single and dual channel float formats hold data and keep the values as they are.
+pub fn pixels_for_format(image: &image::DynamicImage, format: wgpu::TextureFormat) -> Result<Vec<u8>> {
+  use wgpu::TextureFormat as F;
+
+  let to_f16 = |values: Vec<f32>| -> Vec<u8> {
+    let texels: Vec<u16> = values.into_iter().map(|value| half::f16::from_f32(value).to_bits()).collect();
+    bytemuck::cast_slice(&texels).to_vec()
+  };
+  let to_rgba32f = || {
+    let mut rgba = image.to_rgba32f();
+    if format.components() == 4 && !is_float(image.color()) {
+      for pixel in rgba.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+          *channel = srgb_to_linear(*channel);
+        }
+      }
+    }
+    rgba
+  };
+
+  let color_type = image.color();
+  let channels: &[usize] = match format.components() {
+    2 if !color_type.has_color() && color_type.has_alpha() => &[0, 3],
+    components => &[0, 1, 2, 3][..components as usize],
+  };
+
+  Ok(match format {
+    F::Rgba8Unorm | F::Rgba8UnormSrgb => image.to_rgba8().into_raw(),
+    F::R8Unorm | F::Rg8Unorm => pick_channels(&image.to_rgba8(), channels),
+    F::R16Unorm | F::Rg16Unorm | F::Rgba16Unorm => {
+      bytemuck::cast_slice(&pick_channels(&image.to_rgba16(), channels)).to_vec()
+    }
+    F::R16Float | F::Rg16Float | F::Rgba16Float => to_f16(pick_channels(&to_rgba32f(), channels)),
+    F::R32Float | F::Rg32Float | F::Rgba32Float => {
+      bytemuck::cast_slice(&pick_channels(&to_rgba32f(), channels)).to_vec()
+    }
+    _ => return Err(anyhow!("Images can't be converted to {:?}", format)),
+  })
+}
+
+/// Multiplies the color channels of `image` by its alpha, for premultiplied alpha blending.
+/// Images without alpha are returned as they are.
+pub fn premultiply_alpha(image: &image::DynamicImage) -> std::borrow::Cow<'_, image::DynamicImage> {
+  use image::{ColorType as C, DynamicImage};
+
+  if !image.color().has_alpha() {
+    return std::borrow::Cow::Borrowed(image);
+  }
+  std::borrow::Cow::Owned(match image.color() {
+    C::Rgba32F => {
+      let mut rgba = image.to_rgba32f();
+      for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3];
+        for channel in &mut pixel.0[..3] {
+          *channel *= alpha;
+        }
+      }
+      DynamicImage::ImageRgba32F(rgba)
+    }
+    C::La16 | C::Rgba16 => {
+      let mut rgba = image.to_rgba16();
+      for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel.0[..3] {
+          *channel = ((*channel as u32 * alpha + 32767) / 65535) as u16;
+        }
+      }
+      DynamicImage::ImageRgba16(rgba)
+    }
+    _ => {
+      let mut rgba = image.to_rgba8();
+      for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel.0[..3] {
+          *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+      }
+      DynamicImage::ImageRgba8(rgba)
+    }
+  })
+}
+
+/// Fails when images of `color_type` can't be stored in `format` without losing their range
+/// or their channels: HDR images only go into float formats, and formats with fewer than four
+/// channels need images with as many, more only with `truncate_channels`.
+/// Formats `pixels_for_format` doesn't handle fail too.
+pub fn check_source(color_type: image::ColorType, format: wgpu::TextureFormat, truncate_channels: bool) -> Result<()> {
+  use image::ColorType as C;
+  use wgpu::TextureFormat as F;
+
+  let float_format = matches!(
+    format,
+    F::R16Float | F::Rg16Float | F::Rgba16Float | F::R32Float | F::Rg32Float | F::Rgba32Float
+  );
+  let channels = color_type.channel_count() as u32;
+  let components = format.components() as u32;
+  if components < 4 && channels != components && !(truncate_channels && channels > components) {
+    return Err(anyhow!(
+      "{:?} images have {channels} channels and {:?} textures {components}, {}",
+      color_type,
+      format,
+      match channels > components {
+        true => "drop the others with `truncate_channels`",
+        false => "convert the images first",
+      }
+    ));
+  }
+  match (color_type, format) {
+    (C::Rgb32F | C::Rgba32F, _) if !float_format => {
+      Err(anyhow!("{:?} images would be clamped by {:?} textures", color_type, format))
+    }
+    (
+      _,
+      F::Rgba8Unorm
+      | F::Rgba8UnormSrgb
+      | F::R8Unorm
+      | F::Rg8Unorm
+      | F::R16Unorm
+      | F::Rg16Unorm
+      | F::Rgba16Unorm,
+    ) => Ok(()),
+    _ if float_format => Ok(()),
+    _ => Err(anyhow!("Images can't be converted to {:?}", format)),
+  }
+}
+
+fn is_float(color_type: image::ColorType) -> bool {
+  matches!(color_type, image::ColorType::Rgb32F | image::ColorType::Rgba32F)
+}
+
+/// Decodes an sRGB encoded channel between 0 and 1
+fn srgb_to_linear(value: f32) -> f32 {
+  if value <= 0.04045 {
+    value / 12.92
+  } else {
+    ((value + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+/// Keeps the `channels` of RGBA texels, in that order
+fn pick_channels<T: Copy>(texels: &[T], channels: &[usize]) -> Vec<T> {
+  if channels.len() == 4 {
+    return texels.to_vec();
+  }
+  texels.chunks_exact(4).flat_map(|texel| channels.iter().map(|&channel| texel[channel])).collect()
+}
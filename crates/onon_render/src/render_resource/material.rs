@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::{
+  render_resource::{SamplerId, render_pipeline::BlendMode},
+  shader_pass::ShaderPass,
+};
+
+/// Value of a named material parameter. Plain values are packed into the
+/// uniform buffer member of the same name, textures and samplers replace the
+/// binding whose global variable has that name.
+#[derive(Debug, Clone)]
+pub enum MaterialParam {
+  Float(f32),
+  Vec2([f32; 2]),
+  Vec3([f32; 3]),
+  Vec4([f32; 4]),
+  Int(i32),
+  UInt(u32),
+  Mat4([[f32; 4]; 4]),
+  Texture(wgpu::TextureView),
+  Sampler(wgpu::Sampler),
+}
+
+impl MaterialParam {
+  fn bytes(&self) -> Option<&[u8]> {
+    match self {
+      MaterialParam::Float(value) => Some(bytemuck::bytes_of(value)),
+      MaterialParam::Vec2(value) => Some(bytemuck::bytes_of(value)),
+      MaterialParam::Vec3(value) => Some(bytemuck::bytes_of(value)),
+      MaterialParam::Vec4(value) => Some(bytemuck::bytes_of(value)),
+      MaterialParam::Int(value) => Some(bytemuck::bytes_of(value)),
+      MaterialParam::UInt(value) => Some(bytemuck::bytes_of(value)),
+      MaterialParam::Mat4(value) => Some(bytemuck::bytes_of(value)),
+      MaterialParam::Texture(_) | MaterialParam::Sampler(_) => None,
+    }
+  }
+}
+
+/// Resources bound in place of textures and samplers the material has no value for
+pub struct MaterialFallbacks {
+  pub texture_2d: wgpu::TextureView,
+  pub texture_2d_array: wgpu::TextureView,
+  pub sampler: wgpu::Sampler,
+}
+
+impl MaterialFallbacks {
+  pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      size: wgpu::Extent3d {
+        width: 1,
+        height: 1,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      label: Some("fallback texture"),
+      view_formats: &[],
+    });
+
+    queue.write_texture(
+      texture.as_image_copy(),
+      &[255; 4],
+      wgpu::TexelCopyBufferLayout {
+        offset: 0,
+        bytes_per_row: Some(4),
+        rows_per_image: Some(1),
+      },
+      texture.size(),
+    );
+
+    Self {
+      texture_2d: texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        ..Default::default()
+      }),
+      texture_2d_array: texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+      }),
+      sampler: device.create_sampler(&wgpu::SamplerDescriptor::default()),
+    }
+  }
+}
+
+struct UniformBlock {
+  group: u32,
+  binding: u32,
+  buffer: wgpu::Buffer,
+  data: Vec<u8>,
+  dirty: bool,
+}
+
+/// A shader pass and the parameter values it is drawn with.
+///
+/// Groups below `ShaderEffect::shared_groups` are bound by the renderer,
+/// group 0 being the texture array of the drawn object.
+/// The material owns every other group.
+pub struct Material {
+  pass: ShaderPass,
+  parameters: HashMap<String, MaterialParam>,
+  uniforms: Vec<UniformBlock>,
+  bind_groups: Vec<Option<wgpu::BindGroup>>,
+  bind_groups_dirty: bool,
+  texture_sampler: Option<SamplerId>,
+  blend_mode: BlendMode,
+}
+
+impl Material {
+  pub fn new(device: &wgpu::Device, pass: ShaderPass) -> Self {
+    let effect = pass.shader_effect();
+    let uniforms = effect
+      .shader()
+      .bindings
+      .iter()
+      .filter(|binding| binding.group >= effect.shared_groups())
+      .filter(|binding| matches!(binding.ty, wgpu::BindingType::Buffer { .. }))
+      .map(|binding| {
+        // Uniform buffers sizes are multiples of 16
+        let size = (binding.size as u64).next_multiple_of(16).max(16);
+        UniformBlock {
+          group: binding.group,
+          binding: binding.binding,
+          buffer: device.create_buffer(&wgpu::BufferDescriptor {
+            label: binding.name.as_deref(),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+          }),
+          data: vec![0; size as usize],
+          dirty: true,
+        }
+      })
+      .collect();
+
+    Self {
+      bind_groups: vec![None; effect.group_count() as usize],
+      blend_mode: pass.state().blend,
+      pass,
+      parameters: HashMap::new(),
+      uniforms,
+      bind_groups_dirty: true,
+      texture_sampler: None,
+    }
+  }
+
+  /// Sets a parameter, failing when the shader has no uniform member or
+  /// binding with that name, or when the value doesn't fit it.
+  pub fn set(&mut self, name: &str, value: MaterialParam) -> Result<()> {
+    let effect = self.pass.shader_effect();
+    let shared_groups = effect.shared_groups();
+
+    match value.bytes() {
+      Some(bytes) => {
+        let (binding, member) = effect
+          .shader()
+          .bindings
+          .iter()
+          .filter(|binding| binding.group >= shared_groups)
+          .find_map(|binding| {
+            let member = binding.members.iter().find(|member| member.name == name)?;
+            Some((binding, member))
+          })
+          .context(format!("No uniform named {name}"))?;
+
+        if member.size as usize != bytes.len() {
+          return Err(anyhow!(
+            "Uniform {name} is {} bytes, got {} bytes",
+            member.size,
+            bytes.len()
+          ));
+        }
+
+        let block = self
+          .uniforms
+          .iter_mut()
+          .find(|block| block.group == binding.group && block.binding == binding.binding)
+          .context(format!("No uniform buffer for {name}"))?;
+        let offset = member.offset as usize;
+        block.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        block.dirty = true;
+      }
+      None => {
+        let binding = effect
+          .shader()
+          .bindings
+          .iter()
+          .filter(|binding| binding.group >= shared_groups)
+          .find(|binding| binding.name.as_deref() == Some(name))
+          .context(format!("No binding named {name}"))?;
+
+        let expected_sampler = matches!(binding.ty, wgpu::BindingType::Sampler(_));
+        let is_sampler = matches!(value, MaterialParam::Sampler(_));
+        if expected_sampler != is_sampler {
+          return Err(anyhow!("Binding {name} has type {:?}", binding.ty));
+        }
+        self.bind_groups_dirty = true;
+      }
+    }
+
+    self.parameters.insert(name.to_string(), value);
+    Ok(())
+  }
+
+  pub fn get(&self, name: &str) -> Option<&MaterialParam> {
+    self.parameters.get(name)
+  }
+
+  pub fn parameters(&self) -> impl Iterator<Item = (&str, &MaterialParam)> {
+    self.parameters.iter().map(|(name, value)| (name.as_str(), value))
+  }
+
+  /// Sampler of the texture array in group 0, objects can override it.
+  /// Samplers of the material's own groups are parameters.
+  pub fn set_texture_sampler(&mut self, sampler: Option<SamplerId>) {
+    self.texture_sampler = sampler;
+  }
+
+  pub fn texture_sampler(&self) -> Option<SamplerId> {
+    self.texture_sampler
+  }
+
+  /// Blend mode of the objects that don't pick one, `PipelineState::blend` at first
+  pub fn set_blend_mode(&mut self, mode: BlendMode) {
+    self.blend_mode = mode;
+  }
+
+  pub fn blend_mode(&self) -> BlendMode {
+    self.blend_mode
+  }
+
+  /// Builds the pipeline variant blending with `mode` when it's missing, see `ShaderPass::prepare_blend`
+  pub fn prepare_blend(&mut self, device: &wgpu::Device, mode: BlendMode, depth: bool) -> Result<()> {
+    self.pass.prepare_blend(device, mode, depth)
+  }
+
+  pub fn pass(&self) -> &ShaderPass {
+    &self.pass
+  }
+
+  pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+    &self.pass.render_pipeline
+  }
+
+  /// Uploads changed uniforms and rebuilds the bind groups after texture or sampler changes
+  pub fn prepare(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    fallbacks: &MaterialFallbacks,
+  ) -> Result<()> {
+    for block in self.uniforms.iter_mut().filter(|block| block.dirty) {
+      queue.write_buffer(&block.buffer, 0, &block.data);
+      block.dirty = false;
+    }
+
+    if !self.bind_groups_dirty {
+      return Ok(());
+    }
+
+    let effect = self.pass.shader_effect();
+    for group in effect.shared_groups()..effect.group_count() {
+      let bindings: Vec<_> = effect
+        .shader()
+        .bindings
+        .iter()
+        .filter(|binding| binding.group == group)
+        .collect();
+      if bindings.is_empty() {
+        continue;
+      }
+
+      let entries = bindings
+        .iter()
+        .map(|binding| {
+          let parameter = binding.name.as_ref().and_then(|name| self.parameters.get(name));
+          let resource = match (binding.ty, parameter) {
+            (wgpu::BindingType::Buffer { .. }, _) => self
+              .uniforms
+              .iter()
+              .find(|block| block.group == binding.group && block.binding == binding.binding)
+              .context("Missing uniform buffer")?
+              .buffer
+              .as_entire_binding(),
+            (wgpu::BindingType::Sampler(_), Some(MaterialParam::Sampler(sampler))) => {
+              wgpu::BindingResource::Sampler(sampler)
+            }
+            (wgpu::BindingType::Sampler(_), _) => {
+              wgpu::BindingResource::Sampler(&fallbacks.sampler)
+            }
+            (wgpu::BindingType::Texture { .. }, Some(MaterialParam::Texture(view))) => {
+              wgpu::BindingResource::TextureView(view)
+            }
+            (wgpu::BindingType::Texture { view_dimension, .. }, _) => match view_dimension {
+              wgpu::TextureViewDimension::D2 => {
+                wgpu::BindingResource::TextureView(&fallbacks.texture_2d)
+              }
+              wgpu::TextureViewDimension::D2Array => {
+                wgpu::BindingResource::TextureView(&fallbacks.texture_2d_array)
+              }
+              _ => return Err(anyhow!("No fallback for {:?} textures", view_dimension)),
+            },
+            (ty, _) => return Err(anyhow!("Unsupported material binding {:?}", ty)),
+          };
+
+          Ok(wgpu::BindGroupEntry {
+            binding: binding.binding,
+            resource,
+          })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+      self.bind_groups[group as usize] = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("material_bind_group"),
+        layout: effect.bind_group_layout(group).context("Missing bind group layout")?,
+        entries: &entries,
+      }));
+    }
+
+    self.bind_groups_dirty = false;
+    Ok(())
+  }
+
+  /// Sets the pipeline blending with `blend`, testing depth when `depth` is set, and the
+  /// bind groups owned by the material. Falls back to the main pipeline when the variant
+  /// wasn't prepared.
+  pub fn bind(&self, render_pass: &mut wgpu::RenderPass, blend: BlendMode, depth: bool) {
+    render_pass.set_pipeline(self.pass.pipeline(blend, depth).unwrap_or(&self.pass.render_pipeline));
+    for (group, bind_group) in self.bind_groups.iter().enumerate() {
+      if let Some(bind_group) = bind_group {
+        render_pass.set_bind_group(group as u32, bind_group, &[]);
+      }
+    }
+  }
+}
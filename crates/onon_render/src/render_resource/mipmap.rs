@@ -0,0 +1,147 @@
+use crate::render_resource::render_pipeline::PipelineBuilder;
+
+/// Fills the mip chain of textures from their first level, rendering each
+/// level from the previous one with linear filtering.
+pub struct MipmapGenerator {
+  pipeline: wgpu::RenderPipeline,
+  bind_group_layout: wgpu::BindGroupLayout,
+  sampler: wgpu::Sampler,
+}
+
+impl MipmapGenerator {
+  /// Whether the mips of `format` textures can be rendered, it must be filterable and renderable
+  pub fn supports(format: wgpu::TextureFormat, features: wgpu::Features) -> bool {
+    let format_features = format.guaranteed_format_features(features);
+    format_features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+      && format_features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+  }
+
+  /// Textures it generates mips for must be of `format` and be render attachments
+  pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Mipmap Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Mipmap Pipeline Layout"),
+      bind_group_layouts: &[&bind_group_layout],
+      immediate_size: 0,
+    });
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../../../../shaders/mipmap.wgsl"));
+
+    let mut pipeline_builder = PipelineBuilder::new();
+    pipeline_builder.set_layout(&layout);
+    pipeline_builder.add_target_with_blend(format, None);
+    pipeline_builder.set_vertex_buffers(Vec::new());
+    pipeline_builder.set_vertex(&shader, "vs_main");
+    pipeline_builder.set_fragment(&shader, "fs_main");
+    let pipeline = pipeline_builder.create_pipeline(device).unwrap();
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      label: Some("Mipmap Sampler"),
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      ..Default::default()
+    });
+
+    Self {
+      pipeline,
+      bind_group_layout,
+      sampler,
+    }
+  }
+
+  /// Number of levels down to 1x1 for a texture of `size`
+  pub fn level_count(size: wgpu::Extent3d) -> u32 {
+    size.max_mips(wgpu::TextureDimension::D2)
+  }
+
+  /// Regenerates every level past the first one of the array `layers` of `texture`
+  pub fn generate(
+    &self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    layers: std::ops::Range<u32>,
+  ) {
+    if texture.mip_level_count() < 2 {
+      return;
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Mipmap Encoder"),
+    });
+
+    for layer in layers {
+      let level_view = |level| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          label: Some("mip level"),
+          dimension: Some(wgpu::TextureViewDimension::D2),
+          base_mip_level: level,
+          mip_level_count: Some(1),
+          base_array_layer: layer,
+          array_layer_count: Some(1),
+          ..Default::default()
+        })
+      };
+
+      for level in 1..texture.mip_level_count() {
+        let source = level_view(level - 1);
+        let target = level_view(level);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+          label: Some("mipmap_bind_group"),
+          layout: &self.bind_group_layout,
+          entries: &[
+            wgpu::BindGroupEntry {
+              binding: 0,
+              resource: wgpu::BindingResource::TextureView(&source),
+            },
+            wgpu::BindGroupEntry {
+              binding: 1,
+              resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+          ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+          label: Some("Mipmap Pass"),
+          color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &target,
+            depth_slice: None,
+            resolve_target: None,
+            ops: wgpu::Operations {
+              load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+              store: wgpu::StoreOp::Store,
+            },
+          })],
+          depth_stencil_attachment: None,
+          timestamp_writes: None,
+          occlusion_query_set: None,
+          multiview_mask: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+      }
+    }
+
+    queue.submit(Some(encoder.finish()));
+  }
+}
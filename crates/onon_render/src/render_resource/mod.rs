@@ -2,13 +2,30 @@ pub mod render_pipeline;
 pub mod frame_context;
 pub mod render_state;
 pub mod texture;
+pub mod mipmap;
+pub mod sampler_cache;
 pub mod texture_array;
+pub mod texture_atlas;
+pub mod render_target;
+pub mod cubemap;
+pub mod compressed;
+pub mod image_format;
+mod block_decode;
+mod basis;
 pub mod shader;
 pub mod shader_effect;
+pub mod material;
 
 pub use frame_context::FrameContext;
 pub use render_state::RenderState;
 pub use texture::Texture;
-pub use texture_array::TextureArray;
+pub use mipmap::MipmapGenerator;
+pub use sampler_cache::{SamplerCache, SamplerId, SamplerKey, SamplerPreset};
+pub use texture_array::{TextureArray, TextureArrayId};
+pub use texture_atlas::{AtlasEntry, TextureAtlas};
+pub use render_target::RenderTarget;
+pub use cubemap::Cubemap;
+pub use compressed::CompressedImage;
 pub use shader::Shader;
-pub use shader_effect::ShaderEffect;
\ No newline at end of file
+pub use shader_effect::ShaderEffect;
+pub use material::{Material, MaterialParam};
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::render_resource;
+use crate::{mesh, render_resource};
 
 pub fn create_layout(device: &wgpu::Device) -> wgpu::PipelineLayout {
   device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -8,15 +8,74 @@ pub fn create_layout(device: &wgpu::Device) -> wgpu::PipelineLayout {
   })
 }
 
-pub fn create_solid_pipeline(render_state: &render_resource::RenderState) -> wgpu::RenderPipeline {
+/// Layout of the texture array bind group, shared by every pipeline
+/// sampling from a `TextureArray` so the same bind group works with all of them.
+pub fn create_texture_array_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+  device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    label: Some("Texture Array Bind Group Layout"),
+    entries: &[
+      wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          sample_type: wgpu::TextureSampleType::Float { filterable: true },
+          view_dimension: wgpu::TextureViewDimension::D2Array,
+          multisampled: false,
+        },
+        count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+      },
+    ],
+  })
+}
+
+pub fn create_texture_array_layout(
+  device: &wgpu::Device,
+  texture_array_layout: &wgpu::BindGroupLayout,
+) -> wgpu::PipelineLayout {
+  device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Texture Array Pipeline Layout"),
+    bind_group_layouts: &[texture_array_layout],
+    immediate_size: 0,
+  })
+}
+
+pub fn create_sprite_pipeline(
+  render_state: &render_resource::RenderState,
+  layout: &wgpu::PipelineLayout,
+  blend: render_resource::render_pipeline::BlendMode,
+) -> wgpu::RenderPipeline {
+  let shader = render_state
+    .device()
+    .create_shader_module(wgpu::include_wgsl!("../../../../../shaders/sprite.wgsl"));
+
+  let mut pipeline_builder = render_resource::render_pipeline::PipelineBuilder::new();
+  pipeline_builder.set_layout(layout);
+  pipeline_builder.add_blended_target(render_state.config.format, blend);
+  pipeline_builder.set_vertex_buffers(vec![mesh::SpriteVertex::desc()]);
+  pipeline_builder.set_vertex(&shader, "vs_main");
+  pipeline_builder.set_fragment(&shader, "fs_main");
+  pipeline_builder.create_pipeline(render_state.device()).unwrap()
+}
+
+pub fn create_picking_pipeline(
+  render_state: &render_resource::RenderState,
+  layout: &wgpu::PipelineLayout,
+) -> wgpu::RenderPipeline {
   let shader = render_state
     .device()
-    .create_shader_module(wgpu::include_wgsl!("../../../../../shaders/triangle.wgsl"));
+    .create_shader_module(wgpu::include_wgsl!("../../../../../shaders/picking.wgsl"));
 
-  //let layout = create_layout(&render_state.device());
   let mut pipeline_builder = render_resource::render_pipeline::PipelineBuilder::new();
-  pipeline_builder.add_target(render_state.config.format);
+  pipeline_builder.set_layout(layout);
+  pipeline_builder.add_target_with_blend(crate::picking::PICKING_FORMAT, None);
+  pipeline_builder.set_vertex_buffers(vec![mesh::Vertex::desc(), mesh::InstanceData::desc()]);
   pipeline_builder.set_vertex(&shader, "vs_main");
   pipeline_builder.set_fragment(&shader, "fs_main");
-  pipeline_builder.create_pipeline(&render_state.device()).unwrap()
+  pipeline_builder.create_pipeline(render_state.device()).unwrap()
 }
\ No newline at end of file
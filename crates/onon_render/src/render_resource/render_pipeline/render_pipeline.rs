@@ -4,6 +4,73 @@ use crate::mesh;
 pub enum PipelineType {
   Solid,
   Wireframe,
+  Sprite(BlendMode),
+  Picking,
+}
+
+/// Blend presets for color targets. `Multiply` and `Screen` expect premultiplied
+/// colors, so that fully transparent texels leave the target untouched.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BlendMode {
+  /// Overwrites the target, alpha included
+  #[default]
+  Opaque,
+  /// Straight alpha, `src * a + dst * (1 - a)`
+  Alpha,
+  /// Colors already multiplied by alpha, `src + dst * (1 - a)`
+  PremultipliedAlpha,
+  /// `src * a + dst`, for glows and particles
+  Additive,
+  /// `src * dst`, darkens the target
+  Multiply,
+  /// `src + dst * (1 - src)`, lightens the target
+  Screen,
+}
+
+impl BlendMode {
+  pub const ALL: [BlendMode; 6] = [
+    BlendMode::Opaque,
+    BlendMode::Alpha,
+    BlendMode::PremultipliedAlpha,
+    BlendMode::Additive,
+    BlendMode::Multiply,
+    BlendMode::Screen,
+  ];
+
+  pub fn blend_state(self) -> wgpu::BlendState {
+    use wgpu::{BlendComponent, BlendFactor as F, BlendOperation};
+
+    let component = |src_factor, dst_factor| BlendComponent {
+      src_factor,
+      dst_factor,
+      operation: BlendOperation::Add,
+    };
+    // Coverage accumulates the same way for every blended mode
+    let alpha = component(F::One, F::OneMinusSrcAlpha);
+
+    match self {
+      BlendMode::Opaque => wgpu::BlendState::REPLACE,
+      BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+      BlendMode::PremultipliedAlpha => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+      BlendMode::Additive => wgpu::BlendState {
+        color: component(F::SrcAlpha, F::One),
+        alpha,
+      },
+      BlendMode::Multiply => wgpu::BlendState {
+        color: component(F::Dst, F::OneMinusSrcAlpha),
+        alpha,
+      },
+      BlendMode::Screen => wgpu::BlendState {
+        color: component(F::One, F::OneMinusSrc),
+        alpha,
+      },
+    }
+  }
+
+  /// Whether what's behind shows through, such draws come after opaque ones at the same depth
+  pub fn is_transparent(self) -> bool {
+    self != BlendMode::Opaque
+  }
 }
 
 #[derive(Clone)]
@@ -12,10 +79,12 @@ pub struct PipelineBuilder<'a> {
   cull_mode: wgpu::Face,
   polygon_mode: wgpu::PolygonMode,
   targets: Vec<Option<wgpu::ColorTargetState>>,
+  vertex_buffers: Vec<wgpu::VertexBufferLayout<'a>>,
   vertex_module: Option<&'a wgpu::ShaderModule>,
   vertex_entry: &'a str,
   fragment_module: Option<&'a wgpu::ShaderModule>,
   fragment_entry: &'a str,
+  depth_stencil: Option<wgpu::DepthStencilState>,
 }
 
 impl<'a> PipelineBuilder<'a> {
@@ -29,6 +98,8 @@ impl<'a> PipelineBuilder<'a> {
       fragment_module: None,
       fragment_entry: "",
       targets: Vec::new(),
+      vertex_buffers: vec![mesh::Vertex::desc()],
+      depth_stencil: None,
     }
   }
 
@@ -41,17 +112,15 @@ impl<'a> PipelineBuilder<'a> {
     let vertex = wgpu::VertexState {
       module: &vertex_module,
       compilation_options: Default::default(),
-      entry_point: Some("vs_main"),
-      buffers: &[
-        mesh::Vertex::desc()
-      ],
+      entry_point: Some(self.vertex_entry),
+      buffers: &self.vertex_buffers,
     };
 
     let fragment = match self.fragment_module.as_ref() {
       Some(shader_module) => Some(wgpu::FragmentState {
         module: shader_module,
         compilation_options: Default::default(),
-        entry_point: Some("fs_main"),
+        entry_point: Some(self.fragment_entry),
         targets: &self.targets,
       }),
       None => None
@@ -71,7 +140,7 @@ impl<'a> PipelineBuilder<'a> {
         unclipped_depth: false,
         conservative: false,
       },
-      depth_stencil: None,
+      depth_stencil: self.depth_stencil,
       multisample: wgpu::MultisampleState {
         count: 1,
         mask: !0,
@@ -96,6 +165,11 @@ impl<'a> PipelineBuilder<'a> {
     self.fragment_entry = entry_point;
   }
 
+  /// Replaces the default `mesh::Vertex` layout
+  pub fn set_vertex_buffers(&mut self, layouts: Vec<wgpu::VertexBufferLayout<'a>>) {
+    self.vertex_buffers = layouts;
+  }
+
   pub fn set_cull_mode(&mut self, cull_face: wgpu::Face) {
     self.cull_mode = cull_face;
   }
@@ -104,10 +178,42 @@ impl<'a> PipelineBuilder<'a> {
     self.polygon_mode = mode;
   }
 
+  /// Tests against a `format` depth attachment with `compare`, writing depth when `write` is set.
+  /// Passes with a depth attachment only take pipelines of the same depth format.
+  pub fn set_depth(&mut self, format: wgpu::TextureFormat, compare: wgpu::CompareFunction, write: bool) {
+    self.depth_stencil = Some(wgpu::DepthStencilState {
+      format,
+      depth_write_enabled: write,
+      depth_compare: compare,
+      stencil: wgpu::StencilState::default(),
+      bias: wgpu::DepthBiasState::default(),
+    });
+  }
+
   pub fn add_target(&mut self, format: wgpu::TextureFormat) {
+    self.add_target_with_blend(format, Some(wgpu::BlendState::REPLACE));
+  }
+
+  pub fn add_blended_target(&mut self, format: wgpu::TextureFormat, mode: BlendMode) {
+    self.add_target_with_blend(format, Some(mode.blend_state()));
+  }
+
+  /// Changes the blend state of the target added at `index`
+  pub fn set_target_blend(
+    &mut self,
+    index: usize,
+    blend: Option<wgpu::BlendState>,
+  ) -> Result<(), &'static str> {
+    let target = self.targets.get_mut(index).and_then(Option::as_mut).ok_or("No target at this index")?;
+    target.blend = blend;
+    Ok(())
+  }
+
+  /// Integer formats can't be blended and need a `None` blend state
+  pub fn add_target_with_blend(&mut self, format: wgpu::TextureFormat, blend: Option<wgpu::BlendState>) {
     self.targets.push(Some(wgpu::ColorTargetState {
-      format: format,
-      blend: Some(wgpu::BlendState::REPLACE),
+      format,
+      blend,
       write_mask: wgpu::ColorWrites::ALL,
     }))
   }
@@ -0,0 +1,77 @@
+use crate::render_resource::{TextureArray, TextureArrayId};
+
+/// Offscreen color texture, with an optional depth texture, that objects can be
+/// rendered into through `FrameContext::create_target_pass`. Without depth, objects are
+/// painted back to front in `SortKey` order like on the surface. Passes into a target
+/// with depth only take pipelines testing it, `Renderer::render_solids_to_target` and
+/// `Renderer::render_skybox_to_target` draw with those.
+///
+/// The color texture is the only layer of a texture array, so other objects draw it
+/// through `Scene::set_texture_array` with `id` and `RenderTarget::SLOT`.
+/// Objects can't sample the target they are being rendered into.
+pub struct RenderTarget {
+  pub id: TextureArrayId,
+  pub size: glam::UVec2,
+  color_view: wgpu::TextureView,
+  depth: Option<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl RenderTarget {
+  pub const SLOT: u32 = 0;
+  pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+  /// Layer `SLOT` of `texture_array` is rendered into, along with a `DEPTH_FORMAT`
+  /// depth texture of the same size when `depth` is set
+  pub fn new(device: &wgpu::Device, texture_array: &TextureArray, depth: bool) -> Self {
+    let size = texture_array.layer_size();
+    let color_view = texture_array.texture.texture().create_view(&wgpu::TextureViewDescriptor {
+      label: Some("render target view"),
+      dimension: Some(wgpu::TextureViewDimension::D2),
+      base_array_layer: Self::SLOT,
+      array_layer_count: Some(1),
+      ..Default::default()
+    });
+
+    let depth = depth.then(|| {
+      let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+          width: size.x,
+          height: size.y,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Self::DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        label: Some("render target depth"),
+        view_formats: &[],
+      });
+      let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+      (texture, view)
+    });
+
+    Self {
+      id: texture_array.id(),
+      size,
+      color_view,
+      depth,
+    }
+  }
+
+  pub fn color_view(&self) -> &wgpu::TextureView {
+    &self.color_view
+  }
+
+  pub fn has_depth(&self) -> bool {
+    self.depth.is_some()
+  }
+
+  pub fn depth_texture(&self) -> Option<&wgpu::Texture> {
+    self.depth.as_ref().map(|(texture, _)| texture)
+  }
+
+  pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+    self.depth.as_ref().map(|(_, view)| view)
+  }
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, sync::Arc};
+
+/// Index of a sampler in its `SamplerCache`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SamplerId(pub u32);
+
+/// Hashable description of a sampler, level of detail clamps being left to their defaults
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SamplerKey {
+  pub address_mode_u: wgpu::AddressMode,
+  pub address_mode_v: wgpu::AddressMode,
+  pub address_mode_w: wgpu::AddressMode,
+  pub mag_filter: wgpu::FilterMode,
+  pub min_filter: wgpu::FilterMode,
+  pub mipmap_filter: wgpu::MipmapFilterMode,
+  /// Above 1, every filter has to be `Linear`
+  pub anisotropy_clamp: u16,
+  /// Comparison samplers only fit comparison bindings, such as shadow maps
+  pub compare: Option<wgpu::CompareFunction>,
+  pub border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl SamplerKey {
+  pub fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+    wgpu::SamplerDescriptor {
+      label: None,
+      address_mode_u: self.address_mode_u,
+      address_mode_v: self.address_mode_v,
+      address_mode_w: self.address_mode_w,
+      mag_filter: self.mag_filter,
+      min_filter: self.min_filter,
+      mipmap_filter: self.mipmap_filter,
+      anisotropy_clamp: self.anisotropy_clamp,
+      compare: self.compare,
+      border_color: self.border_color,
+      ..Default::default()
+    }
+  }
+
+  fn filtered(address_mode: wgpu::AddressMode, filter: wgpu::FilterMode) -> Self {
+    Self {
+      address_mode_u: address_mode,
+      address_mode_v: address_mode,
+      address_mode_w: address_mode,
+      mag_filter: filter,
+      min_filter: filter,
+      mipmap_filter: match filter {
+        wgpu::FilterMode::Nearest => wgpu::MipmapFilterMode::Nearest,
+        wgpu::FilterMode::Linear => wgpu::MipmapFilterMode::Linear,
+      },
+      anisotropy_clamp: 1,
+      compare: None,
+      border_color: None,
+    }
+  }
+}
+
+/// Common samplers
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SamplerPreset {
+  /// Nearest filtering and clamped, keeps pixel art crisp
+  PixelArt,
+  /// Trilinear and clamped
+  Linear,
+  /// Trilinear and repeated, for tiling textures
+  LinearRepeat,
+  /// Trilinear, repeated by mirroring every other tile
+  Mirrored,
+  /// Trilinear and repeated with 16x anisotropic filtering, for textures seen at grazing angles
+  Anisotropic,
+  /// Linear `LessEqual` comparison, for depth textures
+  Comparison,
+}
+
+impl From<SamplerPreset> for SamplerKey {
+  fn from(preset: SamplerPreset) -> Self {
+    use wgpu::{AddressMode, FilterMode};
+
+    match preset {
+      SamplerPreset::PixelArt => Self::filtered(AddressMode::ClampToEdge, FilterMode::Nearest),
+      SamplerPreset::Linear => Self::filtered(AddressMode::ClampToEdge, FilterMode::Linear),
+      SamplerPreset::LinearRepeat => Self::filtered(AddressMode::Repeat, FilterMode::Linear),
+      SamplerPreset::Mirrored => Self::filtered(AddressMode::MirrorRepeat, FilterMode::Linear),
+      SamplerPreset::Anisotropic => Self {
+        anisotropy_clamp: 16,
+        ..Self::filtered(AddressMode::Repeat, FilterMode::Linear)
+      },
+      SamplerPreset::Comparison => Self {
+        mipmap_filter: wgpu::MipmapFilterMode::Nearest,
+        compare: Some(wgpu::CompareFunction::LessEqual),
+        ..Self::filtered(AddressMode::ClampToEdge, FilterMode::Linear)
+      },
+    }
+  }
+}
+
+/// Creates each distinct sampler once, handing out the same id for equal keys
+#[derive(Default)]
+pub struct SamplerCache {
+  samplers: Vec<(SamplerKey, Arc<wgpu::Sampler>)>,
+  ids: HashMap<SamplerKey, SamplerId>,
+}
+
+impl SamplerCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn get_or_create(&mut self, device: &wgpu::Device, key: impl Into<SamplerKey>) -> SamplerId {
+    let key = key.into();
+    if let Some(id) = self.ids.get(&key) {
+      return *id;
+    }
+
+    let id = SamplerId(self.samplers.len() as u32);
+    self.samplers.push((key, Arc::new(device.create_sampler(&key.descriptor()))));
+    self.ids.insert(key, id);
+    id
+  }
+
+  pub fn get(&self, id: SamplerId) -> Option<&Arc<wgpu::Sampler>> {
+    self.samplers.get(id.0 as usize).map(|(_, sampler)| sampler)
+  }
+
+  pub fn key(&self, id: SamplerId) -> Option<&SamplerKey> {
+    self.samplers.get(id.0 as usize).map(|(key, _)| key)
+  }
+}
@@ -10,11 +10,24 @@ use wgpu::{
   TextureViewDimension,
 };
 
+/// Member of a uniform buffer struct
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UniformMember {
+  pub name: String,
+  pub offset: u32,
+  pub size: u32,
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct ShaderBindingInfo {
   pub group: u32,
   pub binding: u32,
   pub ty: BindingType,
+  /// Name of the global variable in the shader
+  pub name: Option<String>,
+  /// Size in bytes of buffer bindings, 0 for other bindings
+  pub size: u32,
+  pub members: Vec<UniformMember>,
 }
 
 impl Ord for ShaderBindingInfo {
@@ -35,11 +48,19 @@ impl PartialOrd for ShaderBindingInfo {
 /// Holds bindings sorted by group first, binding second
 pub struct Shader {
   module: Module,
+  source: String,
   pub bindings: Vec<ShaderBindingInfo>,
 }
 
 impl Shader {
-  pub fn new(module: Module) -> Self {
+  pub fn from_wgsl(source: &str) -> Result<Self> {
+    let module = naga::front::wgsl::parse_str(source)
+      .map_err(|e| anyhow!(e.emit_to_string(source)))?;
+    Ok(Self::new(module, source.to_string()))
+  }
+
+  /// `source` is the WGSL code `module` was parsed from
+  pub fn new(module: Module, source: String) -> Self {
     let mut bindings: Vec<ShaderBindingInfo> = Vec::new();
 
     for (_, global) in module.global_variables.iter() {
@@ -47,19 +68,52 @@ impl Shader {
 
       if let Some(ref binding) = global.binding {
         match get_binding_type(ty, global) {
-          Ok(binding_type) => bindings.push(ShaderBindingInfo {
-            group: binding.group,
-            binding: binding.binding,
-            ty: binding_type,
-          }),
+          Ok(binding_type) => {
+            let (size, members) = get_struct_layout(&module, ty);
+            bindings.push(ShaderBindingInfo {
+              group: binding.group,
+              binding: binding.binding,
+              ty: binding_type,
+              name: global.name.clone(),
+              size,
+              members,
+            })
+          }
           Err(e) => log::error!("{}", e),
         }
       }
     }
 
     bindings.sort();
-    Shader { module, bindings }
+    Shader { module, source, bindings }
   }
+
+  pub fn module(&self) -> &Module {
+    &self.module
+  }
+
+  pub fn source(&self) -> &str {
+    &self.source
+  }
+}
+
+fn get_struct_layout(module: &Module, ty: &Type) -> (u32, Vec<UniformMember>) {
+  let TypeInner::Struct { ref members, span } = ty.inner else {
+    return (0, Vec::new());
+  };
+
+  let members = members
+    .iter()
+    .filter_map(|member| {
+      Some(UniformMember {
+        name: member.name.clone()?,
+        offset: member.offset,
+        size: module.types[member.ty].inner.size(module.to_ctx()),
+      })
+    })
+    .collect();
+
+  (span, members)
 }
 
 fn get_binding_type(ty: &Type, global: &GlobalVariable) -> Result<BindingType> {
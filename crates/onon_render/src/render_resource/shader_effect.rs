@@ -8,36 +8,40 @@ use crate::render_resource::{Shader, shader::ShaderBindingInfo};
 
 pub struct ShaderEffect {
   shader: Rc<Shader>,
+  module: wgpu::ShaderModule,
   pipeline_layout: PipelineLayout,
   bind_group_layouts: Vec<BindGroupLayout>,
+  shared_groups: u32,
 }
 
 impl ShaderEffect {
-  pub fn new(device: &Device, shader: Rc<Shader>) -> Self {
+  /// The first groups use `shared_layouts` instead of reflected layouts, so
+  /// bind groups owned by the renderer can be bound with this effect.
+  pub fn new(device: &Device, shader: Rc<Shader>, shared_layouts: &[&BindGroupLayout]) -> Self {
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Shader Effect Module"),
+      source: wgpu::ShaderSource::Wgsl(shader.source().into()),
+    });
+
     // Bindings are sorted by group first, binding second
-    let last_group = shader.bindings.last();
-    let Some(last_group) = last_group else {
-      return Self {
-        shader,
-        pipeline_layout: device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-          label: Some("Render Pipeline Layout"),
-          bind_group_layouts: &[],
-          immediate_size: 12,
-        }),
-        bind_group_layouts: Vec::new(),
-      };
-    };
-
-    let groups_count = last_group.group as usize + 1;
+    let groups_count = shader
+      .bindings
+      .last()
+      .map_or(0, |last_group| last_group.group as usize + 1)
+      .max(shared_layouts.len());
     let mut groups: Vec<Vec<&ShaderBindingInfo>> = vec![Vec::new(); groups_count];
 
     for binding in &shader.bindings {
-      groups[binding.group as usize].push(&binding);
+      groups[binding.group as usize].push(binding);
     }
 
     let bind_group_layouts: Vec<BindGroupLayout> = groups
       .into_iter()
-      .map(|group_binding_infos| create_layout_from_bindings(device, &group_binding_infos))
+      .enumerate()
+      .map(|(group, group_binding_infos)| match shared_layouts.get(group) {
+        Some(&layout) => layout.clone(),
+        None => create_layout_from_bindings(device, &group_binding_infos),
+      })
       .collect();
 
     let bind_group_layout_refs: Vec<&wgpu::BindGroupLayout> = bind_group_layouts.iter().collect();
@@ -45,15 +49,42 @@ impl ShaderEffect {
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Render Pipeline Layout"),
       bind_group_layouts: &bind_group_layout_refs,
-      immediate_size: 12,
+      immediate_size: 0,
     });
 
     Self {
       shader,
+      module,
       pipeline_layout,
       bind_group_layouts,
+      shared_groups: shared_layouts.len() as u32,
     }
   }
+
+  pub fn shader(&self) -> &Shader {
+    &self.shader
+  }
+
+  pub fn module(&self) -> &wgpu::ShaderModule {
+    &self.module
+  }
+
+  pub fn pipeline_layout(&self) -> &PipelineLayout {
+    &self.pipeline_layout
+  }
+
+  pub fn bind_group_layout(&self, group: u32) -> Option<&BindGroupLayout> {
+    self.bind_group_layouts.get(group as usize)
+  }
+
+  pub fn group_count(&self) -> u32 {
+    self.bind_group_layouts.len() as u32
+  }
+
+  /// Number of leading groups whose bind groups are provided by the renderer
+  pub fn shared_groups(&self) -> u32 {
+    self.shared_groups
+  }
 }
 
 fn create_layout_from_bindings(
@@ -1,3 +1,5 @@
+use crate::render_resource::MipmapGenerator;
+
 pub struct Texture {
   texture: wgpu::Texture,
   view: wgpu::TextureView,
@@ -9,20 +11,34 @@ impl Texture {
     device: &wgpu::Device,
     sampler: std::sync::Arc<wgpu::Sampler>,
     size: wgpu::Extent3d,
-    format: wgpu::TextureFormat
+    format: wgpu::TextureFormat,
+    mip_level_count: u32,
+    extra_usage: wgpu::TextureUsages,
   ) -> Self {
+    // Mips are rendered by the `MipmapGenerator`, when the format allows it
+    let render_usage = format.guaranteed_format_features(device.features()).allowed_usages
+      & wgpu::TextureUsages::RENDER_ATTACHMENT;
     let texture = device.create_texture(&wgpu::TextureDescriptor {
       size,
-      mip_level_count: 1,
+      mip_level_count,
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
       format,
-      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      // Copied from when the array grows
+      usage: wgpu::TextureUsages::TEXTURE_BINDING
+        | wgpu::TextureUsages::COPY_DST
+        | wgpu::TextureUsages::COPY_SRC
+        | render_usage
+        | extra_usage,
       label: Some("texture array"),
       view_formats: &[],
     });
 
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    // Arrays of a single layer would default to a `D2` view
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::D2Array),
+      ..Default::default()
+    });
 
     Self {
       texture,
@@ -31,11 +47,13 @@ impl Texture {
     }
   }
 
+  /// Generates the whole mip chain when given a `mipmaps` generator
   pub fn from_image(
     device: &wgpu::Device,
     queue: wgpu::Queue,
     img: image::DynamicImage,
     sampler: std::sync::Arc<wgpu::Sampler>,
+    mipmaps: Option<&MipmapGenerator>,
   ) -> Self {
     let diffuse_rgba = img.to_rgba8();
 
@@ -47,13 +65,17 @@ impl Texture {
       depth_or_array_layers: 1,
     };
 
+    let (mip_level_count, mip_usage) = match mipmaps {
+      Some(_) => (MipmapGenerator::level_count(size), wgpu::TextureUsages::RENDER_ATTACHMENT),
+      None => (1, wgpu::TextureUsages::empty()),
+    };
     let texture = device.create_texture(&wgpu::TextureDescriptor {
       size,
-      mip_level_count: 1,
+      mip_level_count,
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
       format: wgpu::TextureFormat::Rgba8UnormSrgb,
-      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | mip_usage,
       label: Some("diffuse texture"),
       view_formats: &[],
     });
@@ -73,6 +95,9 @@ impl Texture {
       },
       size,
     );
+    if let Some(mipmaps) = mipmaps {
+      mipmaps.generate(device, &queue, &texture, 0..1);
+    }
 
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
@@ -1,63 +1,315 @@
-use crate::render_resource::Texture;
-use anyhow::Result;
+use crate::render_resource::{
+  MipmapGenerator, Texture,
+  sampler_cache::SamplerId,
+  compressed::{self, CompressedImage},
+  image_format::{self, DecodedImage},
+};
+use anyhow::{Context, Result, anyhow};
 use std::collections::{HashMap, VecDeque};
 
+/// Handle to a texture array or atlas of the `TextureManager`, objects draw with
+/// the array it names
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureArrayId(pub u32);
+
 /// Struct used to store the important elements, that differ with
 /// each texture array.
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct TextureArrayInfo {
+  /// Layer size, `depth_or_array_layers` being the initial layer count
   pub dims: wgpu::Extent3d,
+  /// Images are converted to it, see `image_format::pixels_for_format` for the ones
+  /// images can go into. Block compressed arrays only take `CompressedImage`s of the
+  /// same format and size.
+  pub format: wgpu::TextureFormat,
+  /// Added to the usages every array has: binding, copies and rendering when the format allows it
+  pub usage: wgpu::TextureUsages,
+  /// The array grows up to this many layers, uploads fail past it
+  pub max_layers: u32,
+  /// How textures of another size than the layers are stored
+  pub fit: TextureFit,
+  /// Generates the mip chain of layers when they are written,
+  /// the sampler needs a `Linear` mipmap filter for trilinear filtering.
+  /// Compressed arrays take their mips from the uploaded images instead, and
+  /// formats that aren't filterable and renderable don't get any.
+  pub mipmaps: bool,
   pub sampler: std::sync::Arc<wgpu::Sampler>,
   pub bind_group_layout: wgpu::BindGroupLayout,
+  /// Multiplies the colors of uploaded images by their alpha, for `BlendMode::PremultipliedAlpha`.
+  /// Compressed images and raw texels are uploaded as they are.
+  pub premultiply_alpha: bool,
+  /// Lets images with more channels than the format, such as RGB images going into an
+  /// `R8Unorm` array, keep their first channels instead of failing to upload
+  pub truncate_channels: bool,
+}
+
+/// How a texture whose size differs from the layer size is stored in its layer
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum TextureFit {
+  /// Stretched to the layer size
+  Resize,
+  /// Kept at its size in the top left corner of the layer, shaders scale
+  /// their UVs by the slot's `uv_scale`. Larger textures are shrunk to fit,
+  /// keeping their aspect ratio. The edge texels are repeated over the rest
+  /// of the layer, so they don't fade into it when filtered.
+  #[default]
+  Pad,
+}
+
+/// Where the texture of a slot lies in its layer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SlotRegion {
+  /// Size of the uploaded texture, before it was fitted
+  pub size: glam::UVec2,
+  /// Part of the layer covered by the texture, UVs of `[0, 1]` are multiplied by it
+  pub uv_scale: glam::Vec2,
+}
+
+/// Returned when a texture array already reached `TextureArrayInfo::max_layers`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureArrayFull {
+  pub max_layers: u32,
+}
+
+impl std::fmt::Display for TextureArrayFull {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Texture array is full ({} layers)", self.max_layers)
+  }
+}
+
+impl std::error::Error for TextureArrayFull {}
+
+/// What a texture array does with textures no object references anymore
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+  /// Textures stay loaded until they are unloaded explicitly
+  #[default]
+  Never,
+  /// Unreferenced textures are evicted, least recently used first, when
+  /// loading a new one would go over `budget` bytes or need the array to grow.
+  /// Every loaded texture counts as a full layer, so this is a budget of
+  /// `budget / layer_bytes` slots. The array never shrinks: eviction only makes
+  /// room for new textures, the GPU memory stays at the largest size it grew to.
+  Lru { budget: u64 },
+}
+
+/// Slot `set_placeholder` reserves, objects whose texture isn't loaded yet draw it
+pub const PLACEHOLDER_SLOT: u32 = 0;
+
+/// An image converted to the format of a texture array and fitted to its layers, ready to
+/// be copied into a slot. Fitting is the slow part of uploads, `TextureArray::fit_image`
+/// does it without the GPU so it can run on any thread.
+pub struct FittedImage {
+  /// Array the texels were made for, other arrays don't take them
+  info: TextureArrayInfo,
+  /// Size of the image before fitting
+  size: glam::UVec2,
+  fitted_size: glam::UVec2,
+  pixels: Vec<u8>,
+}
+
+impl FittedImage {
+  pub fn info(&self) -> &TextureArrayInfo {
+    &self.info
+  }
+}
+
+#[derive(Default)]
+struct SlotEntry {
+  allocated: bool,
+  region: Option<SlotRegion>,
+  path: Option<std::path::PathBuf>,
+  refs: u32,
+  last_used: u64,
 }
 
 pub struct TextureArray {
   pub texture: Texture,
   pub bind_group: wgpu::BindGroup,
   info: TextureArrayInfo,
+  id: TextureArrayId,
 
   free_slots: VecDeque<u32>,
+  slots: Vec<SlotEntry>,
   cache: HashMap<std::path::PathBuf, u32>,
+  eviction: EvictionPolicy,
+  frame: u64,
+  /// Whether `PLACEHOLDER_SLOT` is reserved for the placeholder
+  placeholder: bool,
+  mipmaps: Option<MipmapGenerator>,
+  /// Bind groups sampling with other samplers than `TextureArrayInfo::sampler`
+  sampler_bind_groups: HashMap<SamplerId, (std::sync::Arc<wgpu::Sampler>, wgpu::BindGroup)>,
 }
 
 impl TextureArray {
-  pub fn new(device: &wgpu::Device, info: &TextureArrayInfo) -> Self {
+  pub fn new(device: &wgpu::Device, info: &TextureArrayInfo, id: TextureArrayId) -> Self {
+    let layers = info.dims.depth_or_array_layers.min(info.max_layers);
+    let format = info.format;
     let texture = Texture::create_array(
-      &device,
+      device,
       info.sampler.clone(),
-      info.dims,
-      wgpu::TextureFormat::Rgba8UnormSrgb,
+      wgpu::Extent3d {
+        depth_or_array_layers: layers,
+        ..info.dims
+      },
+      format,
+      mip_level_count(device, info),
+      info.usage,
     );
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      layout: &info.bind_group_layout,
-      entries: &[
-        wgpu::BindGroupEntry {
-          binding: 0,
-          resource: wgpu::BindingResource::TextureView(&texture.view()),
-        },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::Sampler(&texture.sampler()),
-        },
-      ],
-      label: Some("diffuse_bind_group"),
-    });
+    let bind_group = create_bind_group(device, info, &texture, &info.sampler);
 
     Self {
       texture,
       bind_group,
-      free_slots: (0..info.dims.depth_or_array_layers).collect(),
+      free_slots: (0..layers).collect(),
+      slots: (0..layers).map(|_| SlotEntry::default()).collect(),
       info: info.clone(),
+      id,
       cache: HashMap::new(),
+      eviction: EvictionPolicy::default(),
+      frame: 0,
+      placeholder: false,
+      mipmaps: (info.mipmaps && MipmapGenerator::supports(format, device.features()))
+        .then(|| MipmapGenerator::new(device, format)),
+      sampler_bind_groups: HashMap::new(),
     }
   }
 
+  pub fn info(&self) -> &TextureArrayInfo {
+    &self.info
+  }
+
+  /// Id given by the `TextureManager`, also used to tell bind groups apart when sorting draws
+  pub fn id(&self) -> TextureArrayId {
+    self.id
+  }
+
+  /// Slot of the texture cached under `path`, a file path or the key it was loaded from memory with
   pub fn by_path(&self, path: &std::path::Path) -> Option<&u32> {
     self.cache.get(path)
   }
 
+  /// Path or key of the texture cached in `slot`, `None` for slots written without one
+  pub fn path_of(&self, slot: u32) -> Option<&std::path::Path> {
+    self.slots.get(slot as usize).and_then(|entry| entry.path.as_deref())
+  }
+
+  /// Creates the bind group sampling the array with `sampler`, see `bind_group_for`.
+  /// `sampler` can't be a comparison sampler.
+  pub fn prepare_sampler(&mut self, device: &wgpu::Device, id: SamplerId, sampler: &std::sync::Arc<wgpu::Sampler>) {
+    if !self.sampler_bind_groups.contains_key(&id) {
+      let bind_group = create_bind_group(device, &self.info, &self.texture, sampler);
+      self.sampler_bind_groups.insert(id, (sampler.clone(), bind_group));
+    }
+  }
+
+  /// Bind group sampling with `sampler`, the array's own sampler being
+  /// used for `None` and samplers that weren't prepared
+  pub fn bind_group_for(&self, sampler: Option<SamplerId>) -> &wgpu::BindGroup {
+    sampler
+      .and_then(|id| self.sampler_bind_groups.get(&id))
+      .map_or(&self.bind_group, |(_, bind_group)| bind_group)
+  }
+
+  pub fn layer_size(&self) -> glam::UVec2 {
+    glam::uvec2(self.info.dims.width, self.info.dims.height)
+  }
+
+  pub fn layer_count(&self) -> u32 {
+    self.texture.texture().depth_or_array_layers()
+  }
+
+  pub fn set_eviction_policy(&mut self, eviction: EvictionPolicy) {
+    self.eviction = eviction;
+  }
+
+  /// Bytes used by the loaded textures, every slot takes a full layer
+  pub fn memory_usage(&self) -> u64 {
+    let loaded = self.slots.iter().filter(|entry| entry.allocated).count() as u64;
+    loaded * self.layer_bytes()
+  }
+
+  /// Bytes of the whole texture, free layers included
+  pub fn allocated_memory(&self) -> u64 {
+    self.layer_count() as u64 * self.layer_bytes()
+  }
+
+  /// Starts a new frame for the LRU policy
+  pub fn advance_frame(&mut self) {
+    self.frame += 1;
+  }
+
+  /// Marks the slot as used this frame, textures used this frame are never evicted
+  pub fn touch(&mut self, slot: u32) {
+    if let Some(entry) = self.slots.get_mut(slot as usize) {
+      entry.last_used = self.frame;
+    }
+  }
+
+  /// Adds a reference to the slot, referenced slots are never evicted or unloaded
+  pub fn retain(&mut self, slot: u32) {
+    if let Some(entry) = self.slots.get_mut(slot as usize).filter(|entry| entry.allocated) {
+      entry.refs += 1;
+    }
+  }
+
+  /// Removes a reference, the texture stays loaded until it is unloaded or evicted
+  pub fn release(&mut self, slot: u32) {
+    if let Some(entry) = self.slots.get_mut(slot as usize) {
+      entry.refs = entry.refs.saturating_sub(1);
+    }
+  }
+
+  /// Region of the slot's texture, the whole layer for unknown slots
+  pub fn region(&self, slot: u32) -> SlotRegion {
+    let layer = glam::uvec2(self.info.dims.width, self.info.dims.height);
+    self
+      .slots
+      .get(slot as usize)
+      .and_then(|entry| entry.region)
+      .unwrap_or(SlotRegion {
+        size: layer,
+        uv_scale: glam::Vec2::ONE,
+      })
+  }
+
+  pub fn ref_count(&self, slot: u32) -> u32 {
+    self.slots.get(slot as usize).map_or(0, |entry| entry.refs)
+  }
+
+  /// Frees the slot the file at `path` was loaded into, see `unload_slot`
+  pub fn unload_path(&mut self, path: &std::path::Path) -> bool {
+    match self.cache.get(path) {
+      Some(&slot) => self.unload_slot(slot),
+      None => false,
+    }
+  }
+
+  /// Frees the slot so the next upload can reuse it.
+  /// Returns false when the slot isn't loaded or is still referenced.
+  pub fn unload_slot(&mut self, slot: u32) -> bool {
+    let Some(entry) = self.slots.get_mut(slot as usize) else {
+      return false;
+    };
+    if !entry.allocated {
+      return false;
+    }
+    if entry.refs > 0 {
+      log::warn!("Texture slot {} is still referenced {} times", slot, entry.refs);
+      return false;
+    }
+
+    if let Some(path) = entry.path.take() {
+      self.cache.remove(&path);
+    }
+    *entry = SlotEntry::default();
+    self.free_slots.push_back(slot);
+    true
+  }
+
   pub fn load_from_file<P: AsRef<std::path::Path>>(
     &mut self,
+    device: &wgpu::Device,
     queue: &wgpu::Queue,
     path: P,
   ) -> Result<u32> {
@@ -68,35 +320,525 @@ impl TextureArray {
     }
 
     let bytes = std::fs::read(path_ref)?;
-    let image = image::load_from_memory(&bytes)?;
-    let rgba = image.to_rgba8();
+    self.load_from_memory(device, queue, path_ref, &bytes)
+  }
+
+  /// Decodes an encoded image, such as `include_bytes!` data or a fetched file, and
+  /// caches it under `key` like a path. The format is guessed from the data.
+  pub fn load_from_memory<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    bytes: &[u8],
+  ) -> Result<u32> {
+    let key = key.as_ref();
+
+    if let Some(slot) = self.by_path(key) {
+      return Ok(*slot);
+    }
+
+    let image = image_format::decode(bytes).context(format!("{:?}", key))?;
+    self.load_decoded(device, queue, key, &image)
+  }
+
+  /// Uploads texels already in the array's format, tightly packed, cached under `key`
+  /// like a path. They can't be fitted, so they must be the layer size for `Resize`
+  /// arrays and no larger than it for `Pad` ones.
+  pub fn load_pixels<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    data: &[u8],
+    width: u32,
+    height: u32,
+  ) -> Result<u32> {
+    let key = key.as_ref();
+
+    if let Some(slot) = self.by_path(key) {
+      return Ok(*slot);
+    }
+
+    let format = self.info.format;
+    let size = glam::uvec2(width, height);
+    let expected = compressed::level_byte_size(format, size);
+    if format.is_compressed() || data.len() as u64 != expected {
+      return Err(anyhow!(
+        "{} bytes don't make {}x{} {:?} texels, expected {} bytes",
+        data.len(),
+        width,
+        height,
+        format,
+        expected
+      ));
+    }
+    let layer = self.layer_size();
+    let fits = match self.info.fit {
+      TextureFit::Resize => size == layer,
+      TextureFit::Pad => size.cmple(layer).all(),
+    };
+    if !fits || size.min_element() == 0 {
+      return Err(anyhow!("{}x{} texels don't fit the {}x{} layers", width, height, layer.x, layer.y));
+    }
+
+    let slot = self.allocate_slot(device, queue)?;
+    self.slots[slot as usize].region = Some(SlotRegion {
+      size,
+      uv_scale: size.as_vec2() / layer.as_vec2(),
+    });
+    let (data, extended) = self.extrude_padding(data, size);
+    self.write_region(queue, slot, glam::UVec2::ZERO, &data, extended.x, extended.y);
+    self.update_mipmaps(device, queue, slot);
+
+    self.cache.insert(key.to_path_buf(), slot);
+    self.slots[slot as usize].path = Some(key.to_path_buf());
+    Ok(slot)
+  }
+
+  /// Uploads RGBA8 pixels, converted to the array's format, cached under `key` like a path
+  pub fn load_raw<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    data: &[u8],
+    width: u32,
+    height: u32,
+  ) -> Result<u32> {
+    let key = key.as_ref();
+
+    if let Some(slot) = self.by_path(key) {
+      return Ok(*slot);
+    }
+
+    let image = image::RgbaImage::from_raw(width, height, data.to_vec())
+      .context(format!("{} bytes don't make a {}x{} RGBA8 image", data.len(), width, height))?;
+    self.load_decoded(device, queue, key, &DecodedImage::Image(image.into()))
+  }
+
+  /// Uploads an image decoded ahead of time or generated, cached under `path` like `load_from_file`
+  pub fn load_decoded(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &std::path::Path,
+    image: &DecodedImage,
+  ) -> Result<u32> {
+    if let Some(slot) = self.by_path(path) {
+      return Ok(*slot);
+    }
 
-    use image::GenericImageView;
-    let dimensions = image.dimensions();
+    let slot = self.write_decoded(device, queue, None, image)?;
+    self.cache.insert(path.to_path_buf(), slot);
+    self.slots[slot as usize].path = Some(path.to_path_buf());
 
-    let slot = self.upload_texture(queue, &rgba, dimensions.0, dimensions.1);
-    self.cache.insert(path_ref.to_path_buf(), slot);
+    Ok(slot)
+  }
+
+  /// Uploads an image fitted ahead of time by `fit_image` for this array, cached under
+  /// `path` like `load_decoded`
+  pub fn load_fitted(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &std::path::Path,
+    image: &FittedImage,
+  ) -> Result<u32> {
+    if let Some(slot) = self.by_path(path) {
+      return Ok(*slot);
+    }
+
+    let slot = self.write_fitted(device, queue, None, image)?;
+    self.cache.insert(path.to_path_buf(), slot);
+    self.slots[slot as usize].path = Some(path.to_path_buf());
 
     Ok(slot)
   }
 
-  /// This function is not recommended to use as it does not cache texture
+  /// Overwrites the slot of the texture cached under `path` with a new version of it,
+  /// loading it like `load_decoded` when it isn't cached. Objects keep their slot, the
+  /// renderer updates their `uv_scale` when the new version has another size.
+  pub fn reload_decoded(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &std::path::Path,
+    image: &DecodedImage,
+  ) -> Result<u32> {
+    match self.by_path(path) {
+      Some(&slot) => self.write_decoded(device, queue, Some(slot), image),
+      None => self.load_decoded(device, queue, path, image),
+    }
+  }
+
+  /// `reload_decoded` for an image fitted ahead of time, like `load_fitted`
+  pub fn reload_fitted(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &std::path::Path,
+    image: &FittedImage,
+  ) -> Result<u32> {
+    match self.by_path(path) {
+      Some(&slot) => self.write_fitted(device, queue, Some(slot), image),
+      None => self.load_fitted(device, queue, path, image),
+    }
+  }
+
+  /// Fills `PLACEHOLDER_SLOT` with `image` stretched to the layer, reserving the slot
+  /// the first time. It must be called before anything else is uploaded, the slot is
+  /// never evicted nor unloaded. Compressed arrays can't have a placeholder.
+  pub fn set_placeholder(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &image::DynamicImage,
+  ) -> Result<()> {
+    let placeholder = Self::fit_placeholder(&self.info, image)?;
+    self.write_placeholder(device, queue, &placeholder)
+  }
+
+  /// `image` stretched to the layers of `info`, for `write_placeholder`
+  pub fn fit_placeholder(info: &TextureArrayInfo, image: &image::DynamicImage) -> Result<FittedImage> {
+    if info.format.is_compressed() {
+      return Err(anyhow!("{:?} texture arrays can't have a placeholder", info.format));
+    }
+
+    let layer = glam::uvec2(info.dims.width, info.dims.height);
+    let fitted = image.resize_exact(layer.x, layer.y, image::imageops::FilterType::Nearest);
+    Ok(FittedImage {
+      info: info.clone(),
+      size: layer,
+      fitted_size: layer,
+      pixels: image_format::pixels_for_format(&fitted, info.format)?,
+    })
+  }
+
+  /// Fills `PLACEHOLDER_SLOT` with a placeholder from `fit_placeholder`, see `set_placeholder`
+  pub fn write_placeholder(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, placeholder: &FittedImage) -> Result<()> {
+    self.reserve_placeholder()?;
+    self.check_fitted(placeholder)?;
+    let layer = self.layer_size();
+    if placeholder.fitted_size != layer {
+      return Err(anyhow!("Placeholders fill the whole layer"));
+    }
+    self.write_region(queue, PLACEHOLDER_SLOT, glam::UVec2::ZERO, &placeholder.pixels, layer.x, layer.y);
+    self.update_mipmaps(device, queue, PLACEHOLDER_SLOT);
+    Ok(())
+  }
+
+  /// Keeps `PLACEHOLDER_SLOT` for the placeholder, it is empty until one is written
+  pub fn reserve_placeholder(&mut self) -> Result<()> {
+    if self.info.format.is_compressed() {
+      return Err(anyhow!("{:?} texture arrays can't have a placeholder", self.info.format));
+    }
+
+    if self.placeholder {
+      return Ok(());
+    }
+    if self.free_slots.front() != Some(&PLACEHOLDER_SLOT) {
+      return Err(anyhow!("Slot {} of texture array {} is already used", PLACEHOLDER_SLOT, self.id.0));
+    }
+    self.free_slots.pop_front();
+    self.slots[PLACEHOLDER_SLOT as usize] = SlotEntry {
+      allocated: true,
+      refs: 1,
+      ..Default::default()
+    };
+    self.placeholder = true;
+    Ok(())
+  }
+
+  pub fn has_placeholder(&self) -> bool {
+    self.placeholder
+  }
+
+  /// Uploads RGBA8 pixels without caching them, see `load_raw` and `upload_image`.
   pub fn upload_texture(
     &mut self,
+    device: &wgpu::Device,
     queue: &wgpu::Queue,
     data: &[u8],
     width: u32,
     height: u32,
-  ) -> u32 {
-    let slot = self.free_slots.pop_front().expect("Texture Array is full!");
+  ) -> Result<u32> {
+    let image = image::RgbaImage::from_raw(width, height, data.to_vec())
+      .context(format!("{} bytes don't make a {}x{} RGBA8 image", data.len(), width, height))?;
+    self.upload_image(device, queue, &image::DynamicImage::ImageRgba8(image))
+  }
+
+  /// Converts the image to the array's format and fits it to the layer according to
+  /// `TextureArrayInfo::fit`. Grows the array when it is full, fails with
+  /// `TextureArrayFull` past `max_layers`.
+  pub fn upload_image(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &image::DynamicImage,
+  ) -> Result<u32> {
+    self.write_image(device, queue, None, image)
+  }
+
+  /// Uploads an image in the array's format, with as many of its mips as the array has.
+  /// Compressed images can't be fitted, they must be the size of the layers.
+  pub fn upload_compressed(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &CompressedImage,
+  ) -> Result<u32> {
+    self.write_compressed(device, queue, None, image)
+  }
+
+  /// Writes the image into `slot`, or a newly allocated one when `None`
+  fn write_decoded(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    slot: Option<u32>,
+    image: &DecodedImage,
+  ) -> Result<u32> {
+    match image {
+      DecodedImage::Compressed(image) if image.is_compressed() && image.format == self.info.format => {
+        self.write_compressed(device, queue, slot, image)
+      }
+      _ => {
+        let image = Self::fit_decoded(&self.info, image)?.context("Compressed images are uploaded as they are")?;
+        self.write_fitted(device, queue, slot, &image)
+      }
+    }
+  }
+
+  /// Fits a decoded image to the layers of `info` like `fit_image`, decompressing it when
+  /// it's compressed in another format. `None` for images compressed in the array's format,
+  /// they are uploaded as they are.
+  pub fn fit_decoded(info: &TextureArrayInfo, image: &DecodedImage) -> Result<Option<FittedImage>> {
+    match image {
+      DecodedImage::Compressed(image) if image.is_compressed() && image.format == info.format => Ok(None),
+      DecodedImage::Compressed(image) => {
+        let image = image.clone().decompress()?.to_image()?;
+        Self::fit_image(info, &image).map(Some)
+      }
+      DecodedImage::Image(image) => Self::fit_image(info, image).map(Some),
+    }
+  }
+
+  /// Converts `image` to the format of `info` and fits it to its layers according to
+  /// `TextureArrayInfo::fit`, premultiplying its alpha when the array asks for it.
+  /// Doesn't touch the GPU, the texture loader runs it on its worker threads.
+  pub fn fit_image(info: &TextureArrayInfo, image: &image::DynamicImage) -> Result<FittedImage> {
+    if info.format.is_compressed() {
+      return Err(anyhow!("{:?} texture arrays only take compressed images", info.format));
+    }
+    image_format::check_source(image.color(), info.format, info.truncate_channels)?;
+    // Before fitting, so resizing doesn't bleed the color of transparent texels
+    let image = match info.premultiply_alpha {
+      true => image_format::premultiply_alpha(image),
+      false => std::borrow::Cow::Borrowed(image),
+    };
+    let image = image.as_ref();
+    let layer = glam::uvec2(info.dims.width, info.dims.height);
+    let size = glam::uvec2(image.width(), image.height());
+
+    let fitted_size = match info.fit {
+      TextureFit::Resize => layer,
+      TextureFit::Pad if size.cmple(layer).all() => size,
+      TextureFit::Pad => {
+        let scale = (layer.as_vec2() / size.as_vec2()).min_element();
+        (size.as_vec2() * scale).floor().as_uvec2().clamp(glam::UVec2::ONE, layer)
+      }
+    };
+    let pixels = if fitted_size == size {
+      image_format::pixels_for_format(image, info.format)?
+    } else {
+      let fitted = image.resize_exact(fitted_size.x, fitted_size.y, image::imageops::FilterType::Triangle);
+      image_format::pixels_for_format(&fitted, info.format)?
+    };
+
+    Ok(FittedImage {
+      info: info.clone(),
+      size,
+      fitted_size,
+      pixels,
+    })
+  }
+
+  fn check_fitted(&self, image: &FittedImage) -> Result<()> {
+    match image.info == self.info {
+      true => Ok(()),
+      false => Err(anyhow!("Image was fitted for another texture array than {}", self.id.0)),
+    }
+  }
+
+  fn write_image(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    slot: Option<u32>,
+    image: &image::DynamicImage,
+  ) -> Result<u32> {
+    let image = Self::fit_image(&self.info, image)?;
+    self.write_fitted(device, queue, slot, &image)
+  }
+
+  fn write_fitted(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    slot: Option<u32>,
+    image: &FittedImage,
+  ) -> Result<u32> {
+    self.check_fitted(image)?;
+    let slot = match slot {
+      Some(slot) => slot,
+      None => self.allocate_slot(device, queue)?,
+    };
+    self.slots[slot as usize].region = Some(SlotRegion {
+      size: image.size,
+      uv_scale: image.fitted_size.as_vec2() / self.layer_size().as_vec2(),
+    });
+
+    let (pixels, extended) = self.extrude_padding(&image.pixels, image.fitted_size);
+    self.write_region(queue, slot, glam::UVec2::ZERO, &pixels, extended.x, extended.y);
+    self.update_mipmaps(device, queue, slot);
+
+    Ok(slot)
+  }
+
+  /// Repeats the right and bottom edges of `size` texels padded into a layer over the
+  /// padding, so that filtering and mips don't blend the empty padding into the edges.
+  /// Returns the texels and their size, the whole layer when the array has mips.
+  fn extrude_padding<'a>(&self, data: &'a [u8], size: glam::UVec2) -> (std::borrow::Cow<'a, [u8]>, glam::UVec2) {
+    let layer = self.layer_size();
+    if size == layer {
+      return (std::borrow::Cow::Borrowed(data), size);
+    }
+
+    // Bilinear filtering reaches a texel past the edge, each mip level twice as far as the last
+    let extended = match self.mipmaps {
+      Some(_) => layer,
+      None => (size + 1).min(layer),
+    };
+    let texel_size = self.info.format.block_copy_size(None).unwrap_or(4) as usize;
+    let row_size = size.x as usize * texel_size;
+    let mut extruded = Vec::with_capacity(extended.x as usize * extended.y as usize * texel_size);
+    for y in 0..extended.y.min(size.y) {
+      let row = &data[y as usize * row_size..(y as usize + 1) * row_size];
+      extruded.extend_from_slice(row);
+      let edge = &row[row_size - texel_size..];
+      for _ in size.x..extended.x {
+        extruded.extend_from_slice(edge);
+      }
+    }
+    let extended_row_size = extended.x as usize * texel_size;
+    let last_row = extruded.len() - extended_row_size;
+    for _ in size.y..extended.y {
+      extruded.extend_from_within(last_row..last_row + extended_row_size);
+    }
+    (std::borrow::Cow::Owned(extruded), extended)
+  }
+
+  fn write_compressed(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    slot: Option<u32>,
+    image: &CompressedImage,
+  ) -> Result<u32> {
+    let layer = self.layer_size();
+    if image.format != self.info.format || image.size != layer {
+      return Err(anyhow!(
+        "{:?} {}x{} image doesn't match the {:?} {}x{} layers",
+        image.format,
+        image.size.x,
+        image.size.y,
+        self.info.format,
+        layer.x,
+        layer.y
+      ));
+    }
+
+    let slot = match slot {
+      Some(slot) => slot,
+      None => self.allocate_slot(device, queue)?,
+    };
+    let texture = self.texture.texture();
+    let level_count = texture.mip_level_count().min(image.levels.len() as u32);
+    if level_count < texture.mip_level_count() {
+      log::warn!("Image has {} mips, texture array {} expects {}", level_count, self.id.0, texture.mip_level_count());
+    }
+
+    let (block_width, block_height) = image.format.block_dimensions();
+    let block_size = image.format.block_copy_size(None).unwrap_or(4);
+    for (level, data) in image.levels.iter().take(level_count as usize).enumerate() {
+      let size = compressed::mip_size(layer, level as u32);
+      let extent = wgpu::Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+      }
+      .physical_size(image.format);
+
+      queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+          texture,
+          mip_level: level as u32,
+          origin: wgpu::Origin3d {
+            x: 0,
+            y: 0,
+            z: slot,
+          },
+          aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::TexelCopyBufferLayout {
+          offset: 0,
+          bytes_per_row: Some(extent.width / block_width * block_size),
+          rows_per_image: Some(extent.height / block_height),
+        },
+        extent,
+      );
+    }
 
+    if level_count == 1 {
+      self.update_mipmaps(device, queue, slot);
+    }
+    Ok(slot)
+  }
+
+  /// Allocates an empty layer, filled by the caller with `write_region`.
+  /// The layer is retained once so it never gets evicted.
+  pub(crate) fn allocate_layer(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<u32> {
+    let slot = self.allocate_slot(device, queue)?;
+    self.retain(slot);
+    Ok(slot)
+  }
+
+  /// Regenerates the mips of the layer from its first level, when the array has mipmaps
+  pub(crate) fn update_mipmaps(&self, device: &wgpu::Device, queue: &wgpu::Queue, slot: u32) {
+    if let Some(mipmaps) = &self.mipmaps {
+      mipmaps.generate(device, queue, self.texture.texture(), slot..slot + 1);
+    }
+  }
+
+  /// Writes `data`, texels of the array's format, at `origin` in the layer of `slot`
+  pub(crate) fn write_region(
+    &self,
+    queue: &wgpu::Queue,
+    slot: u32,
+    origin: glam::UVec2,
+    data: &[u8],
+    width: u32,
+    height: u32,
+  ) {
     queue.write_texture(
       wgpu::TexelCopyTextureInfo {
         texture: self.texture.texture(),
         mip_level: 0,
         origin: wgpu::Origin3d {
-          x: 0,
-          y: 0,
+          x: origin.x,
+          y: origin.y,
           z: slot,
         },
         aspect: wgpu::TextureAspect::All,
@@ -104,7 +846,7 @@ impl TextureArray {
       data,
       wgpu::TexelCopyBufferLayout {
         offset: 0,
-        bytes_per_row: Some(width * 4),
+        bytes_per_row: Some(width * self.info.format.block_copy_size(None).unwrap_or(4)),
         rows_per_image: Some(height),
       },
       wgpu::Extent3d {
@@ -113,7 +855,137 @@ impl TextureArray {
         depth_or_array_layers: 1,
       },
     );
+  }
+
+  fn allocate_slot(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<u32> {
+    if let EvictionPolicy::Lru { budget } = self.eviction {
+      while self.memory_usage() + self.layer_bytes() > budget && self.evict_one() {}
+      if self.free_slots.is_empty() {
+        self.evict_one();
+      }
+    }
+    if self.free_slots.is_empty() {
+      self.grow(device, queue)?;
+    }
+
+    let slot = self.free_slots.pop_front().expect("Texture Array is full!");
+    self.slots[slot as usize] = SlotEntry {
+      allocated: true,
+      last_used: self.frame,
+      ..Default::default()
+    };
+    Ok(slot)
+  }
+
+  /// Unloads the least recently used unreferenced texture not used this frame
+  fn evict_one(&mut self) -> bool {
+    let victim = self
+      .slots
+      .iter()
+      .enumerate()
+      .filter(|(_, entry)| entry.allocated && entry.refs == 0 && entry.last_used < self.frame)
+      .min_by_key(|(_, entry)| entry.last_used)
+      .map(|(slot, _)| slot as u32);
+
+    match victim {
+      Some(slot) => {
+        log::debug!("Evicting slot {} of texture array {}", slot, self.id.0);
+        self.unload_slot(slot)
+      }
+      None => false,
+    }
+  }
+
+  fn layer_bytes(&self) -> u64 {
+    let texture = self.texture.texture();
+    (0..texture.mip_level_count())
+      .map(|level| compressed::level_byte_size(texture.format(), compressed::mip_size(self.layer_size(), level)))
+      .sum()
+  }
+
+  /// Doubles the layer count, copying the existing layers on the GPU.
+  /// Slots keep their layer, only the texture and the bind group change.
+  fn grow(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<()> {
+    let layers = self.layer_count();
+    if layers >= self.info.max_layers {
+      return Err(TextureArrayFull {
+        max_layers: self.info.max_layers,
+      }
+      .into());
+    }
+
+    let new_layers = (layers * 2).clamp(layers + 1, self.info.max_layers);
+    let texture = Texture::create_array(
+      device,
+      self.info.sampler.clone(),
+      wgpu::Extent3d {
+        depth_or_array_layers: new_layers,
+        ..self.info.dims
+      },
+      self.texture.texture().format(),
+      self.texture.texture().mip_level_count(),
+      self.info.usage,
+    );
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Texture Array Grow Encoder"),
+    });
+    let old = self.texture.texture();
+    for mip_level in 0..old.mip_level_count() {
+      encoder.copy_texture_to_texture(
+        wgpu::TexelCopyTextureInfo {
+          mip_level,
+          ..old.as_image_copy()
+        },
+        wgpu::TexelCopyTextureInfo {
+          mip_level,
+          ..texture.texture().as_image_copy()
+        },
+        old.size().mip_level_size(mip_level, wgpu::TextureDimension::D2),
+      );
+    }
+    queue.submit(Some(encoder.finish()));
 
-    slot
+    log::info!("Texture array {} grew from {} to {} layers", self.id.0, layers, new_layers);
+    self.bind_group = create_bind_group(device, &self.info, &texture, &self.info.sampler);
+    for (sampler, bind_group) in self.sampler_bind_groups.values_mut() {
+      *bind_group = create_bind_group(device, &self.info, &texture, sampler);
+    }
+    self.texture = texture;
+    self.free_slots.extend(layers..new_layers);
+    self.slots.resize_with(new_layers as usize, SlotEntry::default);
+    Ok(())
+  }
+}
+
+/// Compressed arrays get the mips of their images, others only the ones the generator can render
+fn mip_level_count(device: &wgpu::Device, info: &TextureArrayInfo) -> u32 {
+  let has_mips = info.format.is_compressed() || MipmapGenerator::supports(info.format, device.features());
+  if info.mipmaps && has_mips {
+    MipmapGenerator::level_count(info.dims)
+  } else {
+    1
   }
 }
+
+fn create_bind_group(
+  device: &wgpu::Device,
+  info: &TextureArrayInfo,
+  texture: &Texture,
+  sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+  device.create_bind_group(&wgpu::BindGroupDescriptor {
+    layout: &info.bind_group_layout,
+    entries: &[
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(texture.view()),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::Sampler(sampler),
+      },
+    ],
+    label: Some("diffuse_bind_group"),
+  })
+}
@@ -0,0 +1,329 @@
+use crate::{
+  rect::Rect,
+  render_resource::{TextureArray, image_format, texture_array::{TextureArrayId, TextureArrayInfo}},
+};
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+
+/// Where an image was packed in an atlas
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasEntry {
+  /// Page holding the image, used as the texture slot when drawing
+  pub page: u32,
+  /// Pixels of the image in its page, without the padding
+  pub rect: Rect,
+  /// `rect` normalized to the page size
+  pub uv_rect: Rect,
+}
+
+/// Packs images of any size into pages, the layers of a texture array.
+///
+/// Images are surrounded by `padding` pixels, filled by extruding their
+/// edges, so filtering at their borders doesn't bleed into the neighbours.
+pub struct TextureAtlas {
+  array: TextureArray,
+  padding: u32,
+  pages: Vec<(u32, Skyline)>,
+  cache: HashMap<std::path::PathBuf, AtlasEntry>,
+}
+
+impl TextureAtlas {
+  /// `info.dims` is the page size, `depth_or_array_layers` the initial page capacity
+  pub fn new(device: &wgpu::Device, info: &TextureArrayInfo, padding: u32, id: TextureArrayId) -> Self {
+    Self {
+      array: TextureArray::new(device, info, id),
+      padding,
+      pages: Vec::new(),
+      cache: HashMap::new(),
+    }
+  }
+
+  /// Texture array holding the pages, bound like any other texture array
+  pub fn texture_array(&self) -> &TextureArray {
+    &self.array
+  }
+
+  /// Mutable page array, for reference counts and LRU frames. Images must go through
+  /// the atlas so they get packed.
+  pub fn texture_array_mut(&mut self) -> &mut TextureArray {
+    &mut self.array
+  }
+
+  pub fn page_count(&self) -> u32 {
+    self.pages.len() as u32
+  }
+
+  pub fn by_path(&self, path: &std::path::Path) -> Option<&AtlasEntry> {
+    self.cache.get(path)
+  }
+
+  pub fn load_from_file<P: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: P,
+  ) -> Result<AtlasEntry> {
+    let path_ref = path.as_ref();
+
+    if let Some(entry) = self.by_path(path_ref) {
+      return Ok(*entry);
+    }
+
+    let bytes = std::fs::read(path_ref)?;
+    self.load_from_memory(device, queue, path_ref, &bytes)
+  }
+
+  /// Packs an encoded image, such as `include_bytes!` data, cached under `key` like a path
+  pub fn load_from_memory<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    bytes: &[u8],
+  ) -> Result<AtlasEntry> {
+    let key = key.as_ref();
+
+    if let Some(entry) = self.by_path(key) {
+      return Ok(*entry);
+    }
+
+    let rgba = image::load_from_memory(bytes).context(format!("{:?}", key))?.to_rgba8();
+    self.load_raw(device, queue, key, &rgba, rgba.width(), rgba.height())
+  }
+
+  /// Packs RGBA8 pixels, cached under `key` like a path
+  pub fn load_raw<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    data: &[u8],
+    width: u32,
+    height: u32,
+  ) -> Result<AtlasEntry> {
+    let key = key.as_ref();
+
+    if let Some(entry) = self.by_path(key) {
+      return Ok(*entry);
+    }
+
+    let entry = self.add_image(device, queue, data, width, height)?;
+    self.cache.insert(key.to_path_buf(), entry);
+
+    Ok(entry)
+  }
+
+  /// Packs the RGBA8 `data` into the first page with room for it,
+  /// adding a page when none has any. Not cached, see `load_raw`.
+  pub fn add_image(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    data: &[u8],
+    width: u32,
+    height: u32,
+  ) -> Result<AtlasEntry> {
+    let image = image::RgbaImage::from_raw(width, height, data.to_vec())
+      .context(format!("{} bytes don't make a {}x{} RGBA8 image", data.len(), width, height))?;
+    let image = match self.array.info().premultiply_alpha {
+      true => image_format::premultiply_alpha(&image.into()).to_rgba8(),
+      false => image,
+    };
+
+    let page_size = self.array.layer_size();
+    let padded = glam::uvec2(width, height) + self.padding * 2;
+    if padded.x > page_size.x || padded.y > page_size.y {
+      return Err(anyhow!(
+        "{}x{} image with {} pixels of padding doesn't fit in {}x{} pages",
+        width,
+        height,
+        self.padding,
+        page_size.x,
+        page_size.y
+      ));
+    }
+
+    let placed = self
+      .pages
+      .iter_mut()
+      .find_map(|(page, skyline)| Some((*page, skyline.insert(padded)?)));
+    let (page, origin) = match placed {
+      Some(placed) => placed,
+      None => {
+        let page = self.array.allocate_layer(device, queue)?;
+        let mut skyline = Skyline::new(page_size);
+        let origin = skyline.insert(padded).context("Image doesn't fit in an empty page")?;
+        self.pages.push((page, skyline));
+        (page, origin)
+      }
+    };
+
+    let extruded = extrude(&image, self.padding);
+    self.array.write_region(queue, page, origin, &extruded, padded.x, padded.y);
+    self.array.update_mipmaps(device, queue, page);
+
+    let rect = Rect::new(
+      (origin.x + self.padding) as f32,
+      (origin.y + self.padding) as f32,
+      width as f32,
+      height as f32,
+    );
+    let page_size = page_size.as_vec2();
+    Ok(AtlasEntry {
+      page,
+      rect,
+      uv_rect: Rect::from_min_max(rect.min() / page_size, rect.max() / page_size),
+    })
+  }
+}
+
+/// Copies the image into the middle of a buffer `padding` pixels larger on
+/// each side, the border repeating the closest edge pixel.
+fn extrude(image: &image::RgbaImage, padding: u32) -> Vec<u8> {
+  if padding == 0 {
+    return image.as_raw().clone();
+  }
+
+  let width = image.width() + padding * 2;
+  let height = image.height() + padding * 2;
+  let mut data = Vec::with_capacity((width * height * 4) as usize);
+  for y in 0..height {
+    let source_y = y.saturating_sub(padding).min(image.height() - 1);
+    for x in 0..width {
+      let source_x = x.saturating_sub(padding).min(image.width() - 1);
+      data.extend_from_slice(&image.get_pixel(source_x, source_y).0);
+    }
+  }
+  data
+}
+
+/// Skyline bottom-left packer, places each rect as high as possible then as left as possible
+struct Skyline {
+  size: glam::UVec2,
+  /// Segments of the skyline from left to right: x, y and width
+  nodes: Vec<(u32, u32, u32)>,
+}
+
+impl Skyline {
+  fn new(size: glam::UVec2) -> Self {
+    Self {
+      size,
+      nodes: vec![(0, 0, size.x)],
+    }
+  }
+
+  fn insert(&mut self, size: glam::UVec2) -> Option<glam::UVec2> {
+    let (index, origin) = (0..self.nodes.len())
+      .filter_map(|index| Some((index, self.fit(index, size)?)))
+      .min_by_key(|(_, origin)| (origin.y + size.y, origin.x))?;
+
+    self.nodes.insert(index, (origin.x, origin.y + size.y, size.x));
+
+    // Cut the segments now under the new one
+    let right = origin.x + size.x;
+    let next = index + 1;
+    while next < self.nodes.len() {
+      let (x, y, width) = self.nodes[next];
+      if x >= right {
+        break;
+      }
+      if x + width <= right {
+        self.nodes.remove(next);
+      } else {
+        self.nodes[next] = (right, y, x + width - right);
+        break;
+      }
+    }
+
+    // Merge neighbours at the same height
+    let mut index = 0;
+    while index + 1 < self.nodes.len() {
+      if self.nodes[index].1 == self.nodes[index + 1].1 {
+        self.nodes[index].2 += self.nodes[index + 1].2;
+        self.nodes.remove(index + 1);
+      } else {
+        index += 1;
+      }
+    }
+
+    Some(origin)
+  }
+
+  /// Position of a rect whose left edge starts at the segment `index`, if it fits
+  fn fit(&self, index: usize, size: glam::UVec2) -> Option<glam::UVec2> {
+    let x = self.nodes[index].0;
+    if x + size.x > self.size.x {
+      return None;
+    }
+
+    let mut y = 0;
+    let mut covered = 0;
+    for &(_, node_y, node_width) in &self.nodes[index..] {
+      if covered >= size.x {
+        break;
+      }
+      y = y.max(node_y);
+      covered += node_width;
+    }
+
+    (y + size.y <= self.size.y).then_some(glam::uvec2(x, y))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn overlaps(a: (glam::UVec2, glam::UVec2), b: (glam::UVec2, glam::UVec2)) -> bool {
+    let (a_min, a_max) = (a.0, a.0 + a.1);
+    let (b_min, b_max) = (b.0, b.0 + b.1);
+    a_min.cmplt(b_max).all() && b_min.cmplt(a_max).all()
+  }
+
+  #[test]
+  fn skyline_places_rects_bottom_left_first() {
+    let mut skyline = Skyline::new(glam::uvec2(64, 64));
+    assert_eq!(skyline.insert(glam::uvec2(32, 16)), Some(glam::uvec2(0, 0)));
+    assert_eq!(skyline.insert(glam::uvec2(32, 8)), Some(glam::uvec2(32, 0)));
+    // Lowest top edge wins, on the shorter right segment
+    assert_eq!(skyline.insert(glam::uvec2(16, 8)), Some(glam::uvec2(32, 8)));
+    // Spanning both segments lands on the higher one
+    assert_eq!(skyline.insert(glam::uvec2(64, 8)), Some(glam::uvec2(0, 16)));
+  }
+
+  #[test]
+  fn skyline_rects_never_overlap_and_stay_inside() {
+    let size = glam::uvec2(128, 128);
+    let mut skyline = Skyline::new(size);
+    let mut placed = Vec::new();
+    for index in 0..200u32 {
+      let rect = glam::uvec2(4 + index * 7 % 29, 3 + index * 11 % 23);
+      let Some(origin) = skyline.insert(rect) else {
+        continue;
+      };
+      assert!((origin + rect).cmple(size).all(), "{origin} + {rect} is out of the page");
+      for &other in &placed {
+        assert!(!overlaps((origin, rect), other), "{origin} {rect} overlaps {other:?}");
+      }
+      placed.push((origin, rect));
+    }
+    assert!(placed.len() > 20);
+  }
+
+  #[test]
+  fn skyline_rejects_what_doesnt_fit() {
+    let mut skyline = Skyline::new(glam::uvec2(32, 32));
+    assert_eq!(skyline.insert(glam::uvec2(33, 1)), None);
+    assert_eq!(skyline.insert(glam::uvec2(1, 33)), None);
+    assert_eq!(skyline.insert(glam::uvec2(32, 32)), Some(glam::UVec2::ZERO));
+    assert_eq!(skyline.insert(glam::uvec2(1, 1)), None);
+  }
+
+  #[test]
+  fn extrude_repeats_the_edges() {
+    let image = image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([x as u8, 0, 0, 255]));
+    let data = extrude(&image, 1);
+    let reds: Vec<u8> = data.chunks(4).map(|texel| texel[0]).collect();
+    assert_eq!(reds, [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1]);
+  }
+}
@@ -1,19 +1,39 @@
 use crate::{
-  TextureManager,
-  render_object::RenderObject,
+  MaterialId, MaterialManager, TextureManager,
+  camera::Camera,
+  picking::{PickQuery, PickResult, PickingPass},
+  scene::{Scene, TextureRefChange},
+  draw_queue::{DrawItem, DrawQueue, PassState, SortKey, bind_group_key},
+  sprite_batch::SpriteBatch,
+  skybox::Skybox,
+  texture_loader::TextureLoader,
+  asset_server::LoadState,
   render_resource::{
-    FrameContext, RenderState, render_pipeline, texture_array::TextureArrayInfo,
+    FrameContext, Material, RenderState, Shader, ShaderEffect, render_pipeline::{self, BlendMode},
+    AtlasEntry, CompressedImage, Cubemap, RenderTarget, TextureArray, TextureArrayId,
+    SamplerCache, SamplerId, SamplerKey, SamplerPreset,
+    texture_array::{SlotRegion, TextureArrayInfo, TextureFit},
   },
+  shader_pass::{PipelineState, ShaderPass},
 };
-use anyhow::{Context, Result};
-use std::sync::Arc;
+use anyhow::{Context, Result, anyhow};
+use std::{collections::HashMap, rc::Rc, sync::Arc};
 use winit::{dpi::PhysicalSize, window::Window};
 
 pub struct Renderer<'a> {
   pub render_state: RenderState<'a>,
   pipeline_manager: render_pipeline::PipelineManager,
   texture_manager: TextureManager,
+  material_manager: MaterialManager,
+  texture_array_layout: wgpu::BindGroupLayout,
+  default_texture_array: TextureArrayId,
+  /// Other arrays are made from the default one's info
   default_texture_array_info: TextureArrayInfo,
+  draw_queue: DrawQueue,
+  picking: PickingPass,
+  texture_loader: TextureLoader,
+  sampler_cache: SamplerCache,
+  skybox: Option<Skybox>,
 }
 
 impl<'a> Renderer<'a> {
@@ -21,44 +41,481 @@ impl<'a> Renderer<'a> {
     let render_state = RenderState::new(window.clone()).await;
     let mut pipeline_manager = render_pipeline::PipelineManager::new();
 
-    let solid_pipeline = render_pipeline::helpers::create_solid_pipeline(&render_state);
-
-    let diffuse_sampler = Arc::new(render_state.device().create_sampler(
-      &wgpu::SamplerDescriptor {
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
-        address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::MipmapFilterMode::Nearest,
-        ..Default::default()
-      },
-    ));
+    let texture_array_layout =
+      render_pipeline::helpers::create_texture_array_bind_group_layout(render_state.device());
+    let texture_array_pipeline_layout = render_pipeline::helpers::create_texture_array_layout(
+      render_state.device(),
+      &texture_array_layout,
+    );
+    let picking_pipeline =
+      render_pipeline::helpers::create_picking_pipeline(&render_state, &texture_array_pipeline_layout);
+
+    // Trilinear, the default texture array has mipmaps
+    let mut sampler_cache = SamplerCache::new();
+    let diffuse_sampler_id = sampler_cache.get_or_create(render_state.device(), SamplerPreset::Linear);
+    let diffuse_sampler = sampler_cache.get(diffuse_sampler_id).expect("Sampler was just created").clone();
 
     let mut texture_manager = TextureManager::deafult();
     let texture_array_info = TextureArrayInfo {
       dims: wgpu::Extent3d {
         width: 256,
         height: 256,
-        depth_or_array_layers: 5,
+        depth_or_array_layers: 16,
       },
+      max_layers: render_state.device().limits().max_texture_array_layers,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      usage: wgpu::TextureUsages::empty(),
+      fit: TextureFit::Pad,
+      mipmaps: true,
       sampler: diffuse_sampler.clone(),
-      bind_group_layout: solid_pipeline.get_bind_group_layout(0),
+      bind_group_layout: texture_array_layout.clone(),
+      premultiply_alpha: false,
+      truncate_channels: false,
     };
-    texture_manager.add_texture_array(render_state.device(), texture_array_info.clone());
+    let default_texture_array = texture_manager.add_texture_array(render_state.device(), texture_array_info.clone());
+
+    for blend in BlendMode::ALL {
+      let sprite_pipeline =
+        render_pipeline::helpers::create_sprite_pipeline(&render_state, &texture_array_pipeline_layout, blend);
+      pipeline_manager.add(render_pipeline::PipelineType::Sprite(blend), sprite_pipeline);
+    }
+    pipeline_manager.add(render_pipeline::PipelineType::Picking, picking_pipeline);
 
-    pipeline_manager.add(render_pipeline::PipelineType::Solid, solid_pipeline);
+    let material_manager = MaterialManager::new(render_state.device(), &render_state.queue);
 
-    Self {
+    let mut renderer = Self {
       render_state,
       pipeline_manager,
       texture_manager,
+      material_manager,
+      texture_array_layout,
+      default_texture_array,
       default_texture_array_info: texture_array_info,
+      draw_queue: DrawQueue::default(),
+      picking: PickingPass::new(),
+      texture_loader: TextureLoader::new(),
+      sampler_cache,
+      skybox: None,
+    };
+
+    renderer
+      .create_material(
+        include_str!("../../../shaders/triangle.wgsl"),
+        PipelineState::default(),
+      )
+      .expect("Failed to create the default material");
+    renderer
+  }
+
+  /// Builds a material from WGSL source, group 0 of the shader must be the texture array.
+  pub fn create_material(&mut self, wgsl: &str, state: PipelineState) -> Result<MaterialId> {
+    let material = self.build_material(wgsl, state)?;
+    Ok(self.material_manager.add(material))
+  }
+
+  /// Rebuilds the material of `id` from new WGSL source in place, keeping its texture
+  /// sampler, the blend mode set on it and the parameters the new shader still has.
+  pub fn reload_material(&mut self, id: MaterialId, wgsl: &str, state: PipelineState) -> Result<()> {
+    let mut material = self.build_material(wgsl, state)?;
+    let previous = self.material_manager.get(id).context(format!("No material {:?}", id))?;
+    material.set_texture_sampler(previous.texture_sampler());
+    // A blend mode picked at runtime wins over the one of the old state
+    if previous.blend_mode() != previous.pass().state().blend {
+      material.set_blend_mode(previous.blend_mode());
+    }
+    for (name, value) in previous.parameters() {
+      if let Err(error) = material.set(name, value.clone()) {
+        log::warn!("Dropped parameter {} of material {:?}: {:?}", name, id, error);
+      }
+    }
+
+    self.material_manager.replace(id, material);
+    Ok(())
+  }
+
+  fn build_material(&self, wgsl: &str, state: PipelineState) -> Result<Material> {
+    let device = self.render_state.device();
+    let shader = Rc::new(Shader::from_wgsl(wgsl)?);
+    let effect = Rc::new(ShaderEffect::new(device, shader, &[&self.texture_array_layout]));
+    let pass = ShaderPass::new(device, effect, state, self.render_state.config.format)?;
+
+    Ok(Material::new(device, pass))
+  }
+
+  /// Material used by objects that don't need anything but their texture
+  pub fn default_material(&self) -> MaterialId {
+    MaterialId(0)
+  }
+
+  pub fn material(&self, id: MaterialId) -> Option<&Material> {
+    self.material_manager.get(id)
+  }
+
+  pub fn material_mut(&mut self, id: MaterialId) -> Option<&mut Material> {
+    self.material_manager.get_mut(id)
+  }
+
+  /// Id of the sampler described by `key`, created the first time, for
+  /// `RenderObject::sampler` and `Material::set_texture_sampler`
+  pub fn sampler(&mut self, key: impl Into<SamplerKey>) -> SamplerId {
+    self.sampler_cache.get_or_create(self.render_state.device(), key)
+  }
+
+  /// The sampler of `id`, to set as a `MaterialParam::Sampler`
+  pub fn get_sampler(&self, id: SamplerId) -> Option<&wgpu::Sampler> {
+    self.sampler_cache.get(id).map(|sampler| sampler.as_ref())
+  }
+
+  /// Texture array of `id`, `None` being the default one
+  pub fn texture_array(&self, id: Option<TextureArrayId>) -> Option<&TextureArray> {
+    self.texture_manager.get_texture_array(id.unwrap_or(self.default_texture_array))
+  }
+
+  /// Texture array of `id`, `None` being the default one
+  pub fn texture_array_mut(&mut self, id: Option<TextureArrayId>) -> Option<&mut TextureArray> {
+    self.texture_manager.get_texture_array_mut(id.unwrap_or(self.default_texture_array))
+  }
+
+  /// Loads the file at `path` into the texture array of its size class and format,
+  /// created from the default one when needed. Objects using the slot need the returned id.
+  pub fn load_texture_by_size(&mut self, path: &std::path::Path) -> Result<(TextureArrayId, u32)> {
+    self.texture_manager.add_texture_by_size(
+      self.render_state.device(),
+      &self.render_state.queue,
+      path,
+      &self.default_texture_array_info,
+    )
+  }
+
+  /// Loads a KTX2 or DDS file into a texture array of its format and size, created
+  /// when needed. Formats the device can't sample are decompressed on the CPU.
+  pub fn load_compressed_texture(&mut self, path: &std::path::Path) -> Result<(TextureArrayId, u32)> {
+    let device = self.render_state.device();
+    let bytes = std::fs::read(path).context(format!("{:?}", path))?;
+    let image = CompressedImage::from_bytes(&bytes, path)?.for_device(device)?;
+
+    let (block_width, block_height) = image.format.block_dimensions();
+    if image.size.x % block_width != 0 || image.size.y % block_height != 0 {
+      return Err(anyhow!("{:?} isn't a whole number of {:?} blocks", path, image.format));
+    }
+
+    let info = TextureArrayInfo {
+      dims: wgpu::Extent3d {
+        width: image.size.x,
+        height: image.size.y,
+        depth_or_array_layers: 4,
+      },
+      format: image.format,
+      // Uncompressed images get their mips generated
+      mipmaps: image.has_full_mip_chain() || !image.is_compressed(),
+      ..self.default_texture_array_info.clone()
+    };
+    let id = match self.texture_manager.find_texture_array(&info) {
+      Some(id) => id,
+      None => self.texture_manager.add_texture_array(device, info),
+    };
+
+    let texture_array = self
+      .texture_manager
+      .get_texture_array_mut(id)
+      .context("Failed to get texture array")?;
+    let slot = texture_array.upload_compressed(device, &self.render_state.queue, &image)?;
+    Ok((id, slot))
+  }
+
+  /// Creates a texture array of `layer_size` layers in `format`, such as `R8Unorm` for
+  /// masks or linear `Rgba8Unorm` for data that must not be sRGB decoded, with
+  /// `usage` on top of the default usages. Images with more channels than `format` only
+  /// go into it with `truncate_channels`, see `TextureArrayInfo::truncate_channels`.
+  /// The other settings are the default array's.
+  pub fn create_texture_array(
+    &mut self,
+    layer_size: glam::UVec2,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+    truncate_channels: bool,
+  ) -> Result<TextureArrayId> {
+    let device = self.render_state.device();
+    let features = format.guaranteed_format_features(device.features());
+    if !device.features().contains(format.required_features()) {
+      return Err(anyhow!("{:?} needs device features {:?}", format, format.required_features()));
+    }
+    if !features.allowed_usages.contains(usage | wgpu::TextureUsages::TEXTURE_BINDING) {
+      return Err(anyhow!("{:?} textures don't allow {:?}", format, usage));
+    }
+    if !features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE) {
+      return Err(anyhow!("{:?} isn't filterable, texture arrays are sampled with filtering", format));
+    }
+
+    let info = TextureArrayInfo {
+      dims: wgpu::Extent3d {
+        width: layer_size.x,
+        height: layer_size.y,
+        ..self.default_texture_array_info.dims
+      },
+      format,
+      usage,
+      truncate_channels,
+      ..self.default_texture_array_info.clone()
+    };
+    match self.texture_manager.find_texture_array(&info) {
+      Some(id) => Ok(id),
+      None => Ok(self.texture_manager.add_texture_array(device, info)),
+    }
+  }
+
+  /// Creates a texture array like the default one with `layer_size` layers, whose images get
+  /// their alpha premultiplied when loaded, for objects drawn with `BlendMode::PremultipliedAlpha`
+  pub fn create_premultiplied_texture_array(&mut self, layer_size: glam::UVec2) -> TextureArrayId {
+    let info = TextureArrayInfo {
+      dims: wgpu::Extent3d {
+        width: layer_size.x,
+        height: layer_size.y,
+        ..self.default_texture_array_info.dims
+      },
+      premultiply_alpha: true,
+      ..self.default_texture_array_info.clone()
+    };
+    match self.texture_manager.find_texture_array(&info) {
+      Some(id) => id,
+      None => self.texture_manager.add_texture_array(self.render_state.device(), info),
     }
   }
 
+  /// Creates an atlas of `page_size` pages, drawn through the returned id like a
+  /// texture array
+  pub fn create_texture_atlas(&mut self, page_size: u32, padding: u32) -> TextureArrayId {
+    let info = TextureArrayInfo {
+      dims: wgpu::Extent3d {
+        width: page_size,
+        height: page_size,
+        depth_or_array_layers: 1,
+      },
+      ..self.default_texture_array_info.clone()
+    };
+    self
+      .texture_manager
+      .add_texture_atlas(self.render_state.device(), info, padding)
+  }
+
+  /// Packs the file at `path` into the atlas of `id`. The entry's page and rect
+  /// are the texture slot and source rect to draw sprites with.
+  pub fn load_atlas_texture(&mut self, path: &std::path::Path, id: TextureArrayId) -> Result<AtlasEntry> {
+    self
+      .texture_manager
+      .add_atlas_texture(self.render_state.device(), &self.render_state.queue, path, id)
+  }
+
+  /// Decodes an encoded image, such as `include_bytes!` data or a fetched file, into
+  /// the texture array of `id`, `None` being the default one. Objects whose
+  /// `texture_path` is `key` then use it like a loaded file.
+  pub fn load_texture_from_memory(
+    &mut self,
+    key: &std::path::Path,
+    bytes: &[u8],
+    id: Option<TextureArrayId>,
+  ) -> Result<u32> {
+    let id = id.unwrap_or(self.default_texture_array);
+    self
+      .texture_manager
+      .add_texture_from_memory(self.render_state.device(), &self.render_state.queue, key, bytes, id)
+  }
+
+  /// Uploads generated RGBA8 pixels, cached under `key` like `load_texture_from_memory`
+  pub fn load_texture_raw(
+    &mut self,
+    key: &std::path::Path,
+    data: &[u8],
+    size: glam::UVec2,
+    id: Option<TextureArrayId>,
+  ) -> Result<u32> {
+    let id = id.unwrap_or(self.default_texture_array);
+    self
+      .texture_manager
+      .add_texture_raw(self.render_state.device(), &self.render_state.queue, key, data, size, id)
+  }
+
+  /// Packs an encoded image into the atlas of `id`, cached under `key`
+  pub fn load_atlas_texture_from_memory(
+    &mut self,
+    key: &std::path::Path,
+    bytes: &[u8],
+    id: TextureArrayId,
+  ) -> Result<AtlasEntry> {
+    self.texture_manager.add_atlas_texture_from_memory(
+      self.render_state.device(),
+      &self.render_state.queue,
+      key,
+      bytes,
+      id,
+    )
+  }
+
+  /// Decodes an encoded image in the background, like `preload_texture` for data
+  /// that isn't a file. This is how fetched images load on the web.
+  pub fn preload_texture_from_memory(&mut self, key: &std::path::Path, bytes: Vec<u8>, id: Option<TextureArrayId>) {
+    let id = id.unwrap_or(self.default_texture_array);
+    if let Some(texture_array) = self.texture_manager.get_texture_array(id) {
+      self.texture_loader.request_bytes(key, bytes, id, texture_array.info());
+    }
+  }
+
+  /// Starts loading the file at `path` in the background, so objects using it don't
+  /// wait for it later. `None` is the default texture array.
+  pub fn preload_texture(&mut self, path: &std::path::Path, id: Option<TextureArrayId>) {
+    let id = id.unwrap_or(self.default_texture_array);
+    if let Some(texture_array) = self.texture_manager.get_texture_array(id)
+      && texture_array.by_path(path).is_none()
+    {
+      self.texture_loader.request(path, id, texture_array.info());
+    }
+  }
+
+  /// Image objects draw while their texture loads, stretched to the layers of each array
+  /// by the texture loader. Arrays show the previous placeholder until then.
+  pub fn set_placeholder_texture(&mut self, image: image::DynamicImage) {
+    self.texture_manager.set_placeholder(image);
+  }
+
+  /// How far the file at `path` got into the texture array of `id`,
+  /// `None` when it was never loaded nor requested
+  pub fn texture_load_state(&self, path: &std::path::Path, id: Option<TextureArrayId>) -> Option<LoadState> {
+    if self.texture_loader.is_reloading(path) {
+      Some(LoadState::Loading)
+    } else if self.texture_array(id).is_some_and(|array| array.by_path(path).is_some()) {
+      Some(LoadState::Loaded)
+    } else if self.texture_loader.is_pending(path) {
+      Some(LoadState::Loading)
+    } else if self.texture_loader.has_failed(path) {
+      Some(LoadState::Failed)
+    } else {
+      None
+    }
+  }
+
+  /// Reads the file at `path` again in the background and overwrites its slot in the array
+  /// of `id`, see `TextureArray::reload_decoded`. Files that aren't loaded are loaded.
+  /// The texture is `Loading` until the new version is uploaded.
+  pub fn reload_texture(&mut self, path: &std::path::Path, id: Option<TextureArrayId>) -> Result<()> {
+    let id = id.unwrap_or(self.default_texture_array);
+    let texture_array = self
+      .texture_manager
+      .get_texture_array(id)
+      .context(format!("No texture array for {:?}", path))?;
+
+    self.texture_loader.reload(path, id, texture_array.info());
+    Ok(())
+  }
+
+  /// Number of texture files still being read or decoded
+  pub fn textures_loading(&self) -> usize {
+    self.texture_loader.loading_count()
+  }
+
+  /// Creates an offscreen target in the surface format, so materials can draw into it,
+  /// with a depth texture when `depth` is set, see `render_solids_to_target`.
+  /// Objects sample it through `RenderTarget::id` at `RenderTarget::SLOT`.
+  pub fn create_render_target(&mut self, size: glam::UVec2, depth: bool) -> Result<RenderTarget> {
+    let max_size = self.render_state.device().limits().max_texture_dimension_2d;
+    if size.min_element() == 0 || size.max_element() > max_size {
+      return Err(anyhow!("Render targets are 1 to {max_size} pixels wide, not {}x{}", size.x, size.y));
+    }
+
+    let info = TextureArrayInfo {
+      dims: wgpu::Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+      },
+      format: self.render_state.config.format,
+      max_layers: 1,
+      fit: TextureFit::Resize,
+      mipmaps: false,
+      ..self.default_texture_array_info.clone()
+    };
+
+    self
+      .texture_manager
+      .add_render_target(self.render_state.device(), &self.render_state.queue, info, depth)
+  }
+
+  /// Frees the textures of `target`, objects must not draw it anymore
+  pub fn destroy_render_target(&mut self, target: RenderTarget) {
+    self.texture_manager.remove_texture_array(target.id);
+  }
+
+  /// Loads a cubemap from six face images, in +X, -X, +Y, -Y, +Z, -Z order
+  pub fn load_cubemap(&self, faces: [&std::path::Path; 6]) -> Result<Cubemap> {
+    let mut images = Vec::with_capacity(faces.len());
+    for path in faces {
+      images.push(image::open(path).context(format!("{:?}", path))?);
+    }
+    let images: [image::DynamicImage; 6] = images.try_into().map_err(|_| anyhow!("A cubemap has six faces"))?;
+    Cubemap::from_faces(self.render_state.device(), &self.render_state.queue, &images)
+  }
+
+  /// Loads an equirectangular panorama, such as an HDR environment, into a cubemap
+  /// of `face_size` faces
+  pub fn load_equirect_cubemap(&self, path: &std::path::Path, face_size: u32) -> Result<Cubemap> {
+    let image = image::open(path).context(format!("{:?}", path))?;
+    Cubemap::from_equirectangular(self.render_state.device(), &self.render_state.queue, &image, face_size)
+      .context(format!("{:?}", path))
+  }
+
+  /// Sets the cubemap drawn by `render_skybox`, `None` removes the skybox
+  pub fn set_skybox(&mut self, cubemap: Option<Cubemap>) {
+    self.skybox = cubemap.map(|cubemap| {
+      let sampler_id = self.sampler_cache.get_or_create(self.render_state.device(), SamplerPreset::Linear);
+      let sampler = self.sampler_cache.get(sampler_id).expect("Sampler was just created");
+      Skybox::new(self.render_state.device(), self.render_state.config.format, cubemap, sampler)
+    });
+  }
+
+  pub fn skybox(&self) -> Option<&Skybox> {
+    self.skybox.as_ref()
+  }
+
+  /// Draws the skybox as seen through the rotation of `camera`, filling the pass into a
+  /// `target_size` target, such as the surface or a `RenderTarget`. Call it before the other
+  /// passes draw, the sky doesn't test depth. Does nothing without a skybox.
+  pub fn render_skybox(&mut self, render_pass: &mut wgpu::RenderPass, camera: &Camera, target_size: glam::UVec2) {
+    if let Some(skybox) = &mut self.skybox {
+      skybox.render(self.render_state.device(), &self.render_state.queue, render_pass, camera, target_size, false);
+    }
+  }
+
+  /// Like `render_skybox`, for a pass into `target`, whose depth texture it leaves untouched
+  pub fn render_skybox_to_target(
+    &mut self,
+    render_pass: &mut wgpu::RenderPass,
+    camera: &Camera,
+    target: &RenderTarget,
+  ) {
+    if let Some(skybox) = &mut self.skybox {
+      let (device, queue) = (self.render_state.device(), &self.render_state.queue);
+      skybox.render(device, queue, render_pass, camera, target.size, target.has_depth());
+    }
+  }
+
+  /// Size of the surface, the target size of passes drawing into the window
+  pub fn surface_size(&self) -> glam::UVec2 {
+    let size = self.render_state.get_size();
+    glam::uvec2(size.width, size.height)
+  }
+
+  /// Frees the slot of a texture loaded from `path`, unless an object still references it
+  pub fn unload_texture(&mut self, path: &std::path::Path, id: Option<TextureArrayId>) -> bool {
+    let id = id.unwrap_or(self.default_texture_array);
+    self.texture_manager.unload_texture(path, id)
+  }
+
   pub fn begin_rendering(&mut self) -> Result<Option<FrameContext>, wgpu::SurfaceError> {
     self.render_state.resize();
+    self.picking.collect(self.render_state.device());
+    self.texture_manager.advance_frame();
+    if let Some(skybox) = &mut self.skybox {
+      skybox.begin_frame();
+    }
 
     let output = self.render_state.surface.get_current_texture()?;
     let encoder =
@@ -76,53 +533,283 @@ impl<'a> Renderer<'a> {
     )))
   }
 
+  /// Draws the objects of `scene` seen by `camera` sorted by their `SortKey`, skipping
+  /// state changes that are already bound. The resulting order is kept for `draw_order`.
   pub fn render_solids(
     &mut self,
     render_pass: &mut wgpu::RenderPass,
-    objects: &Vec<RenderObject>,
+    scene: &mut Scene,
+    camera: &Camera,
   ) -> Result<()> {
-    let pipeline = self
-      .pipeline_manager
-      .get(render_pipeline::PipelineType::Solid)
-      .context("No solid pipeline is setup")?;
+    self.draw_solids(render_pass, scene, camera, false)
+  }
 
-    render_pass.set_pipeline(pipeline);
+  /// Like `render_solids`, for a pass into `target`. Objects test and write the depth
+  /// texture of targets that have one, transparent ones only test it.
+  pub fn render_solids_to_target(
+    &mut self,
+    render_pass: &mut wgpu::RenderPass,
+    target: &RenderTarget,
+    scene: &mut Scene,
+    camera: &Camera,
+  ) -> Result<()> {
+    self.draw_solids(render_pass, scene, camera, target.has_depth())
+  }
 
-    for object in objects {
-      let texture_array_info = match &object.texture_array_info {
-        Some(info) => info,
-        None => &self.default_texture_array_info,
-      };
+  fn draw_solids(
+    &mut self,
+    render_pass: &mut wgpu::RenderPass,
+    scene: &mut Scene,
+    camera: &Camera,
+    depth: bool,
+  ) -> Result<()> {
+    self
+      .material_manager
+      .prepare(self.render_state.device(), &self.render_state.queue)?;
+
+    self.apply_texture_refs(scene);
+    self.upload_loaded_textures();
 
+    self.draw_queue.clear();
+    let mut loaded_slots = Vec::new();
+    let mut resized_slots = Vec::new();
+    for (id, object) in scene.iter() {
+      if !object.is_visible_to(camera) {
+        continue;
+      }
+
+      let texture_array_id = object.texture_array.unwrap_or(self.default_texture_array);
       let texture_array = self
         .texture_manager
-        .get_texture_array_mut(&texture_array_info)
+        .get_texture_array_mut(texture_array_id)
         .context("Failed to get texture array")?;
 
-      let slot = match object.texture_slot {
-        Some(slot) => slot,
-        None => {
-          let path = object
-            .texture_path
-            .as_ref()
-            .context("No path for texture")?;
+      if object.texture_slot.is_none() {
+        let path = object
+          .texture_path
+          .as_ref()
+          .context("No path for texture")?;
+
+        // Drawn with the placeholder until the loader is done with the file
+        match texture_array.by_path(path) {
+          Some(&slot) => loaded_slots.push((id, slot, texture_array.region(slot).uv_scale)),
+          None => self.texture_loader.request(path, texture_array_id, texture_array.info()),
+        }
+      }
+      if let Some(slot) = object.texture_slot {
+        texture_array.touch(slot);
+        // Files reloaded at another size cover another part of their layer
+        let uv_scale = texture_array.region(slot).uv_scale;
+        if texture_array.path_of(slot).is_some() && uv_scale != object.uv_scale {
+          resized_slots.push((id, uv_scale));
+        }
+      }
+
+      let material = self
+        .material_manager
+        .get_mut(object.material)
+        .context(format!("No material {:?}", object.material))?;
+      let blend = object.blend.unwrap_or(material.blend_mode());
+      material.prepare_blend(self.render_state.device(), blend, depth)?;
+      let material = &*material;
+
+      let sampler = object.sampler.or(material.texture_sampler());
+      if let Some(sampler_id) = sampler {
+        let key = self.sampler_cache.key(sampler_id).context(format!("No sampler {:?}", sampler_id))?;
+        if key.compare.is_some() {
+          return Err(anyhow!("Comparison sampler {:?} can't sample texture arrays", sampler_id));
+        }
+        let sampler = self.sampler_cache.get(sampler_id).context(format!("No sampler {:?}", sampler_id))?;
+        texture_array.prepare_sampler(self.render_state.device(), sampler_id, sampler);
+      }
+
+      self.draw_queue.push(DrawItem {
+        key: SortKey::new(object, material, bind_group_key(texture_array.id(), sampler)),
+        object: id,
+        sampler,
+      });
+    }
+    self.draw_queue.sort();
+
+    for (id, slot, uv_scale) in loaded_slots {
+      scene.set_texture_slot(id, slot, uv_scale);
+    }
+    for (id, uv_scale) in resized_slots {
+      scene.set_uv_scale(id, uv_scale);
+    }
+    self.apply_texture_refs(scene);
+    scene.sync(self.render_state.device(), &self.render_state.queue);
+
+    let Some(instance_buffer) = scene.instance_buffer() else {
+      return Ok(());
+    };
+    render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+
+    let mut pass_state = PassState::default();
+    for item in self.draw_queue.items() {
+      let object = scene.get(item.object).context("Object removed while drawing")?;
+
+      if pass_state.set_material(item.key.material, item.key.blend) {
+        let material = self
+          .material_manager
+          .get(object.material)
+          .context(format!("No material {:?}", object.material))?;
+        material.bind(render_pass, item.key.blend, depth);
+      }
+
+      if pass_state.set_bind_group(item.key.bind_group) {
+        let texture_array = self
+          .texture_manager
+          .get_texture_array(object.texture_array.unwrap_or(self.default_texture_array))
+          .context("Failed to get texture array")?;
+        render_pass.set_bind_group(0, texture_array.bind_group_for(item.sampler), &[]);
+      }
+
+      if pass_state.set_mesh(item.key.mesh) {
+        render_pass.set_vertex_buffer(0, object.mesh.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(
+          object.mesh.index_buffer.slice(..),
+          wgpu::IndexFormat::Uint16,
+        );
+      }
+
+      let instance = item.object.index();
+      render_pass.draw_indexed(0..object.mesh.indices.len() as u32, 0, instance..(instance + 1));
+    }
+
+    Ok(())
+  }
 
-          texture_array.load_from_file(&self.render_state.queue, path).context(format!("{:?}", path))?
+  /// Copies what the texture loader finished into the arrays, the placeholders of new
+  /// arrays being handed to it on the way
+  fn upload_loaded_textures(&mut self) {
+    let device = self.render_state.device();
+    let queue = &self.render_state.queue;
+    let (placeholder, generation, requests) = self.texture_manager.take_placeholder_requests();
+    for (id, info) in &requests {
+      self.texture_loader.request_placeholder(placeholder.clone(), generation, *id, info);
+    }
+    for loaded in self.texture_loader.poll_placeholders() {
+      let result = loaded.image.and_then(|image| {
+        self
+          .texture_manager
+          .write_placeholder(device, queue, loaded.generation, loaded.id, &image)
+      });
+      if let Err(error) = result {
+        log::error!("Failed to set a placeholder: {:?}", error);
+      }
+    }
+
+    for loaded in self.texture_loader.poll() {
+      let image = match loaded.image {
+        Ok(image) => image,
+        Err(error) => {
+          log::error!("Failed to load texture: {:?}", error);
+          continue;
         }
       };
 
+      for &id in &loaded.targets {
+        let Some(texture_array) = self.texture_manager.get_texture_array_mut(id) else {
+          continue;
+        };
+        // Targets added while the file loaded are fitted here
+        let fitted = loaded.fitted.iter().find(|(target, _)| *target == id).map(|(_, fitted)| fitted);
+        let result = match (fitted, loaded.reload) {
+          (Some(Err(error)), _) => Err(anyhow!("{:?}", error)),
+          (Some(Ok(Some(fitted))), false) => texture_array.load_fitted(device, queue, &loaded.path, fitted),
+          (Some(Ok(Some(fitted))), true) => texture_array.reload_fitted(device, queue, &loaded.path, fitted),
+          (Some(Ok(None)) | None, false) => texture_array.load_decoded(device, queue, &loaded.path, &image),
+          (Some(Ok(None)) | None, true) => texture_array.reload_decoded(device, queue, &loaded.path, &image),
+        };
+        if let Err(error) = result {
+          log::error!("Failed to upload texture {:?}: {:?}", loaded.path, error);
+          self.texture_loader.mark_failed(&loaded.path);
+        }
+      }
+    }
+  }
+
+  fn apply_texture_refs(&mut self, scene: &mut Scene) {
+    for change in scene.take_texture_refs() {
+      let (id, slot, retain) = match change {
+        TextureRefChange::Retain(id, slot) => (id, slot, true),
+        TextureRefChange::Release(id, slot) => (id, slot, false),
+      };
+      let id = id.unwrap_or(self.default_texture_array);
+      let Some(texture_array) = self.texture_manager.get_texture_array_mut(id) else {
+        continue;
+      };
+
+      if retain {
+        texture_array.retain(slot);
+      } else {
+        texture_array.release(slot);
+      }
+    }
+  }
+
+  /// Order in which the last `render_solids` call drew its objects
+  pub fn draw_order(&self) -> &[DrawItem] {
+    self.draw_queue.items()
+  }
+
+  /// Uploads the sprites queued in `batch` and draws them, one draw call
+  /// per run of sprites sharing a texture array and blend mode. The batch is cleared afterwards.
+  pub fn render_sprites(
+    &mut self,
+    render_pass: &mut wgpu::RenderPass,
+    batch: &mut SpriteBatch,
+  ) -> Result<()> {
+    if batch.is_empty() {
+      return Ok(());
+    }
+
+    let texture_manager = &self.texture_manager;
+    let default_texture_array = self.default_texture_array;
+    batch.prepare(
+      self.render_state.device(),
+      &self.render_state.queue,
+      self.render_state.get_size(),
+      |id, slot| {
+        match texture_manager.get_texture_array(id.unwrap_or(default_texture_array)) {
+          Some(texture_array) => texture_array.region(slot),
+          // Segments of a missing array fail to draw below
+          None => SlotRegion {
+            size: glam::UVec2::ZERO,
+            uv_scale: glam::Vec2::ONE,
+          },
+        }
+      },
+    );
+
+    render_pass.set_vertex_buffer(0, batch.vertex_buffer().slice(..));
+    render_pass.set_index_buffer(batch.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
+
+    for segment in batch.segments() {
+      let texture_array = self
+        .texture_manager
+        .get_texture_array(segment.texture_array.unwrap_or(self.default_texture_array))
+        .context("Failed to get texture array")?;
+
+      let pipeline = self
+        .pipeline_manager
+        .get(render_pipeline::PipelineType::Sprite(segment.blend))
+        .context("No sprite pipeline is setup")?;
+
+      render_pass.set_pipeline(pipeline);
       render_pass.set_bind_group(0, &texture_array.bind_group, &[]);
-      render_pass.set_vertex_buffer(0, object.mesh.vertex_buffer.slice(..));
-      render_pass.set_index_buffer(
-        object.mesh.index_buffer.slice(..),
-        wgpu::IndexFormat::Uint16,
-      );
-      render_pass.draw_indexed(0..object.mesh.indices.len() as u32, 0, slot..(slot + 1));
+      render_pass.draw_indexed(segment.indices(), 0, 0..1);
     }
 
+    batch.finish_frame();
     Ok(())
   }
 
+  pub fn create_sprite_batch(&self) -> SpriteBatch {
+    SpriteBatch::new(self.render_state.device())
+  }
+
   #[allow(unused)]
   pub fn render_wireframes(&self, render_pass: &mut wgpu::RenderPass) -> Result<(), &'static str> {
     let pipeline = self
@@ -134,14 +821,128 @@ impl<'a> Renderer<'a> {
     Ok(())
   }
 
-  pub fn finish_rendering(&self, frame_ctx: FrameContext) {
+  pub fn finish_rendering(&mut self, frame_ctx: FrameContext) {
     self
       .render_state
       .queue
       .submit(Some(frame_ctx.encoder.finish()));
+    self.picking.map_rendered();
     frame_ctx.output.present();
   }
 
+  /// Queues a pick at `x`, `y` in physical pixels. The ids are rendered by the
+  /// next `render_picking` call and the result is available a frame later.
+  pub fn pick(&mut self, x: u32, y: u32) -> PickQuery {
+    self.picking.pick(x, y)
+  }
+
+  pub fn pick_result(&mut self, query: PickQuery) -> PickResult {
+    self.picking.take_result(query)
+  }
+
+  /// Renders the ids of the objects seen by `camera`, only when picks are queued
+  pub fn render_picking(
+    &mut self,
+    frame_ctx: &mut FrameContext,
+    scene: &mut Scene,
+    camera: &Camera,
+  ) -> Result<()> {
+    if !self.picking.has_queued() {
+      return Ok(());
+    }
+
+    let pipeline = self
+      .pipeline_manager
+      .get(render_pipeline::PipelineType::Picking)
+      .context("No picking pipeline is setup")?;
+
+    let device = self.render_state.device();
+    scene.sync(device, &self.render_state.queue);
+    let Some(instance_buffer) = scene.instance_buffer() else {
+      return Ok(());
+    };
+
+    let mut objects = HashMap::new();
+    let view = self.picking.target_view(device, self.render_state.get_size());
+    {
+      let mut render_pass = frame_ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Picking Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view,
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            store: wgpu::StoreOp::Store,
+          },
+          depth_slice: None,
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+        multiview_mask: None,
+      });
+
+      render_pass.set_pipeline(pipeline);
+      render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+
+      // Same order as `render_solids`, so overlapping objects resolve to the one drawn on top.
+      // Objects still loading their texture are picked through the placeholder they show.
+      let mut draw_queue = DrawQueue::default();
+      for (id, object) in scene.iter() {
+        if !object.is_visible_to(camera) {
+          continue;
+        }
+
+        let texture_array = self
+          .texture_manager
+          .get_texture_array(object.texture_array.unwrap_or(self.default_texture_array))
+          .context("Failed to get texture array")?;
+        let material = self
+          .material_manager
+          .get(object.material)
+          .context(format!("No material {:?}", object.material))?;
+
+        let sampler = object.sampler.or(material.texture_sampler());
+        draw_queue.push(DrawItem {
+          key: SortKey::new(object, material, bind_group_key(texture_array.id(), sampler)),
+          object: id,
+          sampler,
+        });
+      }
+      draw_queue.sort();
+
+      let mut pass_state = PassState::default();
+      for item in draw_queue.items() {
+        let id = item.object;
+        let object = scene.get(id).context("Object removed while picking")?;
+        let texture_array = self
+          .texture_manager
+          .get_texture_array(object.texture_array.unwrap_or(self.default_texture_array))
+          .context("Failed to get texture array")?;
+
+        // Alpha is tested with the array's own sampler
+        if pass_state.set_bind_group(bind_group_key(texture_array.id(), None)) {
+          render_pass.set_bind_group(0, &texture_array.bind_group, &[]);
+        }
+
+        if pass_state.set_mesh(object.mesh.id.0) {
+          render_pass.set_vertex_buffer(0, object.mesh.vertex_buffer.slice(..));
+          render_pass.set_index_buffer(
+            object.mesh.index_buffer.slice(..),
+            wgpu::IndexFormat::Uint16,
+          );
+        }
+
+        let instance = id.index();
+        render_pass.draw_indexed(0..object.mesh.indices.len() as u32, 0, instance..(instance + 1));
+        objects.insert(instance, id);
+      }
+    }
+
+    self.picking.copy_queued(device, &mut frame_ctx.encoder, objects);
+    Ok(())
+  }
+
   pub fn request_resize(&mut self, new_size: PhysicalSize<u32>) {
     self.render_state.new_size = Some(new_size);
   }
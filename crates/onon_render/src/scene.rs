@@ -0,0 +1,610 @@
+use anyhow::{Result, anyhow};
+
+use crate::{
+  camera::Camera,
+  material_manager::MaterialId,
+  mesh::{InstanceData, MeshHit},
+  rect::Rect,
+  render_object::RenderObject,
+  render_resource::{TextureArrayId, texture_array::PLACEHOLDER_SLOT},
+  transform::Transform,
+};
+
+const INITIAL_INSTANCE_CAPACITY: u64 = 64;
+
+/// Handle to an object of a `Scene`. The generation makes handles of
+/// removed objects stale, even once their slot has been reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId {
+  index: u32,
+  generation: u32,
+}
+
+impl ObjectId {
+  /// Index of the object's instance in the scene's instance buffer
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+}
+
+/// Texture slot reference taken or dropped by an object of the scene.
+/// `None` stands for the renderer's default texture array.
+#[derive(Clone)]
+pub enum TextureRefChange {
+  Retain(Option<TextureArrayId>, u32),
+  Release(Option<TextureArrayId>, u32),
+}
+
+struct Slot {
+  generation: u32,
+  object: Option<RenderObject>,
+  dirty: bool,
+
+  parent: Option<u32>,
+  children: Vec<u32>,
+  world: glam::Affine2,
+  transform_dirty: bool,
+}
+
+/// Retained tree of render objects.
+///
+/// Every object owns the instance at its slot index in the instance buffer,
+/// only the instances of objects changed since the last sync are uploaded.
+/// Object transforms are relative to their parent, world transforms are
+/// propagated to the subtrees of changed objects before uploading.
+#[derive(Default)]
+pub struct Scene {
+  slots: Vec<Slot>,
+  free_slots: Vec<u32>,
+  dirty: Vec<u32>,
+  transform_dirty: Vec<u32>,
+  len: usize,
+  texture_refs: Vec<TextureRefChange>,
+
+  instance_buffer: Option<wgpu::Buffer>,
+  instance_capacity: u64,
+}
+
+impl Scene {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, object: RenderObject) -> ObjectId {
+    self.len += 1;
+    if let Some(slot) = object.texture_slot {
+      self.texture_refs.push(TextureRefChange::Retain(object.texture_array, slot));
+    }
+
+    let index = match self.free_slots.pop() {
+      Some(index) => {
+        self.slots[index as usize].object = Some(object);
+        index
+      }
+      None => {
+        self.slots.push(Slot {
+          generation: 0,
+          object: Some(object),
+          dirty: false,
+          parent: None,
+          children: Vec::new(),
+          world: glam::Affine2::IDENTITY,
+          transform_dirty: false,
+        });
+        self.slots.len() as u32 - 1
+      }
+    };
+
+    self.mark_transform_dirty(index);
+    ObjectId {
+      index,
+      generation: self.slots[index as usize].generation,
+    }
+  }
+
+  pub fn insert_child(&mut self, parent: ObjectId, object: RenderObject) -> Result<ObjectId> {
+    if !self.contains(parent) {
+      return Err(anyhow!("Parent {:?} is not in the scene", parent));
+    }
+
+    let id = self.insert(object);
+    self.set_parent(id, Some(parent))?;
+    Ok(id)
+  }
+
+  /// Removes the object, its children become roots and keep their local transform
+  pub fn remove(&mut self, id: ObjectId) -> Option<RenderObject> {
+    self.get(id)?;
+
+    self.unlink_parent(id.index);
+    let children = std::mem::take(&mut self.slots[id.index as usize].children);
+    for child in children {
+      self.slots[child as usize].parent = None;
+      self.mark_transform_dirty(child);
+    }
+
+    let slot = &mut self.slots[id.index as usize];
+    let object = slot.object.take()?;
+    slot.generation = slot.generation.wrapping_add(1);
+    slot.transform_dirty = false;
+    self.free_slots.push(id.index);
+    self.len -= 1;
+    if let Some(slot) = object.texture_slot {
+      self.texture_refs.push(TextureRefChange::Release(object.texture_array, slot));
+    }
+    Some(object)
+  }
+
+  /// Removes the object and all of its descendants
+  pub fn remove_subtree(&mut self, id: ObjectId) -> Option<RenderObject> {
+    self.get(id)?;
+
+    let mut stack = self.slots[id.index as usize].children.clone();
+    while let Some(index) = stack.pop() {
+      let slot = &self.slots[index as usize];
+      stack.extend_from_slice(&slot.children);
+      let child = ObjectId {
+        index,
+        generation: slot.generation,
+      };
+      self.remove(child);
+    }
+
+    self.remove(id)
+  }
+
+  /// Attaches `id` under `parent`, or makes it a root with `None`.
+  /// The local transform is kept, so the world transform follows the new parent.
+  pub fn set_parent(&mut self, id: ObjectId, parent: Option<ObjectId>) -> Result<()> {
+    if !self.contains(id) {
+      return Err(anyhow!("Object {:?} is not in the scene", id));
+    }
+
+    if let Some(parent) = parent {
+      if !self.contains(parent) {
+        return Err(anyhow!("Parent {:?} is not in the scene", parent));
+      }
+
+      let mut ancestor = Some(parent.index);
+      while let Some(index) = ancestor {
+        if index == id.index {
+          return Err(anyhow!("Parenting {:?} to {:?} would create a cycle", id, parent));
+        }
+        ancestor = self.slots[index as usize].parent;
+      }
+    }
+
+    self.unlink_parent(id.index);
+    if let Some(parent) = parent {
+      self.slots[parent.index as usize].children.push(id.index);
+    }
+    self.slots[id.index as usize].parent = parent.map(|parent| parent.index);
+    self.mark_transform_dirty(id.index);
+    Ok(())
+  }
+
+  /// Detaches the subtree of `id` from its parent
+  pub fn detach(&mut self, id: ObjectId) -> Result<()> {
+    self.set_parent(id, None)
+  }
+
+  pub fn parent(&self, id: ObjectId) -> Option<ObjectId> {
+    self.get(id)?;
+    let index = self.slots[id.index as usize].parent?;
+    Some(self.id_at(index))
+  }
+
+  pub fn children(&self, id: ObjectId) -> impl Iterator<Item = ObjectId> {
+    let children = match self.get(id) {
+      Some(_) => self.slots[id.index as usize].children.as_slice(),
+      None => &[],
+    };
+    children.iter().map(|&index| self.id_at(index))
+  }
+
+  /// World transform as of the last propagation
+  pub fn world_transform(&self, id: ObjectId) -> Option<glam::Affine2> {
+    self.get(id)?;
+    Some(self.slots[id.index as usize].world)
+  }
+
+  pub fn contains(&self, id: ObjectId) -> bool {
+    self.get(id).is_some()
+  }
+
+  pub fn get(&self, id: ObjectId) -> Option<&RenderObject> {
+    let slot = self.slots.get(id.index as usize)?;
+    if slot.generation != id.generation {
+      return None;
+    }
+    slot.object.as_ref()
+  }
+
+  /// The object's instance and world transform are updated again on the next sync.
+  /// Its texture array and slot can only be changed through the scene's setters.
+  pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut RenderObject> {
+    self.get(id)?;
+    self.mark_transform_dirty(id.index);
+    self.slots[id.index as usize].object.as_mut()
+  }
+
+  pub fn set_transform(&mut self, id: ObjectId, transform: Transform) -> bool {
+    self.get_mut(id).map(|object| object.transform = transform).is_some()
+  }
+
+  /// Texture slots have to be changed through this, to keep their reference counts right
+  pub fn set_texture_slot(&mut self, id: ObjectId, texture_slot: u32, uv_scale: glam::Vec2) -> bool {
+    let Some(object) = self.get(id) else {
+      return false;
+    };
+    let texture_array = object.texture_array;
+    if let Some(previous) = object.texture_slot {
+      self.texture_refs.push(TextureRefChange::Release(texture_array, previous));
+    }
+    self.texture_refs.push(TextureRefChange::Retain(texture_array, texture_slot));
+
+    self.mark_dirty(id.index);
+    self
+      .object_mut(id)
+      .map(|object| {
+        object.texture_slot = Some(texture_slot);
+        object.uv_scale = uv_scale;
+      })
+      .is_some()
+  }
+
+  /// Moves the object to `texture_slot` of `texture_array`, `None` being the default one
+  pub fn set_texture_array(
+    &mut self,
+    id: ObjectId,
+    texture_array: Option<TextureArrayId>,
+    texture_slot: u32,
+    uv_scale: glam::Vec2,
+  ) -> bool {
+    let Some(object) = self.get(id) else {
+      return false;
+    };
+    if let Some(previous) = object.texture_slot {
+      self.texture_refs.push(TextureRefChange::Release(object.texture_array, previous));
+    }
+    self.texture_refs.push(TextureRefChange::Retain(texture_array, texture_slot));
+
+    self.mark_dirty(id.index);
+    self
+      .object_mut(id)
+      .map(|object| {
+        object.texture_array = texture_array;
+        object.texture_slot = Some(texture_slot);
+        object.uv_scale = uv_scale;
+      })
+      .is_some()
+  }
+
+  /// See `RenderObject::uv_scale`
+  pub fn set_uv_scale(&mut self, id: ObjectId, uv_scale: glam::Vec2) -> bool {
+    let Some(object) = self.object_mut(id) else {
+      return false;
+    };
+    object.uv_scale = uv_scale;
+    self.mark_dirty(id.index);
+    true
+  }
+
+  /// Draws the `uv_rect` part of the object's texture, such as a sprite sheet frame
+  pub fn set_uv_rect(&mut self, id: ObjectId, uv_rect: Rect) -> bool {
+    let Some(object) = self.object_mut(id) else {
+      return false;
+    };
+    object.uv_rect = uv_rect;
+    self.mark_dirty(id.index);
+    true
+  }
+
+  /// Reference changes since the last call, in order
+  pub fn take_texture_refs(&mut self) -> Vec<TextureRefChange> {
+    std::mem::take(&mut self.texture_refs)
+  }
+
+  /// Materials and visibility are only read on the CPU, nothing is uploaded
+  pub fn set_material(&mut self, id: ObjectId, material: MaterialId) -> bool {
+    self.object_mut(id).map(|object| object.material = material).is_some()
+  }
+
+  pub fn set_visible(&mut self, id: ObjectId, visible: bool) -> bool {
+    self.object_mut(id).map(|object| object.visible = visible).is_some()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (ObjectId, &RenderObject)> {
+    self.slots.iter().enumerate().filter_map(|(index, slot)| {
+      let id = ObjectId {
+        index: index as u32,
+        generation: slot.generation,
+      };
+      slot.object.as_ref().map(|object| (id, object))
+    })
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Finds the topmost object seen by `camera` whose mesh contains `point`, in world space.
+  /// Layers are compared first, then depth. Texture transparency is not considered, nor are
+  /// the materials that order draws at the same depth, `Renderer::pick` matches what's drawn.
+  pub fn hit_test(&mut self, point: glam::Vec2, camera: &Camera) -> Option<(ObjectId, MeshHit)> {
+    self.propagate_transforms();
+
+    let mut best: Option<(ObjectId, MeshHit, (u8, f32))> = None;
+    for (index, slot) in self.slots.iter().enumerate() {
+      let Some(object) = slot.object.as_ref() else {
+        continue;
+      };
+      if !object.is_visible_to(camera) {
+        continue;
+      }
+
+      // Later slots are drawn over earlier ones of the same order
+      let order = (object.sort_layer, -object.depth);
+      if let Some((_, _, best_order)) = best
+        && best_order > order
+      {
+        continue;
+      }
+
+      if let Some(hit) = object.mesh.hit_test(slot.world, point) {
+        let id = ObjectId {
+          index: index as u32,
+          generation: slot.generation,
+        };
+        best = Some((id, hit, order));
+      }
+    }
+
+    best.map(|(id, hit, _)| (id, hit))
+  }
+
+  /// Instance buffer, indexed with `ObjectId::index`. Only valid after a sync.
+  pub fn instance_buffer(&self) -> Option<&wgpu::Buffer> {
+    self.instance_buffer.as_ref()
+  }
+
+  /// Recomputes the world transforms of changed objects and their descendants
+  pub fn propagate_transforms(&mut self) {
+    let mut roots = std::mem::take(&mut self.transform_dirty);
+    // Subtrees of changed ancestors are updated along with them
+    roots.retain(|&index| !self.has_dirty_ancestor(index));
+
+    let mut stack = Vec::new();
+    for root in roots {
+      stack.push(root);
+      while let Some(index) = stack.pop() {
+        let slot = &self.slots[index as usize];
+        let Some(object) = slot.object.as_ref() else {
+          continue;
+        };
+
+        let parent_world = slot
+          .parent
+          .map_or(glam::Affine2::IDENTITY, |parent| self.slots[parent as usize].world);
+        let world = parent_world * object.transform.to_affine();
+        stack.extend_from_slice(&slot.children);
+
+        let slot = &mut self.slots[index as usize];
+        slot.world = world;
+        slot.transform_dirty = false;
+        self.mark_dirty(index);
+      }
+    }
+  }
+
+  /// Propagates transforms and uploads the instances of changed objects,
+  /// reallocating the whole buffer when the scene outgrew it.
+  pub fn sync(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    self.propagate_transforms();
+
+    let needed = (self.slots.len() as u64).max(1);
+    if self.instance_buffer.is_none() || needed > self.instance_capacity {
+      self.instance_capacity = needed.next_power_of_two().max(INITIAL_INSTANCE_CAPACITY);
+      self.instance_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Scene Instance Buffer"),
+        size: self.instance_capacity * std::mem::size_of::<InstanceData>() as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+      }));
+
+      for index in 0..self.slots.len() as u32 {
+        self.mark_dirty(index);
+      }
+    }
+
+    let Some(buffer) = self.instance_buffer.as_ref() else {
+      return;
+    };
+
+    // Neighbouring dirty instances are uploaded with a single write
+    self.dirty.sort_unstable();
+    let mut run: Vec<InstanceData> = Vec::new();
+    let mut run_start = 0;
+    for (position, &index) in self.dirty.iter().enumerate() {
+      let slot = &mut self.slots[index as usize];
+      slot.dirty = false;
+
+      if run.is_empty() {
+        run_start = index;
+      }
+      run.push(instance_data(slot));
+
+      let next = self.dirty.get(position + 1);
+      if next != Some(&(index + 1)) {
+        queue.write_buffer(
+          buffer,
+          run_start as u64 * std::mem::size_of::<InstanceData>() as u64,
+          bytemuck::cast_slice(&run),
+        );
+        run.clear();
+      }
+    }
+    self.dirty.clear();
+  }
+
+  fn object_mut(&mut self, id: ObjectId) -> Option<&mut RenderObject> {
+    let slot = self.slots.get_mut(id.index as usize)?;
+    if slot.generation != id.generation {
+      return None;
+    }
+    slot.object.as_mut()
+  }
+
+  fn id_at(&self, index: u32) -> ObjectId {
+    ObjectId {
+      index,
+      generation: self.slots[index as usize].generation,
+    }
+  }
+
+  fn unlink_parent(&mut self, index: u32) {
+    if let Some(parent) = self.slots[index as usize].parent.take() {
+      self.slots[parent as usize].children.retain(|&child| child != index);
+    }
+  }
+
+  fn has_dirty_ancestor(&self, index: u32) -> bool {
+    let mut ancestor = self.slots[index as usize].parent;
+    while let Some(index) = ancestor {
+      let slot = &self.slots[index as usize];
+      if slot.transform_dirty {
+        return true;
+      }
+      ancestor = slot.parent;
+    }
+    false
+  }
+
+  fn mark_transform_dirty(&mut self, index: u32) {
+    let slot = &mut self.slots[index as usize];
+    if !slot.transform_dirty {
+      slot.transform_dirty = true;
+      self.transform_dirty.push(index);
+    }
+  }
+
+  fn mark_dirty(&mut self, index: u32) {
+    let slot = &mut self.slots[index as usize];
+    if !slot.dirty {
+      slot.dirty = true;
+      self.dirty.push(index);
+    }
+  }
+}
+
+fn instance_data(slot: &Slot) -> InstanceData {
+  match slot.object.as_ref() {
+    Some(object) => InstanceData::new(
+      slot.world,
+      object.texture_slot.unwrap_or(PLACEHOLDER_SLOT),
+      object.uv_scale,
+      object.uv_rect,
+    ),
+    None => InstanceData::new(slot.world, 0, glam::Vec2::ONE, Rect::UNIT),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh::{Mesh2D, Vertex};
+
+  fn object(device: &wgpu::Device, translation: glam::Vec2) -> RenderObject {
+    let corner = |x: f32, y: f32| Vertex {
+      position: [x, y],
+      tex_coords: [x + 0.5, 0.5 - y],
+    };
+    let vertices = vec![corner(-0.5, -0.5), corner(0.5, -0.5), corner(0.5, 0.5), corner(-0.5, 0.5)];
+    let mesh = Mesh2D::new(vertices, vec![0, 1, 2, 0, 2, 3], device);
+    let mut object = RenderObject::new(mesh, MaterialId(0), None, None, None);
+    object.transform = Transform::from_translation(translation);
+    object
+  }
+
+  fn scene_with_chain() -> (Scene, [ObjectId; 3]) {
+    let (device, _) = wgpu::Device::noop(&Default::default());
+    let mut scene = Scene::new();
+    let root = scene.insert(object(&device, glam::vec2(1.0, 0.0)));
+    let child = scene.insert_child(root, object(&device, glam::vec2(0.0, 2.0))).unwrap();
+    let grandchild = scene.insert_child(child, object(&device, glam::vec2(3.0, 0.0))).unwrap();
+    (scene, [root, child, grandchild])
+  }
+
+  fn world_translation(scene: &Scene, id: ObjectId) -> glam::Vec2 {
+    scene.world_transform(id).unwrap().translation
+  }
+
+  #[test]
+  fn world_transforms_compose_down_the_tree() {
+    let (mut scene, [root, child, grandchild]) = scene_with_chain();
+    scene.propagate_transforms();
+    assert_eq!(world_translation(&scene, root), glam::vec2(1.0, 0.0));
+    assert_eq!(world_translation(&scene, child), glam::vec2(1.0, 2.0));
+    assert_eq!(world_translation(&scene, grandchild), glam::vec2(4.0, 2.0));
+
+    scene.set_transform(root, Transform::from_translation(glam::vec2(-1.0, 0.0)));
+    scene.propagate_transforms();
+    assert_eq!(world_translation(&scene, grandchild), glam::vec2(2.0, 2.0));
+  }
+
+  #[test]
+  fn parenting_to_a_descendant_is_a_cycle() {
+    let (mut scene, [root, child, grandchild]) = scene_with_chain();
+    assert!(scene.set_parent(root, Some(grandchild)).is_err());
+    assert!(scene.set_parent(child, Some(child)).is_err());
+    assert_eq!(scene.parent(root), None);
+    assert_eq!(scene.parent(child), Some(root));
+  }
+
+  #[test]
+  fn reparenting_keeps_the_local_transform() {
+    let (mut scene, [root, child, grandchild]) = scene_with_chain();
+    scene.set_parent(grandchild, Some(root)).unwrap();
+    assert_eq!(scene.children(child).count(), 0);
+    assert_eq!(scene.children(root).collect::<Vec<_>>(), vec![child, grandchild]);
+
+    scene.propagate_transforms();
+    assert_eq!(world_translation(&scene, grandchild), glam::vec2(4.0, 0.0));
+
+    scene.detach(grandchild).unwrap();
+    scene.propagate_transforms();
+    assert_eq!(scene.parent(grandchild), None);
+    assert_eq!(world_translation(&scene, grandchild), glam::vec2(3.0, 0.0));
+  }
+
+  #[test]
+  fn removing_a_parent_turns_its_children_into_roots() {
+    let (mut scene, [root, child, grandchild]) = scene_with_chain();
+    scene.remove(child).unwrap();
+    assert!(!scene.contains(child));
+    assert_eq!(scene.parent(grandchild), None);
+    assert_eq!(scene.children(root).count(), 0);
+    assert_eq!(scene.len(), 2);
+  }
+
+  #[test]
+  fn remove_subtree_removes_every_descendant() {
+    let (mut scene, [root, child, grandchild]) = scene_with_chain();
+    let (device, _) = wgpu::Device::noop(&Default::default());
+    let sibling = scene.insert(object(&device, glam::Vec2::ZERO));
+
+    scene.remove_subtree(child).unwrap();
+    assert!(scene.contains(root) && scene.contains(sibling));
+    assert!(!scene.contains(child) && !scene.contains(grandchild));
+    assert_eq!(scene.len(), 2);
+
+    // Reused slots don't bring stale handles back
+    let reused = scene.insert(object(&device, glam::Vec2::ZERO));
+    assert!(scene.contains(reused));
+    assert!(!scene.contains(child) && !scene.contains(grandchild));
+  }
+}
@@ -1,6 +1,118 @@
-use crate::render_resource::ShaderEffect;
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+  mesh::{InstanceData, Vertex},
+  render_resource::{RenderTarget, ShaderEffect, render_pipeline::{BlendMode, PipelineBuilder}},
+};
+
+/// Entry points every effect shader is expected to define.
+/// Vertex shaders read `mesh::Vertex` at locations 0 and 1, and
+/// `mesh::InstanceData` at locations 2 to 8.
+pub const VERTEX_ENTRY: &str = "vs_main";
+pub const FRAGMENT_ENTRY: &str = "fs_main";
+
+/// Fixed function state a shader effect is drawn with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PipelineState {
+  pub cull_mode: wgpu::Face,
+  pub polygon_mode: wgpu::PolygonMode,
+  /// Draws after opaque objects at the same depth, as blend modes other than `Opaque` do
+  pub transparent: bool,
+  /// Blend mode of the main pipeline, objects and materials can pick others
+  pub blend: BlendMode,
+}
+
+impl Default for PipelineState {
+  fn default() -> Self {
+    Self {
+      cull_mode: wgpu::Face::Back,
+      polygon_mode: wgpu::PolygonMode::Fill,
+      transparent: false,
+      blend: BlendMode::Opaque,
+    }
+  }
+}
 
 pub struct ShaderPass {
+  /// Pipeline blending with `PipelineState::blend`
   pub render_pipeline: wgpu::RenderPipeline,
-  shader_effect: ShaderEffect
-}
\ No newline at end of file
+  shader_effect: Rc<ShaderEffect>,
+  state: PipelineState,
+  format: wgpu::TextureFormat,
+  /// Pipelines of the other blend modes, and of every mode testing the depth of
+  /// `RenderTarget`s, built on first use
+  variants: HashMap<(BlendMode, bool), wgpu::RenderPipeline>,
+}
+
+impl ShaderPass {
+  pub fn new(
+    device: &wgpu::Device,
+    shader_effect: Rc<ShaderEffect>,
+    state: PipelineState,
+    format: wgpu::TextureFormat,
+  ) -> Result<Self> {
+    let render_pipeline = create_pipeline(device, &shader_effect, &state, state.blend, format, false)?;
+
+    Ok(Self {
+      render_pipeline,
+      shader_effect,
+      state,
+      format,
+      variants: HashMap::new(),
+    })
+  }
+
+  /// Builds the pipeline blending with `mode`, testing `RenderTarget::DEPTH_FORMAT`
+  /// depth when `depth` is set, unless it exists already
+  pub fn prepare_blend(&mut self, device: &wgpu::Device, mode: BlendMode, depth: bool) -> Result<()> {
+    if (mode == self.state.blend && !depth) || self.variants.contains_key(&(mode, depth)) {
+      return Ok(());
+    }
+    let pipeline = create_pipeline(device, &self.shader_effect, &self.state, mode, self.format, depth)?;
+    self.variants.insert((mode, depth), pipeline);
+    Ok(())
+  }
+
+  /// Pipeline blending with `mode` and testing depth when `depth` is set. Other modes
+  /// than the state's and depth testing pipelines need `prepare_blend` first.
+  pub fn pipeline(&self, mode: BlendMode, depth: bool) -> Option<&wgpu::RenderPipeline> {
+    match mode == self.state.blend && !depth {
+      true => Some(&self.render_pipeline),
+      false => self.variants.get(&(mode, depth)),
+    }
+  }
+
+  pub fn shader_effect(&self) -> &ShaderEffect {
+    &self.shader_effect
+  }
+
+  pub fn state(&self) -> &PipelineState {
+    &self.state
+  }
+}
+
+fn create_pipeline(
+  device: &wgpu::Device,
+  shader_effect: &ShaderEffect,
+  state: &PipelineState,
+  blend: BlendMode,
+  format: wgpu::TextureFormat,
+  depth: bool,
+) -> Result<wgpu::RenderPipeline> {
+  let mut pipeline_builder = PipelineBuilder::new();
+  pipeline_builder.set_layout(shader_effect.pipeline_layout());
+  pipeline_builder.add_blended_target(format, blend);
+  pipeline_builder.set_vertex_buffers(vec![Vertex::desc(), InstanceData::desc()]);
+  pipeline_builder.set_vertex(shader_effect.module(), VERTEX_ENTRY);
+  pipeline_builder.set_fragment(shader_effect.module(), FRAGMENT_ENTRY);
+  pipeline_builder.set_cull_mode(state.cull_mode);
+  pipeline_builder.set_polygon_mode(state.polygon_mode);
+  if depth {
+    // Equal depths pass, so objects drawn at the same depth still follow their draw order
+    let compare = wgpu::CompareFunction::LessEqual;
+    pipeline_builder.set_depth(RenderTarget::DEPTH_FORMAT, compare, !blend.is_transparent());
+  }
+  pipeline_builder.create_pipeline(device).map_err(|e| anyhow!(e))
+}
@@ -0,0 +1,198 @@
+use crate::{
+  camera::Camera,
+  render_resource::{Cubemap, RenderTarget, render_pipeline::PipelineBuilder},
+};
+
+/// Bytes of the inverse view projection matrix of a view
+const VIEW_SIZE: u64 = std::mem::size_of::<[f32; 16]>() as u64;
+/// Views the uniform buffer holds before it grows
+const INITIAL_VIEW_CAPACITY: u64 = 4;
+
+/// Draws a cubemap as the background of a pass, seen through the rotation and field of
+/// view of a `Camera`. There's no depth test, so it has to be drawn before everything else.
+///
+/// Every `render` call of a frame writes its view to its own part of the uniform buffer,
+/// bound with a dynamic offset, so passes into targets of other sizes or seen by other
+/// cameras keep their own view.
+pub struct Skybox {
+  cubemap: Cubemap,
+  pipeline: wgpu::RenderPipeline,
+  /// Same pipeline for passes into `RenderTarget`s with depth
+  depth_pipeline: wgpu::RenderPipeline,
+  bind_group_layout: wgpu::BindGroupLayout,
+  sampler: wgpu::Sampler,
+  uniform_buffer: wgpu::Buffer,
+  bind_group: wgpu::BindGroup,
+  /// Bytes between views, the uniform offset alignment of the device
+  view_stride: u64,
+  view_capacity: u64,
+  /// Views written since `begin_frame`
+  view_count: u64,
+}
+
+impl Skybox {
+  /// Draws into passes of `format` targets, sampling `cubemap` with `sampler`
+  pub fn new(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    cubemap: Cubemap,
+    sampler: &wgpu::Sampler,
+  ) -> Self {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Skybox Bind Group Layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: wgpu::BufferSize::new(VIEW_SIZE),
+          },
+          count: None,
+        },
+      ],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Skybox Pipeline Layout"),
+      bind_group_layouts: &[&bind_group_layout],
+      immediate_size: 0,
+    });
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../../../shaders/skybox.wgsl"));
+
+    let mut pipeline_builder = PipelineBuilder::new();
+    pipeline_builder.set_layout(&layout);
+    pipeline_builder.add_target(format);
+    pipeline_builder.set_vertex_buffers(Vec::new());
+    pipeline_builder.set_vertex(&shader, "vs_main");
+    pipeline_builder.set_fragment(&shader, "fs_main");
+    let pipeline = pipeline_builder.clone().create_pipeline(device).unwrap();
+    // The sky is behind everything, it doesn't test nor write depth
+    pipeline_builder.set_depth(RenderTarget::DEPTH_FORMAT, wgpu::CompareFunction::Always, false);
+    let depth_pipeline = pipeline_builder.create_pipeline(device).unwrap();
+
+    let view_stride = VIEW_SIZE.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment as u64);
+    let (uniform_buffer, bind_group) = Self::create_views(
+      device,
+      &bind_group_layout,
+      &cubemap,
+      sampler,
+      view_stride * INITIAL_VIEW_CAPACITY,
+    );
+
+    Self {
+      cubemap,
+      pipeline,
+      depth_pipeline,
+      bind_group_layout,
+      sampler: sampler.clone(),
+      uniform_buffer,
+      bind_group,
+      view_stride,
+      view_capacity: INITIAL_VIEW_CAPACITY,
+      view_count: 0,
+    }
+  }
+
+  fn create_views(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    cubemap: &Cubemap,
+    sampler: &wgpu::Sampler,
+    size: u64,
+  ) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Skybox Uniform Buffer"),
+      size,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("skybox_bind_group"),
+      layout: bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&cubemap.view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &uniform_buffer,
+            offset: 0,
+            size: wgpu::BufferSize::new(VIEW_SIZE),
+          }),
+        },
+      ],
+    });
+    (uniform_buffer, bind_group)
+  }
+
+  pub fn cubemap(&self) -> &Cubemap {
+    &self.cubemap
+  }
+
+  /// Lets the next `render` calls reuse the views of the previous frame
+  pub fn begin_frame(&mut self) {
+    self.view_count = 0;
+  }
+
+  /// Draws the sky seen by `camera` over a whole `target_size` target, whose pass has a
+  /// `RenderTarget::DEPTH_FORMAT` depth attachment when `depth` is set. The uniform buffer
+  /// grows when a frame draws more views than it holds, passes recorded before keep the old one.
+  pub fn render(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    render_pass: &mut wgpu::RenderPass,
+    camera: &Camera,
+    target_size: glam::UVec2,
+    depth: bool,
+  ) {
+    if self.view_count == self.view_capacity {
+      self.view_capacity *= 2;
+      (self.uniform_buffer, self.bind_group) = Self::create_views(
+        device,
+        &self.bind_group_layout,
+        &self.cubemap,
+        &self.sampler,
+        self.view_stride * self.view_capacity,
+      );
+      // Views already written went to the old buffer
+      self.view_count = 0;
+    }
+
+    let aspect = target_size.x.max(1) as f32 / target_size.y.max(1) as f32;
+    let inverse_view_projection = camera.rotation_view_projection(aspect).inverse();
+    let offset = self.view_count * self.view_stride;
+    queue.write_buffer(&self.uniform_buffer, offset, bytemuck::bytes_of(&inverse_view_projection.to_cols_array()));
+    self.view_count += 1;
+
+    render_pass.set_pipeline(match depth {
+      true => &self.depth_pipeline,
+      false => &self.pipeline,
+    });
+    render_pass.set_bind_group(0, &self.bind_group, &[offset as u32]);
+    render_pass.draw(0..3, 0..1);
+  }
+}
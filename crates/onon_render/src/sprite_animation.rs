@@ -0,0 +1,528 @@
+use crate::{
+  rect::Rect,
+  scene::{ObjectId, Scene},
+};
+use anyhow::{Context, Result, anyhow};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+/// A frame of a sprite sheet
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteFrame {
+  /// Pixels of the frame in the sheet
+  pub rect: Rect,
+  /// Layer holding the frame when the frames aren't all in one texture,
+  /// `rect` is then in pixels of that texture, which is as big as the sheet
+  pub texture_slot: Option<u32>,
+  pub duration: Duration,
+}
+
+/// How an animation continues once past its last frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PlaybackMode {
+  /// Stops on the last frame
+  Once,
+  /// Starts over from the first frame
+  Loop,
+  /// Plays backwards to the first frame, then forwards again
+  PingPong,
+}
+
+/// A named range of frames, from `from` to `to` included
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteAnimation {
+  pub from: usize,
+  pub to: usize,
+  pub mode: PlaybackMode,
+  /// Starts from `to` and plays towards `from`
+  pub reverse: bool,
+}
+
+/// Frames of a texture and the animations made of them. Frames are in pixels, they are
+/// turned into UV rects of the `size` sheet by `uv_rect`.
+#[derive(Debug, Clone, Default)]
+pub struct SpriteSheet {
+  pub size: glam::UVec2,
+  pub frames: Vec<SpriteFrame>,
+  pub animations: HashMap<String, SpriteAnimation>,
+}
+
+impl SpriteSheet {
+  pub fn new(size: glam::UVec2, frames: Vec<SpriteFrame>) -> Self {
+    Self {
+      size,
+      frames,
+      animations: HashMap::new(),
+    }
+  }
+
+  /// Cuts a `size` sheet into `frame_size` cells, row by row from the top left, keeping the
+  /// first `frame_count` when given. Every frame lasts `duration`.
+  pub fn from_grid(
+    size: glam::UVec2,
+    frame_size: glam::UVec2,
+    frame_count: Option<usize>,
+    duration: Duration,
+  ) -> Self {
+    let columns = size.x / frame_size.x.max(1);
+    let rows = size.y / frame_size.y.max(1);
+    let frames = (0..rows)
+      .flat_map(|row| (0..columns).map(move |column| (column, row)))
+      .take(frame_count.unwrap_or(usize::MAX))
+      .map(|(column, row)| SpriteFrame {
+        rect: Rect::new(
+          (column * frame_size.x) as f32,
+          (row * frame_size.y) as f32,
+          frame_size.x as f32,
+          frame_size.y as f32,
+        ),
+        texture_slot: None,
+        duration,
+      })
+      .collect();
+    Self::new(size, frames)
+  }
+
+  /// Reads an Aseprite JSON export, with frames either as a hash or an array. Frame tags
+  /// become animations: `pingpong` ones ping-pong, tags repeated once play once, the
+  /// others loop. Trimmed frames are drawn without their offset, export them untrimmed.
+  pub fn from_aseprite_json(json: &str) -> Result<Self> {
+    let export: aseprite::Export = serde_json::from_str(json).context("Invalid Aseprite JSON")?;
+
+    let frames = export
+      .frames
+      .0
+      .into_iter()
+      .map(|frame| SpriteFrame {
+        rect: Rect::new(frame.frame.x, frame.frame.y, frame.frame.w, frame.frame.h),
+        texture_slot: None,
+        duration: Duration::from_millis(frame.duration),
+      })
+      .collect::<Vec<_>>();
+
+    let mut sheet = Self::new(glam::uvec2(export.meta.size.w as u32, export.meta.size.h as u32), frames);
+    for tag in export.meta.frame_tags {
+      if tag.from > tag.to || tag.to >= sheet.frames.len() {
+        return Err(anyhow!("Frame tag {:?} is out of the {} frames", tag.name, sheet.frames.len()));
+      }
+      let mode = match (tag.direction.as_str(), tag.repeat.as_deref()) {
+        (_, Some("1")) => PlaybackMode::Once,
+        ("pingpong" | "pingpong_reverse", _) => PlaybackMode::PingPong,
+        _ => PlaybackMode::Loop,
+      };
+      let animation = SpriteAnimation {
+        from: tag.from,
+        to: tag.to,
+        mode,
+        reverse: matches!(tag.direction.as_str(), "reverse" | "pingpong_reverse"),
+      };
+      sheet.animations.insert(tag.name, animation);
+    }
+    Ok(sheet)
+  }
+
+  pub fn load_aseprite(path: &std::path::Path) -> Result<Self> {
+    let json = std::fs::read_to_string(path).context(format!("{:?}", path))?;
+    Self::from_aseprite_json(&json).context(format!("{:?}", path))
+  }
+
+  pub fn add_animation(&mut self, name: impl Into<String>, animation: SpriteAnimation) {
+    self.animations.insert(name.into(), animation);
+  }
+
+  /// Rect of `frame` in texture coordinates of the sheet, see `RenderObject::uv_rect`
+  pub fn uv_rect(&self, frame: usize) -> Option<Rect> {
+    let rect = self.frames.get(frame)?.rect;
+    let size = self.size.max(glam::UVec2::ONE).as_vec2();
+    Some(Rect::from_min_max(rect.min() / size, rect.max() / size))
+  }
+}
+
+/// Plays the animations of a `SpriteSheet`, advanced by `update` and shown by `apply`
+/// setting the UV rect, and the texture slot of frames that have one, of a scene object.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+  sheet: Arc<SpriteSheet>,
+  animation: SpriteAnimation,
+  /// Position in the frame sequence, counting the way back of ping-pong animations
+  step: usize,
+  elapsed: Duration,
+  pub speed: f32,
+  pub paused: bool,
+  finished: bool,
+}
+
+impl AnimationPlayer {
+  /// Loops through every frame of `sheet`
+  pub fn new(sheet: Arc<SpriteSheet>) -> Self {
+    let animation = SpriteAnimation {
+      from: 0,
+      to: sheet.frames.len().saturating_sub(1),
+      mode: PlaybackMode::Loop,
+      reverse: false,
+    };
+
+    Self {
+      sheet,
+      animation,
+      step: 0,
+      elapsed: Duration::ZERO,
+      speed: 1.0,
+      paused: false,
+      finished: false,
+    }
+  }
+
+  pub fn sheet(&self) -> &Arc<SpriteSheet> {
+    &self.sheet
+  }
+
+  /// Starts the animation called `name` of the sheet from its first frame
+  pub fn play(&mut self, name: &str) -> Result<()> {
+    let animation = self
+      .sheet
+      .animations
+      .get(name)
+      .cloned()
+      .context(format!("The sprite sheet has no {:?} animation", name))?;
+    self.play_animation(animation);
+    Ok(())
+  }
+
+  pub fn play_animation(&mut self, animation: SpriteAnimation) {
+    self.animation = animation;
+    self.step = 0;
+    self.elapsed = Duration::ZERO;
+    self.paused = false;
+    self.finished = false;
+  }
+
+  /// Whether a `Once` animation reached its last frame
+  pub fn is_finished(&self) -> bool {
+    self.finished
+  }
+
+  /// Index of the shown frame in the sheet
+  pub fn frame(&self) -> usize {
+    let length = self.animation.to.saturating_sub(self.animation.from) + 1;
+    // Past the last frame, ping-pong animations walk back without repeating the ends
+    let offset = if self.step < length { self.step } else { 2 * (length - 1) - self.step };
+    match self.animation.reverse {
+      true => self.animation.to - offset,
+      false => self.animation.from + offset,
+    }
+  }
+
+  /// Advances by `delta` scaled by `speed`, returns whether the frame changed
+  pub fn update(&mut self, delta: Duration) -> bool {
+    if self.paused || self.finished || self.sheet.frames.is_empty() {
+      return false;
+    }
+
+    let mut changed = false;
+    self.elapsed += delta.mul_f32(self.speed.max(0.0));
+    while let Some(frame) = self.sheet.frames.get(self.frame()) {
+      // Zero length frames would never let go of the loop
+      let duration = frame.duration.max(Duration::from_millis(1));
+      if self.elapsed < duration {
+        break;
+      }
+      self.elapsed -= duration;
+      if !self.next_step() {
+        self.finished = true;
+        self.elapsed = Duration::ZERO;
+        break;
+      }
+      changed = true;
+    }
+    changed
+  }
+
+  /// Shows the current frame on the object `id` of `scene`. Frames with a texture slot
+  /// switch the object to it through `Scene::set_texture_slot`, keeping reference counts right.
+  pub fn apply(&self, scene: &mut Scene, id: ObjectId) -> bool {
+    let frame = self.frame();
+    let (Some(uv_rect), Some(sprite_frame)) = (self.sheet.uv_rect(frame), self.sheet.frames.get(frame)) else {
+      return false;
+    };
+    let Some(object) = scene.get(id) else {
+      return false;
+    };
+
+    if let Some(slot) = sprite_frame.texture_slot
+      && object.texture_slot != Some(slot)
+    {
+      let uv_scale = object.uv_scale;
+      scene.set_texture_slot(id, slot, uv_scale);
+    }
+    scene.set_uv_rect(id, uv_rect)
+  }
+
+  /// Moves to the next step of the sequence, `false` when a `Once` animation is over
+  fn next_step(&mut self) -> bool {
+    let length = self.animation.to.saturating_sub(self.animation.from) + 1;
+    let steps = match self.animation.mode {
+      PlaybackMode::PingPong if length > 1 => 2 * (length - 1),
+      _ => length,
+    };
+    if self.step + 1 < steps {
+      self.step += 1;
+      return true;
+    }
+    match self.animation.mode {
+      PlaybackMode::Once => false,
+      PlaybackMode::Loop | PlaybackMode::PingPong => {
+        self.step = 0;
+        true
+      }
+    }
+  }
+}
+
+/// The parts of Aseprite's JSON export the sheet needs
+mod aseprite {
+  use serde::{Deserialize, Deserializer};
+
+  #[derive(Deserialize)]
+  pub struct Export {
+    pub frames: Frames,
+    pub meta: Meta,
+  }
+
+  /// Frames in file order, exported either as an array or as a hash keyed by file name
+  pub struct Frames(pub Vec<Frame>);
+
+  impl<'de> Deserialize<'de> for Frames {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+      deserializer.deserialize_any(FramesVisitor)
+    }
+  }
+
+  struct FramesVisitor;
+
+  impl<'de> serde::de::Visitor<'de> for FramesVisitor {
+    type Value = Frames;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+      formatter.write_str("an array or a map of frames")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Frames, A::Error> {
+      let mut frames = Vec::new();
+      while let Some(frame) = seq.next_element()? {
+        frames.push(frame);
+      }
+      Ok(Frames(frames))
+    }
+
+    // Visited in file order, which a map type would lose
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Frames, A::Error> {
+      let mut frames = Vec::new();
+      while let Some((_, frame)) = map.next_entry::<String, Frame>()? {
+        frames.push(frame);
+      }
+      Ok(Frames(frames))
+    }
+  }
+
+  #[derive(Deserialize)]
+  pub struct Frame {
+    pub frame: FrameRect,
+    /// Milliseconds
+    pub duration: u64,
+  }
+
+  #[derive(Deserialize)]
+  pub struct FrameRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+  }
+
+  #[derive(Deserialize)]
+  #[serde(rename_all = "camelCase")]
+  pub struct Meta {
+    pub size: Size,
+    #[serde(default)]
+    pub frame_tags: Vec<FrameTag>,
+  }
+
+  #[derive(Deserialize)]
+  pub struct Size {
+    pub w: f32,
+    pub h: f32,
+  }
+
+  #[derive(Deserialize)]
+  pub struct FrameTag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    #[serde(default)]
+    pub direction: String,
+    /// Number of times to play, as a string, missing when it repeats forever
+    pub repeat: Option<String>,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sheet(frame_count: usize) -> Arc<SpriteSheet> {
+    let size = glam::uvec2(16 * frame_count as u32, 16);
+    Arc::new(SpriteSheet::from_grid(size, glam::uvec2(16, 16), None, Duration::from_millis(100)))
+  }
+
+  /// Frames shown after each of `count` updates of one frame duration
+  fn play(player: &mut AnimationPlayer, count: usize) -> Vec<usize> {
+    (0..count)
+      .map(|_| {
+        player.update(Duration::from_millis(100));
+        player.frame()
+      })
+      .collect()
+  }
+
+  fn animation(from: usize, to: usize, mode: PlaybackMode, reverse: bool) -> SpriteAnimation {
+    SpriteAnimation { from, to, mode, reverse }
+  }
+
+  #[test]
+  fn loop_starts_over() {
+    let mut player = AnimationPlayer::new(sheet(3));
+    assert_eq!(player.frame(), 0);
+    assert_eq!(play(&mut player, 4), [1, 2, 0, 1]);
+    assert!(!player.is_finished());
+  }
+
+  #[test]
+  fn ping_pong_doesnt_repeat_the_ends() {
+    let mut player = AnimationPlayer::new(sheet(4));
+    player.play_animation(animation(1, 3, PlaybackMode::PingPong, false));
+    assert_eq!(player.frame(), 1);
+    assert_eq!(play(&mut player, 6), [2, 3, 2, 1, 2, 3]);
+
+    player.play_animation(animation(1, 3, PlaybackMode::PingPong, true));
+    assert_eq!(player.frame(), 3);
+    assert_eq!(play(&mut player, 4), [2, 1, 2, 3]);
+  }
+
+  #[test]
+  fn ping_pong_of_one_frame_stays_on_it() {
+    let mut player = AnimationPlayer::new(sheet(3));
+    player.play_animation(animation(2, 2, PlaybackMode::PingPong, false));
+    assert_eq!(play(&mut player, 3), [2, 2, 2]);
+  }
+
+  #[test]
+  fn once_stops_on_the_last_frame() {
+    let mut player = AnimationPlayer::new(sheet(3));
+    player.play_animation(animation(0, 2, PlaybackMode::Once, false));
+    assert!(player.update(Duration::from_millis(100)));
+    assert!(player.update(Duration::from_millis(100)));
+    assert!(!player.is_finished());
+    assert!(!player.update(Duration::from_millis(100)));
+    assert!(player.is_finished());
+    assert_eq!(player.frame(), 2);
+    assert!(!player.update(Duration::from_secs(1)));
+    assert_eq!(player.frame(), 2);
+
+    player.play_animation(animation(0, 2, PlaybackMode::Once, true));
+    assert!(!player.is_finished());
+    assert_eq!(play(&mut player, 3), [1, 0, 0]);
+    assert!(player.is_finished());
+  }
+
+  #[test]
+  fn update_catches_up_with_long_deltas() {
+    let mut player = AnimationPlayer::new(sheet(4));
+    assert!(!player.update(Duration::from_millis(50)));
+    assert!(player.update(Duration::from_millis(260)));
+    assert_eq!(player.frame(), 3);
+    // The 10 milliseconds left count towards the next frame
+    assert!(player.update(Duration::from_millis(95)));
+    assert_eq!(player.frame(), 0);
+
+    player.speed = 2.0;
+    assert!(player.update(Duration::from_millis(100)));
+    assert_eq!(player.frame(), 2);
+    player.paused = true;
+    assert!(!player.update(Duration::from_secs(1)));
+    assert_eq!(player.frame(), 2);
+  }
+
+  const FRAMES_HASH: &str = r#"{
+    "frames": {
+      "walk 1.aseprite": { "frame": { "x": 32, "y": 0, "w": 32, "h": 16 }, "duration": 100 },
+      "walk 0.aseprite": { "frame": { "x": 0, "y": 0, "w": 32, "h": 16 }, "duration": 50 }
+    },
+    "meta": { "size": { "w": 64, "h": 16 } }
+  }"#;
+
+  const FRAMES_ARRAY: &str = r#"{
+    "frames": [
+      { "filename": "walk 1.aseprite", "frame": { "x": 32, "y": 0, "w": 32, "h": 16 }, "duration": 100 },
+      { "filename": "walk 0.aseprite", "frame": { "x": 0, "y": 0, "w": 32, "h": 16 }, "duration": 50 }
+    ],
+    "meta": { "size": { "w": 64, "h": 16 } }
+  }"#;
+
+  #[test]
+  fn aseprite_frames_keep_the_file_order() {
+    let hash = SpriteSheet::from_aseprite_json(FRAMES_HASH).unwrap();
+    let array = SpriteSheet::from_aseprite_json(FRAMES_ARRAY).unwrap();
+    assert_eq!(hash.frames, array.frames);
+    assert_eq!(hash.size, glam::uvec2(64, 16));
+    assert_eq!(hash.frames.len(), 2);
+    assert_eq!(hash.frames[0].rect, Rect::new(32.0, 0.0, 32.0, 16.0));
+    assert_eq!(hash.frames[0].duration, Duration::from_millis(100));
+    assert_eq!(hash.frames[1].duration, Duration::from_millis(50));
+    assert_eq!(hash.uv_rect(0), Some(Rect::new(0.5, 0.0, 0.5, 1.0)));
+    assert!(hash.animations.is_empty());
+  }
+
+  fn with_tags(tags: &str) -> Result<SpriteSheet> {
+    let frames = (0..4)
+      .map(|index| format!(r#"{{ "frame": {{ "x": {}, "y": 0, "w": 8, "h": 8 }}, "duration": 100 }}"#, index * 8))
+      .collect::<Vec<_>>()
+      .join(",");
+    SpriteSheet::from_aseprite_json(&format!(
+      r#"{{ "frames": [{frames}], "meta": {{ "size": {{ "w": 32, "h": 8 }}, "frameTags": [{tags}] }} }}"#
+    ))
+  }
+
+  #[test]
+  fn aseprite_tags_become_animations() {
+    let sheet = with_tags(
+      r#"
+      { "name": "idle", "from": 0, "to": 1, "direction": "forward" },
+      { "name": "back", "from": 1, "to": 3, "direction": "reverse" },
+      { "name": "bounce", "from": 0, "to": 3, "direction": "pingpong_reverse" },
+      { "name": "hit", "from": 2, "to": 3, "direction": "pingpong", "repeat": "1" },
+      { "name": "twice", "from": 2, "to": 3, "direction": "forward", "repeat": "2" }
+      "#,
+    )
+    .unwrap();
+    let animations = &sheet.animations;
+    assert_eq!(animations["idle"], animation(0, 1, PlaybackMode::Loop, false));
+    assert_eq!(animations["back"], animation(1, 3, PlaybackMode::Loop, true));
+    assert_eq!(animations["bounce"], animation(0, 3, PlaybackMode::PingPong, true));
+    // Playing once wins over the direction
+    assert_eq!(animations["hit"], animation(2, 3, PlaybackMode::Once, false));
+    assert_eq!(animations["twice"], animation(2, 3, PlaybackMode::Loop, false));
+
+    let mut player = AnimationPlayer::new(Arc::new(sheet));
+    player.play("back").unwrap();
+    assert_eq!(player.frame(), 3);
+    assert!(player.play("run").is_err());
+  }
+
+  #[test]
+  fn aseprite_tags_must_be_in_the_frames() {
+    assert!(with_tags(r#"{ "name": "past", "from": 2, "to": 4 }"#).is_err());
+    assert!(with_tags(r#"{ "name": "backwards", "from": 3, "to": 1 }"#).is_err());
+    assert!(with_tags(r#"{ "name": "last", "from": 3, "to": 3 }"#).is_ok());
+    assert!(SpriteSheet::from_aseprite_json(r#"{ "frames": [] }"#).is_err());
+  }
+}
@@ -0,0 +1,266 @@
+use crate::{
+  mesh::SpriteVertex,
+  rect::Rect,
+  render_resource::{
+    TextureArrayId,
+    render_pipeline::BlendMode,
+    texture_array::SlotRegion,
+  },
+};
+use std::ops::Range;
+use winit::dpi::PhysicalSize;
+
+/// Number of vertex buffers the batch cycles through, so that we never
+/// write into a buffer the GPU may still be reading from.
+const FRAMES_IN_FLIGHT: usize = 3;
+const INITIAL_QUAD_CAPACITY: u64 = 256;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Flip {
+  pub horizontal: bool,
+  pub vertical: bool,
+}
+
+impl Flip {
+  pub const NONE: Self = Self { horizontal: false, vertical: false };
+  pub const HORIZONTAL: Self = Self { horizontal: true, vertical: false };
+  pub const VERTICAL: Self = Self { horizontal: false, vertical: true };
+  pub const BOTH: Self = Self { horizontal: true, vertical: true };
+}
+
+struct Sprite {
+  texture_slot: u32,
+  src_rect: Option<Rect>,
+  dst_rect: Rect,
+  rotation: f32,
+  color: [f32; 4],
+  flip: Flip,
+}
+
+/// Run of consecutive quads sharing the same texture array and blend
+/// mode, drawn with a single draw call.
+pub struct SpriteSegment {
+  pub texture_array: Option<TextureArrayId>,
+  pub blend: BlendMode,
+  quads: Range<u32>,
+}
+
+impl SpriteSegment {
+  pub fn indices(&self) -> Range<u32> {
+    self.quads.start * 6..self.quads.end * 6
+  }
+}
+
+/// Collects sprites for one frame and streams them to the GPU.
+///
+/// Positions are given in pixels with the origin in the top left corner
+/// of the surface, source rects are given in texels of the texture array layer.
+pub struct SpriteBatch {
+  sprites: Vec<Sprite>,
+  vertices: Vec<SpriteVertex>,
+  segments: Vec<SpriteSegment>,
+  current_texture_array: Option<TextureArrayId>,
+  current_blend: BlendMode,
+
+  vertex_buffers: Vec<wgpu::Buffer>,
+  quad_capacity: u64,
+  frame_index: usize,
+  index_buffer: wgpu::Buffer,
+}
+
+impl SpriteBatch {
+  pub fn new(device: &wgpu::Device) -> Self {
+    Self {
+      sprites: Vec::new(),
+      vertices: Vec::new(),
+      segments: Vec::new(),
+      current_texture_array: None,
+      current_blend: BlendMode::Alpha,
+      vertex_buffers: (0..FRAMES_IN_FLIGHT)
+        .map(|_| create_vertex_buffer(device, INITIAL_QUAD_CAPACITY))
+        .collect(),
+      quad_capacity: INITIAL_QUAD_CAPACITY,
+      frame_index: 0,
+      index_buffer: create_index_buffer(device, INITIAL_QUAD_CAPACITY),
+    }
+  }
+
+  /// Texture array used by the following `draw` calls, `None` uses the
+  /// renderer's default texture array.
+  pub fn set_texture_array(&mut self, texture_array: Option<TextureArrayId>) {
+    self.current_texture_array = texture_array;
+  }
+
+  /// Blend mode of the following `draw` calls, `BlendMode::Alpha` at first
+  pub fn set_blend_mode(&mut self, blend: BlendMode) {
+    self.current_blend = blend;
+  }
+
+  /// Queues a sprite, `rotation` is in radians around the center of `dst_rect`.
+  /// `src_rect` is in texels of the uploaded texture, `None` samples all of it.
+  pub fn draw(
+    &mut self,
+    texture_slot: u32,
+    src_rect: Option<Rect>,
+    dst_rect: Rect,
+    rotation: f32,
+    color: [f32; 4],
+    flip: Flip,
+  ) {
+    let quad = self.quad_count();
+    match self.segments.last_mut() {
+      Some(segment)
+        if segment.texture_array == self.current_texture_array && segment.blend == self.current_blend =>
+      {
+        segment.quads.end = quad + 1;
+      }
+      _ => self.segments.push(SpriteSegment {
+        texture_array: self.current_texture_array,
+        blend: self.current_blend,
+        quads: quad..quad + 1,
+      }),
+    }
+
+    self.sprites.push(Sprite {
+      texture_slot,
+      src_rect,
+      dst_rect,
+      rotation,
+      color,
+      flip,
+    });
+  }
+
+  pub fn quad_count(&self) -> u32 {
+    self.sprites.len() as u32
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.sprites.is_empty()
+  }
+
+  pub fn segments(&self) -> &[SpriteSegment] {
+    &self.segments
+  }
+
+  /// Converts the queued quads to clip space and uploads them into the
+  /// next buffer of the ring, growing the buffers when needed.
+  /// `region` resolves where a slot's texture lies in its texture array.
+  pub fn prepare<F>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    surface_size: PhysicalSize<u32>,
+    region: F,
+  ) where
+    F: Fn(Option<TextureArrayId>, u32) -> SlotRegion,
+  {
+    if self.is_empty() {
+      return;
+    }
+
+    let quads = self.quad_count() as u64;
+    if quads > self.quad_capacity {
+      self.quad_capacity = quads.next_power_of_two();
+      self.vertex_buffers = (0..FRAMES_IN_FLIGHT)
+        .map(|_| create_vertex_buffer(device, self.quad_capacity))
+        .collect();
+      self.index_buffer = create_index_buffer(device, self.quad_capacity);
+    }
+
+    let scale = glam::vec2(
+      2.0 / surface_size.width.max(1) as f32,
+      -2.0 / surface_size.height.max(1) as f32,
+    );
+    self.vertices.clear();
+    for segment in &self.segments {
+      for sprite in &self.sprites[segment.quads.start as usize..segment.quads.end as usize] {
+        let region = region(segment.texture_array, sprite.texture_slot);
+        push_quad(&mut self.vertices, sprite, region, scale);
+      }
+    }
+
+    queue.write_buffer(
+      &self.vertex_buffers[self.frame_index],
+      0,
+      bytemuck::cast_slice(&self.vertices),
+    );
+  }
+
+  pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+    &self.vertex_buffers[self.frame_index]
+  }
+
+  pub fn index_buffer(&self) -> &wgpu::Buffer {
+    &self.index_buffer
+  }
+
+  /// Clears the queued sprites and moves on to the next buffer of the ring.
+  pub fn finish_frame(&mut self) {
+    self.sprites.clear();
+    self.segments.clear();
+    self.frame_index = (self.frame_index + 1) % self.vertex_buffers.len();
+  }
+}
+
+fn push_quad(vertices: &mut Vec<SpriteVertex>, sprite: &Sprite, region: SlotRegion, scale: glam::Vec2) {
+  // Texels of the uploaded texture to its part of the layer
+  let texel = region.uv_scale / region.size.as_vec2().max(glam::Vec2::ONE);
+  let (mut uv_min, mut uv_max) = match sprite.src_rect {
+    Some(src) => (src.min() * texel, src.max() * texel),
+    None => (glam::Vec2::ZERO, region.uv_scale),
+  };
+  if sprite.flip.horizontal {
+    std::mem::swap(&mut uv_min.x, &mut uv_max.x);
+  }
+  if sprite.flip.vertical {
+    std::mem::swap(&mut uv_min.y, &mut uv_max.y);
+  }
+
+  let center = sprite.dst_rect.center();
+  let half = glam::vec2(sprite.dst_rect.width, sprite.dst_rect.height) * 0.5;
+  let rotation = glam::Vec2::from_angle(sprite.rotation);
+  let corners = [
+    (glam::vec2(-half.x, -half.y), [uv_min.x, uv_min.y]),
+    (glam::vec2(-half.x, half.y), [uv_min.x, uv_max.y]),
+    (glam::vec2(half.x, half.y), [uv_max.x, uv_max.y]),
+    (glam::vec2(half.x, -half.y), [uv_max.x, uv_min.y]),
+  ];
+
+  for (offset, tex_coords) in corners {
+    // Pixels with a top left origin to clip space
+    let position = (center + rotation.rotate(offset)) * scale + glam::vec2(-1.0, 1.0);
+    vertices.push(SpriteVertex {
+      position: position.to_array(),
+      tex_coords,
+      color: sprite.color,
+      texture_slot: sprite.texture_slot,
+    });
+  }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, quad_capacity: u64) -> wgpu::Buffer {
+  device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Sprite Vertex Buffer"),
+    size: quad_capacity * 4 * std::mem::size_of::<SpriteVertex>() as u64,
+    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    mapped_at_creation: false,
+  })
+}
+
+fn create_index_buffer(device: &wgpu::Device, quad_capacity: u64) -> wgpu::Buffer {
+  use wgpu::util::DeviceExt;
+
+  let indices: Vec<u32> = (0..quad_capacity as u32)
+    .flat_map(|quad| {
+      let base = quad * 4;
+      [base, base + 1, base + 2, base, base + 2, base + 3]
+    })
+    .collect();
+
+  device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    label: Some("Sprite Index Buffer"),
+    contents: bytemuck::cast_slice(&indices),
+    usage: wgpu::BufferUsages::INDEX,
+  })
+}
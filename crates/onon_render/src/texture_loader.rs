@@ -0,0 +1,375 @@
+use crate::render_resource::{
+  TextureArray,
+  image_format::{self, DecodedImage},
+  texture_array::{FittedImage, TextureArrayId, TextureArrayInfo},
+};
+use anyhow::{Context, Result};
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  sync::{Arc, mpsc},
+};
+
+/// A file read and decoded by the `TextureLoader`, to be uploaded on the render thread
+pub struct LoadedTexture {
+  /// Path of the file, or the key of data given to `request_bytes`
+  pub path: PathBuf,
+  /// Texture arrays the file was requested for
+  pub targets: Vec<TextureArrayId>,
+  pub image: Result<DecodedImage>,
+  /// The image fitted to each target known when it was requested, see `TextureArray::fit_decoded`.
+  /// Targets added while it loaded have none.
+  pub fitted: Vec<(TextureArrayId, Result<Option<FittedImage>>)>,
+  /// Requested by `reload`, the slots the file is cached in are overwritten
+  pub reload: bool,
+}
+
+/// A placeholder stretched to the layers of a texture array by the `TextureLoader`
+pub struct LoadedPlaceholder {
+  /// Tells placeholders apart, only the latest one is worth uploading
+  pub generation: u64,
+  /// Array the placeholder was stretched for
+  pub id: TextureArrayId,
+  pub image: Result<FittedImage>,
+}
+
+/// Encoded image to decode, read from `key` when there's no `bytes`
+struct LoadRequest {
+  key: PathBuf,
+  bytes: Option<Vec<u8>>,
+  /// Arrays known when the request was made, with the info their images are fitted to
+  targets: Vec<(TextureArrayId, TextureArrayInfo)>,
+}
+
+enum Job {
+  Load(LoadRequest),
+  Placeholder {
+    image: Arc<image::DynamicImage>,
+    generation: u64,
+    id: TextureArrayId,
+    info: TextureArrayInfo,
+  },
+}
+
+#[derive(Clone)]
+struct Results {
+  textures: mpsc::Sender<LoadedTexture>,
+  placeholders: mpsc::Sender<LoadedPlaceholder>,
+}
+
+/// Reads, decodes and fits texture files to their texture arrays off the render thread,
+/// so the render thread only copies texels. Natively this runs on worker threads. On wasm,
+/// paths are fetched as URLs relative to the page and decoded when they arrive, while
+/// `request_bytes` data and placeholders are processed right away, there are no threads
+/// to hand them to. Finished files are collected with `poll`.
+pub struct TextureLoader {
+  #[cfg(not(target_arch = "wasm32"))]
+  jobs: mpsc::Sender<Job>,
+  results_sender: Results,
+  results: mpsc::Receiver<LoadedTexture>,
+  placeholders: mpsc::Receiver<LoadedPlaceholder>,
+  pending: HashMap<PathBuf, Vec<TextureArrayId>>,
+  reloads: HashSet<PathBuf>,
+  failed: HashSet<PathBuf>,
+}
+
+impl TextureLoader {
+  pub fn new() -> Self {
+    let (textures, results) = mpsc::channel();
+    let (placeholder_sender, placeholders) = mpsc::channel();
+    let results_sender = Results {
+      textures,
+      placeholders: placeholder_sender,
+    };
+
+    Self {
+      #[cfg(not(target_arch = "wasm32"))]
+      jobs: spawn_workers(results_sender.clone()),
+      results_sender,
+      results,
+      placeholders,
+      pending: HashMap::new(),
+      reloads: HashSet::new(),
+      failed: HashSet::new(),
+    }
+  }
+
+  /// Starts loading the file at `path` for the texture array `id`, whose info the image
+  /// is fitted to. Files already loading are only read once, files that failed to load
+  /// aren't retried.
+  pub fn request(&mut self, path: &Path, id: TextureArrayId, info: &TextureArrayInfo) {
+    if self.failed.contains(path) {
+      return;
+    }
+    if let Some(targets) = self.pending.get_mut(path) {
+      if !targets.contains(&id) {
+        targets.push(id);
+      }
+      return;
+    }
+
+    self.pending.insert(path.to_path_buf(), vec![id]);
+    self.dispatch(Job::Load(LoadRequest {
+      key: path.to_path_buf(),
+      bytes: None,
+      targets: vec![(id, info.clone())],
+    }));
+  }
+
+  /// Reads the file at `path` again for the texture array `id`, even if it failed
+  /// before. The result is marked as `LoadedTexture::reload`.
+  pub fn reload(&mut self, path: &Path, id: TextureArrayId, info: &TextureArrayInfo) {
+    self.failed.remove(path);
+    self.reloads.insert(path.to_path_buf());
+    self.request(path, id, info);
+  }
+
+  /// Starts decoding the encoded image `bytes`, such as a file fetched from the network,
+  /// to be cached under `key` like a path
+  pub fn request_bytes(&mut self, key: &Path, bytes: Vec<u8>, id: TextureArrayId, info: &TextureArrayInfo) {
+    if let Some(targets) = self.pending.get_mut(key) {
+      if !targets.contains(&id) {
+        targets.push(id);
+      }
+      return;
+    }
+
+    self.failed.remove(key);
+    self.pending.insert(key.to_path_buf(), vec![id]);
+    self.dispatch(Job::Load(LoadRequest {
+      key: key.to_path_buf(),
+      bytes: Some(bytes),
+      targets: vec![(id, info.clone())],
+    }));
+  }
+
+  /// Starts stretching `image` to the layers of the array `id`, collected by `poll_placeholders`
+  pub fn request_placeholder(
+    &self,
+    image: Arc<image::DynamicImage>,
+    generation: u64,
+    id: TextureArrayId,
+    info: &TextureArrayInfo,
+  ) {
+    self.dispatch(Job::Placeholder {
+      image,
+      generation,
+      id,
+      info: info.clone(),
+    });
+  }
+
+  /// Files finished since the last call, failed ones included
+  pub fn poll(&mut self) -> Vec<LoadedTexture> {
+    let mut loaded = Vec::new();
+    while let Ok(mut texture) = self.results.try_recv() {
+      texture.targets = self.pending.remove(&texture.path).unwrap_or_default();
+      texture.reload = self.reloads.remove(&texture.path);
+      if texture.image.is_err() {
+        self.failed.insert(texture.path.clone());
+      }
+      loaded.push(texture);
+    }
+    loaded
+  }
+
+  /// Placeholders finished since the last call
+  pub fn poll_placeholders(&mut self) -> Vec<LoadedPlaceholder> {
+    self.placeholders.try_iter().collect()
+  }
+
+  /// Files requested and not collected by `poll` yet
+  pub fn loading_count(&self) -> usize {
+    self.pending.len()
+  }
+
+  pub fn is_pending(&self, path: &Path) -> bool {
+    self.pending.contains_key(path)
+  }
+
+  pub fn is_reloading(&self, path: &Path) -> bool {
+    self.reloads.contains(path)
+  }
+
+  pub fn has_failed(&self, path: &Path) -> bool {
+    self.failed.contains(path)
+  }
+
+  /// Stops `request` from retrying a file that loaded but couldn't be uploaded, e.g. one
+  /// that doesn't fit its texture array
+  pub fn mark_failed(&mut self, path: &Path) {
+    self.failed.insert(path.to_path_buf());
+  }
+
+  /// Allows a file that failed to load to be requested again
+  pub fn forget_failure(&mut self, path: &Path) -> bool {
+    self.failed.remove(path)
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  fn dispatch(&self, job: Job) {
+    if let Err(mpsc::SendError(job)) = self.jobs.send(job) {
+      let error = || anyhow::anyhow!("Texture loader workers stopped");
+      match job {
+        Job::Load(request) => {
+          let _ = self.results_sender.textures.send(LoadedTexture {
+            path: request.key,
+            targets: request.targets.into_iter().map(|(id, _)| id).collect(),
+            image: Err(error()),
+            fitted: Vec::new(),
+            reload: false,
+          });
+        }
+        Job::Placeholder { generation, id, .. } => {
+          let _ = self.results_sender.placeholders.send(LoadedPlaceholder {
+            generation,
+            id,
+            image: Err(error()),
+          });
+        }
+      }
+    }
+  }
+
+  /// Only fetching waits for anything, decoding and fitting happen on the main thread
+  #[cfg(target_arch = "wasm32")]
+  fn dispatch(&self, job: Job) {
+    match job {
+      Job::Load(LoadRequest {
+        key,
+        bytes: None,
+        targets,
+      }) => {
+        let results = self.results_sender.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+          let request = match fetch(&key).await {
+            Ok(bytes) => LoadRequest {
+              key,
+              bytes: Some(bytes),
+              targets,
+            },
+            Err(error) => {
+              let _ = results.textures.send(LoadedTexture {
+                image: Err(error.context(format!("{:?}", key))),
+                path: key,
+                targets: targets.into_iter().map(|(id, _)| id).collect(),
+                fitted: Vec::new(),
+                reload: false,
+              });
+              return;
+            }
+          };
+          run(Job::Load(request), &results);
+        });
+      }
+      job => {
+        run(job, &self.results_sender);
+      }
+    }
+  }
+}
+
+impl Default for TextureLoader {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Workers share the job queue, they stop once the loader is dropped
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_workers(results: Results) -> mpsc::Sender<Job> {
+  let (jobs, receiver) = mpsc::channel::<Job>();
+  let receiver = std::sync::Arc::new(std::sync::Mutex::new(receiver));
+
+  let workers = std::thread::available_parallelism().map_or(2, |count| count.get().min(4));
+  for index in 0..workers {
+    let receiver = receiver.clone();
+    let results = results.clone();
+    let spawned = std::thread::Builder::new()
+      .name(format!("texture-loader-{index}"))
+      .spawn(move || {
+        while let Some(job) = next_job(&receiver) {
+          if !run(job, &results) {
+            break;
+          }
+        }
+      });
+    if let Err(error) = spawned {
+      log::error!("Failed to spawn texture loader worker: {:?}", error);
+    }
+  }
+
+  jobs
+}
+
+/// Waits for the next job, `None` once the loader is dropped
+#[cfg(not(target_arch = "wasm32"))]
+fn next_job(receiver: &std::sync::Mutex<mpsc::Receiver<Job>>) -> Option<Job> {
+  receiver.lock().ok()?.recv().ok()
+}
+
+/// Runs `job` and sends its result, `false` once the loader is gone
+fn run(job: Job, results: &Results) -> bool {
+  match job {
+    Job::Load(request) => {
+      let image = load(&request);
+      let fitted = match &image {
+        Ok(image) => request
+          .targets
+          .iter()
+          .map(|(id, info)| (*id, TextureArray::fit_decoded(info, image)))
+          .collect(),
+        Err(_) => Vec::new(),
+      };
+      let loaded = LoadedTexture {
+        path: request.key,
+        targets: request.targets.into_iter().map(|(id, _)| id).collect(),
+        image,
+        fitted,
+        reload: false,
+      };
+      results.textures.send(loaded).is_ok()
+    }
+    Job::Placeholder {
+      image,
+      generation,
+      id,
+      info,
+    } => {
+      let image = TextureArray::fit_placeholder(&info, &image);
+      results.placeholders.send(LoadedPlaceholder { generation, id, image }).is_ok()
+    }
+  }
+}
+
+fn load(request: &LoadRequest) -> Result<DecodedImage> {
+  let image = match &request.bytes {
+    Some(bytes) => image_format::decode(bytes),
+    #[cfg(not(target_arch = "wasm32"))]
+    None => std::fs::read(&request.key).map_err(Into::into).and_then(|bytes| image_format::decode(&bytes)),
+    #[cfg(target_arch = "wasm32")]
+    None => Err(anyhow::anyhow!("Files are fetched before they are decoded on the web")),
+  };
+  image.context(format!("{:?}", request.key))
+}
+
+/// Fetches `path` as a URL relative to the page
+#[cfg(target_arch = "wasm32")]
+async fn fetch(path: &Path) -> Result<Vec<u8>> {
+  use wasm_bindgen::JsCast;
+  use wasm_bindgen_futures::JsFuture;
+
+  let js_error = |error: wasm_bindgen::JsValue| anyhow::anyhow!("{:?}", error);
+  let url = path.to_str().context("Texture paths are URLs on the web, they must be UTF-8")?;
+  let window = web_sys::window().context("No window to fetch textures with")?;
+  let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url))
+    .await
+    .map_err(js_error)?
+    .dyn_into()
+    .map_err(js_error)?;
+  if !response.ok() {
+    return Err(anyhow::anyhow!("Fetching {} failed with status {}", url, response.status()));
+  }
+  let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?).await.map_err(js_error)?;
+  Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
@@ -1,47 +1,315 @@
 use std::collections::HashMap;
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
 
-use crate::render_resource::{TextureArray, texture_array::TextureArrayInfo};
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub struct TextureId(pub u32);
+use crate::render_resource::{
+  AtlasEntry, RenderTarget, TextureArray, TextureAtlas, image_format,
+  texture_array::{FittedImage, TextureArrayId, TextureArrayInfo},
+};
 
 pub struct TextureManager {
-  texture_arrays: HashMap<TextureArrayInfo, TextureArray>,
+  texture_arrays: HashMap<TextureArrayId, TextureArray>,
+  texture_atlases: HashMap<TextureArrayId, TextureAtlas>,
+  next_texture_array_id: u32,
+  placeholder: std::sync::Arc<image::DynamicImage>,
+  /// Bumped by `set_placeholder`, so older placeholders still being fitted are dropped
+  placeholder_generation: u64,
+  /// Arrays whose placeholder hasn't been handed to `take_placeholder_requests` yet
+  placeholder_requests: Vec<TextureArrayId>,
 }
 
 impl TextureManager {
   pub fn deafult() -> Self {
     Self {
       texture_arrays: HashMap::new(),
+      texture_atlases: HashMap::new(),
+      next_texture_array_id: 0,
+      placeholder: std::sync::Arc::new(checkerboard()),
+      placeholder_generation: 0,
+      placeholder_requests: Vec::new(),
+    }
+  }
+
+  fn next_id(&mut self) -> TextureArrayId {
+    let id = TextureArrayId(self.next_texture_array_id);
+    self.next_texture_array_id += 1;
+    id
+  }
+
+  /// New texture arrays reserve their `PLACEHOLDER_SLOT` and ask for the placeholder,
+  /// see `set_placeholder`. Objects draw with the array through the returned id.
+  pub fn add_texture_array(
+    &mut self,
+    device: &wgpu::Device,
+    texture_array_info: TextureArrayInfo,
+  ) -> TextureArrayId {
+    let id = self.next_id();
+
+    let mut texture_array = TextureArray::new(device, &texture_array_info, id);
+    if !texture_array_info.format.is_compressed() {
+      match texture_array.reserve_placeholder() {
+        Ok(()) => self.placeholder_requests.push(id),
+        Err(error) => log::error!("Failed to reserve the placeholder of texture array {}: {:?}", id.0, error),
+      }
+    }
+    self.texture_arrays.insert(id, texture_array);
+    id
+  }
+
+  /// Creates the one layer array of `texture_array_info` and the target rendering into it,
+  /// with a depth texture when `depth` is set. The layer is allocated right away, it never
+  /// holds a placeholder nor gets evicted.
+  pub fn add_render_target(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_array_info: TextureArrayInfo,
+    depth: bool,
+  ) -> Result<RenderTarget> {
+    let id = self.next_id();
+
+    let mut texture_array = TextureArray::new(device, &texture_array_info, id);
+    let slot = texture_array.allocate_layer(device, queue)?;
+    if slot != RenderTarget::SLOT {
+      return Err(anyhow!("Render target got slot {}", slot));
     }
+
+    let target = RenderTarget::new(device, &texture_array, depth);
+    self.texture_arrays.insert(id, texture_array);
+    Ok(target)
+  }
+
+  /// Drops a texture array or atlas, objects must not draw with it anymore
+  pub fn remove_texture_array(&mut self, id: TextureArrayId) -> bool {
+    self.texture_arrays.remove(&id).is_some() || self.texture_atlases.remove(&id).is_some()
+  }
+
+  /// First texture array created with `texture_array_info`, atlases aside.
+  /// Lets arrays of a given size or format be shared.
+  pub fn find_texture_array(&self, texture_array_info: &TextureArrayInfo) -> Option<TextureArrayId> {
+    self
+      .texture_arrays
+      .iter()
+      .filter(|(_, texture_array)| texture_array.info() == texture_array_info)
+      .map(|(id, _)| *id)
+      .min()
+  }
+
+  /// Image drawn by objects whose texture is still loading, in every texture array
+  /// but the compressed ones. Defaults to a grey checkerboard. Stretching it to the
+  /// layers is left to whoever takes the `take_placeholder_requests`, the renderer
+  /// hands them to its texture loader.
+  pub fn set_placeholder(&mut self, image: image::DynamicImage) {
+    self.placeholder = std::sync::Arc::new(image);
+    self.placeholder_generation += 1;
+    self.placeholder_requests = self
+      .texture_arrays
+      .iter()
+      .filter(|(_, texture_array)| texture_array.has_placeholder())
+      .map(|(id, _)| *id)
+      .collect();
+  }
+
+  /// The placeholder, its generation and the arrays it must be stretched to, which
+  /// `write_placeholder` then fills. Arrays removed since they asked are left out.
+  pub fn take_placeholder_requests(
+    &mut self,
+  ) -> (std::sync::Arc<image::DynamicImage>, u64, Vec<(TextureArrayId, TextureArrayInfo)>) {
+    let requests = std::mem::take(&mut self.placeholder_requests)
+      .into_iter()
+      .filter_map(|id| Some((id, self.texture_arrays.get(&id)?.info().clone())))
+      .collect();
+    (self.placeholder.clone(), self.placeholder_generation, requests)
+  }
+
+  /// Fills the placeholder slot of the array of `id`, unless a newer placeholder was set since
+  pub fn write_placeholder(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    generation: u64,
+    id: TextureArrayId,
+    placeholder: &FittedImage,
+  ) -> Result<()> {
+    if generation != self.placeholder_generation {
+      return Ok(());
+    }
+    match self.texture_arrays.get_mut(&id) {
+      Some(texture_array) => texture_array.write_placeholder(device, queue, placeholder),
+      None => Ok(()),
+    }
+  }
+
+  /// Atlases share the id space of texture arrays, their pages being array layers
+  pub fn add_texture_atlas(
+    &mut self,
+    device: &wgpu::Device,
+    texture_array_info: TextureArrayInfo,
+    padding: u32,
+  ) -> TextureArrayId {
+    let id = self.next_id();
+    self.texture_atlases.insert(id, TextureAtlas::new(device, &texture_array_info, padding, id));
+    id
+  }
+
+  /// Also finds the page array of atlases, so atlases draw like texture arrays
+  pub fn get_texture_array(&self, id: TextureArrayId) -> Option<&TextureArray> {
+    self
+      .texture_arrays
+      .get(&id)
+      .or_else(|| self.get_texture_atlas(id).map(TextureAtlas::texture_array))
   }
 
-  pub fn add_texture_array(&mut self, device: &wgpu::Device, texture_array_info: TextureArrayInfo) {
-    self.texture_arrays.insert(
-      texture_array_info.clone(),
-      TextureArray::new(device, &texture_array_info),
-    );
+  pub fn get_texture_atlas(&self, id: TextureArrayId) -> Option<&TextureAtlas> {
+    self.texture_atlases.get(&id)
   }
 
-  pub fn get_texture_array(&self, texture_array_info: &TextureArrayInfo) -> Option<&TextureArray> {
-    self.texture_arrays.get(texture_array_info)
+  pub fn contains(&self, id: TextureArrayId) -> bool {
+    self.texture_arrays.contains_key(&id) || self.texture_atlases.contains_key(&id)
   }
 
-  pub fn get_texture_array_mut(
+  pub fn add_atlas_texture<P: AsRef<std::path::Path>>(
     &mut self,
-    texture_array_info: &TextureArrayInfo,
-  ) -> Option<&mut TextureArray> {
-    self.texture_arrays.get_mut(texture_array_info)
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: P,
+    id: TextureArrayId,
+  ) -> Result<AtlasEntry> {
+    let texture_atlas = self
+      .texture_atlases
+      .get_mut(&id)
+      .context("Failed to retrieve texture atlas")?;
+    texture_atlas.load_from_file(device, queue, path)
+  }
+
+  /// Also finds the page array of atlases, see `get_texture_array`
+  pub fn get_texture_array_mut(&mut self, id: TextureArrayId) -> Option<&mut TextureArray> {
+    match self.texture_arrays.get_mut(&id) {
+      Some(texture_array) => Some(texture_array),
+      None => self
+        .texture_atlases
+        .get_mut(&id)
+        .map(TextureAtlas::texture_array_mut),
+    }
   }
 
   pub fn add_texture<P: AsRef<std::path::Path>>(
     &mut self,
+    device: &wgpu::Device,
     queue: &wgpu::Queue,
     path: P,
-    texture_array_info: &TextureArrayInfo,
+    id: TextureArrayId,
+  ) -> Result<u32> {
+    let texture_array = self.texture_arrays.get_mut(&id).context("Failed to retrieve texture array")?;
+    texture_array.load_from_file(device, queue, path)
+  }
+
+  /// Decodes an encoded image into the array of `id`, cached under `key`
+  pub fn add_texture_from_memory<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    bytes: &[u8],
+    id: TextureArrayId,
   ) -> Result<u32> {
-    let texture_array = self.get_texture_array_mut(texture_array_info).context("Failed to retrieve texture array")?;
-    texture_array.load_from_file(queue, path)
+    let texture_array = self.texture_arrays.get_mut(&id).context("Failed to retrieve texture array")?;
+    texture_array.load_from_memory(device, queue, key, bytes)
   }
+
+  /// Uploads RGBA8 pixels into the array of `id`, cached under `key`
+  pub fn add_texture_raw<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    data: &[u8],
+    size: glam::UVec2,
+    id: TextureArrayId,
+  ) -> Result<u32> {
+    let texture_array = self.texture_arrays.get_mut(&id).context("Failed to retrieve texture array")?;
+    texture_array.load_raw(device, queue, key, data, size.x, size.y)
+  }
+
+  /// Packs an encoded image into the atlas of `id`, cached under `key`
+  pub fn add_atlas_texture_from_memory<K: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    key: K,
+    bytes: &[u8],
+    id: TextureArrayId,
+  ) -> Result<AtlasEntry> {
+    let texture_atlas = self
+      .texture_atlases
+      .get_mut(&id)
+      .context("Failed to retrieve texture atlas")?;
+    texture_atlas.load_from_memory(device, queue, key, bytes)
+  }
+
+  /// Loads the file at `path` into an array whose layers are the texture's size
+  /// class: the next power of two of its largest side, no smaller than `base`'s layers.
+  /// The array's format follows the image, see `image_format::source_format`.
+  /// The array is created from `base` when missing. Returns the array's id,
+  /// to be set on the objects using the returned slot.
+  pub fn add_texture_by_size<P: AsRef<std::path::Path>>(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: P,
+    base: &TextureArrayInfo,
+  ) -> Result<(TextureArrayId, u32)> {
+    use image::ImageDecoder;
+    let decoder = image::ImageReader::open(path.as_ref())?.with_guessed_format()?.into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    let format = image_format::source_format(decoder.color_type(), device.features());
+
+    let max_size = device.limits().max_texture_dimension_2d;
+    let class = width
+      .max(height)
+      .next_power_of_two()
+      .max(base.dims.width.max(base.dims.height))
+      .min(max_size);
+
+    let info = TextureArrayInfo {
+      dims: wgpu::Extent3d {
+        width: class,
+        height: class,
+        depth_or_array_layers: base.dims.depth_or_array_layers,
+      },
+      format,
+      ..base.clone()
+    };
+    let id = match self.find_texture_array(&info) {
+      Some(id) => id,
+      None => self.add_texture_array(device, info),
+    };
+
+    let slot = self.add_texture(device, queue, path, id)?;
+    Ok((id, slot))
+  }
+
+  /// Frees the slot of a texture loaded from `path`, unless an object still references it
+  pub fn unload_texture(&mut self, path: &std::path::Path, id: TextureArrayId) -> bool {
+    self
+      .get_texture_array_mut(id)
+      .is_some_and(|texture_array| texture_array.unload_path(path))
+  }
+
+  pub fn advance_frame(&mut self) {
+    for texture_array in self.texture_arrays.values_mut() {
+      texture_array.advance_frame();
+    }
+    for texture_atlas in self.texture_atlases.values_mut() {
+      texture_atlas.texture_array_mut().advance_frame();
+    }
+  }
+}
+
+/// 8x8 cells of two greys
+fn checkerboard() -> image::DynamicImage {
+  let image = image::RgbaImage::from_fn(8, 8, |x, y| {
+    let grey = if (x + y) % 2 == 0 { 96 } else { 160 };
+    image::Rgba([grey, grey, grey, 255])
+  });
+  image::DynamicImage::ImageRgba8(image)
 }
@@ -0,0 +1,32 @@
+/// Position, rotation (radians) and scale of an object in 2D.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+  pub translation: glam::Vec2,
+  pub rotation: f32,
+  pub scale: glam::Vec2,
+}
+
+impl Transform {
+  pub const IDENTITY: Self = Self {
+    translation: glam::Vec2::ZERO,
+    rotation: 0.0,
+    scale: glam::Vec2::ONE,
+  };
+
+  pub fn from_translation(translation: glam::Vec2) -> Self {
+    Self {
+      translation,
+      ..Self::IDENTITY
+    }
+  }
+
+  pub fn to_affine(&self) -> glam::Affine2 {
+    glam::Affine2::from_scale_angle_translation(self.scale, self.rotation, self.translation)
+  }
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self::IDENTITY
+  }
+}
\ No newline at end of file
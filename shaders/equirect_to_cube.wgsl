@@ -0,0 +1,51 @@
+// Projects an equirectangular image onto a cube face, drawn as a single fullscreen triangle.
+// The face index comes in as the instance index.
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4f,
+  @location(0) tex_coords: vec2f,
+  @location(1) @interpolate(flat) face: u32,
+}
+
+@vertex
+fn vs_main(
+  @builtin(vertex_index) vertex_index: u32,
+  @builtin(instance_index) face: u32,
+) -> VertexOutput {
+  let uv = vec2f(f32(vertex_index & 2u), f32((vertex_index << 1u) & 2u));
+  var out: VertexOutput;
+  out.tex_coords = uv;
+  out.face = face;
+  out.clip_position = vec4f(uv * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0), 0.0, 1.0);
+  return out;
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+const PI: f32 = 3.14159265359;
+
+// Direction through the texel at `uv` of `face`, in +X, -X, +Y, -Y, +Z, -Z order
+fn face_direction(face: u32, uv: vec2f) -> vec3f {
+  let st = uv * 2.0 - 1.0;
+  switch face {
+    case 0u: { return vec3f(1.0, -st.y, -st.x); }
+    case 1u: { return vec3f(-1.0, -st.y, st.x); }
+    case 2u: { return vec3f(st.x, 1.0, st.y); }
+    case 3u: { return vec3f(st.x, -1.0, -st.y); }
+    case 4u: { return vec3f(st.x, -st.y, 1.0); }
+    default: { return vec3f(-st.x, -st.y, -1.0); }
+  }
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+  let direction = normalize(face_direction(in.face, in.tex_coords));
+  let longitude = atan2(direction.z, direction.x);
+  let latitude = asin(clamp(direction.y, -1.0, 1.0));
+  let uv = vec2f(longitude / (2.0 * PI) + 0.5, 0.5 - latitude / PI);
+  // No mips to pick from, avoids derivatives jumping across the longitude seam
+  return textureSampleLevel(t_source, s_source, uv, 0.0);
+}
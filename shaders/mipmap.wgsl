@@ -0,0 +1,26 @@
+// Downsamples a mip level into the next one, drawn as a single fullscreen triangle.
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4f,
+  @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  // (0, 0), (0, 2), (2, 0) covers the whole target, counter clockwise once flipped to clip space
+  let uv = vec2f(f32(vertex_index & 2u), f32((vertex_index << 1u) & 2u));
+  var out: VertexOutput;
+  out.tex_coords = uv;
+  out.clip_position = vec4f(uv * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0), 0.0, 1.0);
+  return out;
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+  return textureSample(t_source, s_source, in.tex_coords);
+}
@@ -0,0 +1,53 @@
+struct VertexInput {
+  @location(0) position: vec2f,
+  @location(1) tex_coords: vec2f,
+}
+
+struct InstanceInput {
+  @location(2) model_0: vec4f,
+  @location(3) model_1: vec4f,
+  @location(4) model_2: vec4f,
+  @location(5) model_3: vec4f,
+  @location(6) texture_slot: u32,
+  // Part of the layer covered by the texture
+  @location(7) uv_scale: vec2f,
+  // Start of the drawn part of the layer, such as a sprite sheet frame
+  @location(8) uv_offset: vec2f,
+}
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4f,
+  @location(0) tex_coords: vec2f,
+  @location(1) @interpolate(flat) texture_index: u32,
+  @location(2) @interpolate(flat) object_id: u32
+}
+
+@vertex
+fn vs_main(
+  model: VertexInput,
+  instance: InstanceInput,
+  @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+  let model_matrix = mat4x4f(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+  var out: VertexOutput;
+  out.tex_coords = model.tex_coords * instance.uv_scale + instance.uv_offset;
+  out.texture_index = instance.texture_slot;
+  // 0 is left for pixels without any object
+  out.object_id = instance_index + 1u;
+  out.clip_position = model_matrix * vec4f(model.position, 0.0, 1.0);
+  return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d_array<f32>;
+@group(0)@binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) u32 {
+  let alpha = textureSample(t_diffuse, s_diffuse, in.tex_coords, in.texture_index).a;
+  if (alpha < 0.5) {
+    discard;
+  }
+  return in.object_id;
+}
\ No newline at end of file
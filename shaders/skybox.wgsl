@@ -0,0 +1,34 @@
+// Draws a cubemap behind everything, as a single fullscreen triangle on the far plane.
+
+struct SkyboxUniform {
+  // Inverse of the camera projection and rotation, without translation
+  inverse_view_projection: mat4x4f,
+}
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4f,
+  @location(0) ndc: vec2f,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  let uv = vec2f(f32(vertex_index & 2u), f32((vertex_index << 1u) & 2u));
+  var out: VertexOutput;
+  out.ndc = uv * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0);
+  out.clip_position = vec4f(out.ndc, 1.0, 1.0);
+  return out;
+}
+
+@group(0) @binding(0)
+var t_sky: texture_cube<f32>;
+@group(0) @binding(1)
+var s_sky: sampler;
+@group(0) @binding(2)
+var<uniform> skybox: SkyboxUniform;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+  let far = skybox.inverse_view_projection * vec4f(in.ndc, 1.0, 1.0);
+  let direction = far.xyz / far.w;
+  return textureSample(t_sky, s_sky, direction);
+}
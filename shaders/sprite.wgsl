@@ -0,0 +1,35 @@
+struct VertexInput {
+  @location(0) position: vec2f,
+  @location(1) tex_coords: vec2f,
+  @location(2) color: vec4f,
+  @location(3) texture_slot: u32
+}
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4f,
+  @location(0) tex_coords: vec2f,
+  @location(1) color: vec4f,
+  @location(2) @interpolate(flat) texture_index: u32
+}
+
+@vertex
+fn vs_main(
+  model: VertexInput,
+) -> VertexOutput {
+  var out: VertexOutput;
+  out.tex_coords = model.tex_coords;
+  out.color = model.color;
+  out.texture_index = model.texture_slot;
+  out.clip_position = vec4f(model.position, 0.0, 1.0);
+  return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d_array<f32>;
+@group(0)@binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+  return textureSample(t_diffuse, s_diffuse, in.tex_coords, in.texture_index) * in.color;
+}
\ No newline at end of file
@@ -0,0 +1,71 @@
+// Example material: tint, dissolve and UV scrolling driven by material parameters.
+// material.set("tint", MaterialParam::Vec4(..)), material.set("uv_scroll", MaterialParam::Vec2(..)),
+// material.set("dissolve", MaterialParam::Float(..)), material.set("t_noise", MaterialParam::Texture(..))
+
+struct VertexInput {
+  @location(0) position: vec2f,
+  @location(1) tex_coords: vec2f,
+}
+
+struct InstanceInput {
+  @location(2) model_0: vec4f,
+  @location(3) model_1: vec4f,
+  @location(4) model_2: vec4f,
+  @location(5) model_3: vec4f,
+  @location(6) texture_slot: u32,
+  // Part of the layer covered by the texture
+  @location(7) uv_scale: vec2f,
+  // Start of the drawn part of the layer, such as a sprite sheet frame
+  @location(8) uv_offset: vec2f,
+}
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4f,
+  @location(0) tex_coords: vec2f,
+  @location(1) @interpolate(flat) texture_index: u32,
+  @location(2) @interpolate(flat) uv_scale: vec2f,
+  @location(3) @interpolate(flat) uv_offset: vec2f
+}
+
+struct SpriteEffects {
+  tint: vec4f,
+  uv_scroll: vec2f,
+  dissolve: f32,
+}
+
+@vertex
+fn vs_main(
+  model: VertexInput,
+  instance: InstanceInput,
+) -> VertexOutput {
+  let model_matrix = mat4x4f(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+  var out: VertexOutput;
+  out.tex_coords = model.tex_coords + effects.uv_scroll;
+  out.texture_index = instance.texture_slot;
+  out.uv_scale = instance.uv_scale;
+  out.uv_offset = instance.uv_offset;
+  out.clip_position = model_matrix * vec4f(model.position, 0.0, 1.0);
+  return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d_array<f32>;
+@group(0)@binding(1)
+var s_diffuse: sampler;
+
+@group(1) @binding(0)
+var<uniform> effects: SpriteEffects;
+@group(1) @binding(1)
+var t_noise: texture_2d<f32>;
+@group(1) @binding(2)
+var s_noise: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+  let color = textureSample(t_diffuse, s_diffuse, fract(in.tex_coords) * in.uv_scale + in.uv_offset, in.texture_index);
+  let noise = textureSample(t_noise, s_noise, in.tex_coords).r;
+  if (noise < effects.dissolve) {
+    discard;
+  }
+  return color * effects.tint;
+}
\ No newline at end of file
@@ -1,7 +1,18 @@
 struct VertexInput {
   @location(0) position: vec2f,
   @location(1) tex_coords: vec2f,
-  @builtin(instance_index) texture_slot: u32
+}
+
+struct InstanceInput {
+  @location(2) model_0: vec4f,
+  @location(3) model_1: vec4f,
+  @location(4) model_2: vec4f,
+  @location(5) model_3: vec4f,
+  @location(6) texture_slot: u32,
+  // Part of the layer covered by the texture
+  @location(7) uv_scale: vec2f,
+  // Start of the drawn part of the layer, such as a sprite sheet frame
+  @location(8) uv_offset: vec2f,
 }
 
 struct VertexOutput {
@@ -13,10 +24,13 @@ struct VertexOutput {
 @vertex
 fn vs_main(
   model: VertexInput,
+  instance: InstanceInput,
 ) -> VertexOutput {
+  let model_matrix = mat4x4f(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
   var out: VertexOutput;
-  out.tex_coords = model.tex_coords;
-  out.clip_position = vec4f(model.position, 0.0, 1.0);
+  out.tex_coords = model.tex_coords * instance.uv_scale + instance.uv_offset;
+  out.texture_index = instance.texture_slot;
+  out.clip_position = model_matrix * vec4f(model.position, 0.0, 1.0);
   return out;
 }
 